@@ -0,0 +1,80 @@
+use std::process::ExitCode;
+
+use crate::core::{db, lesson, migrations};
+
+/// Runs `migrate status` or `migrate up` against the configured database.
+pub fn migrate(args: &[String]) -> ExitCode {
+    let result = match args.first().map(String::as_str) {
+        Some("status") => migrate_status(),
+        Some("up") => migrate_up(),
+        _ => {
+            eprintln!("Usage: avvai-backend migrate <status|up>");
+            return ExitCode::from(2);
+        }
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Runs `lessons assign-ids`, which gives vocabulary entries in lesson files
+/// written before ids existed their id, once.
+pub fn lessons(args: &[String]) -> ExitCode {
+    let result = match args.first().map(String::as_str) {
+        Some("assign-ids") => lessons_assign_ids(),
+        _ => {
+            eprintln!("Usage: avvai-backend lessons <assign-ids>");
+            return ExitCode::from(2);
+        }
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn lessons_assign_ids() -> Result<(), String> {
+    let changed = lesson::migrate_vocabulary_ids()?;
+    if changed.is_empty() {
+        println!("Every vocabulary entry already has an id");
+    }
+    for path in changed {
+        println!("Assigned vocabulary ids in {}", path.display());
+    }
+    Ok(())
+}
+
+fn migrate_status() -> Result<(), String> {
+    let conn = db::open().map_err(|err| format!("Failed to open database: {err}"))?;
+    let current = migrations::current_version(&conn)
+        .map_err(|err| format!("Failed to read schema version: {err}"))?;
+    println!("Database: {}", db::db_path().display());
+    println!(
+        "Schema version: {current} (latest {})",
+        migrations::latest_version()
+    );
+    for migration in migrations::MIGRATIONS {
+        let state = if migration.version <= current { "applied" } else { "pending" };
+        println!("  {:>4}  {state:<7}  {}", migration.version, migration.name);
+    }
+    migrations::pending(&conn).map(|_| ())
+}
+
+fn migrate_up() -> Result<(), String> {
+    let mut conn = db::open().map_err(|err| format!("Failed to open database: {err}"))?;
+    let applied = migrations::migrate(&mut conn)?;
+    if applied.is_empty() {
+        println!("Database is up to date");
+    }
+    for migration in applied {
+        println!("Applied migration {} ({})", migration.version, migration.name);
+    }
+    Ok(())
+}
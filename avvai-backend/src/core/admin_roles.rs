@@ -0,0 +1,228 @@
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use tokio::task;
+
+use crate::core::db::Db;
+
+/// What a CMS user may do. Each role includes everything the roles before it
+/// may do: viewers read content, editors create and change it, reviewers may
+/// also delete it, and admins manage who has which role.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Viewer,
+    Editor,
+    Reviewer,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Viewer => "viewer",
+            Self::Editor => "editor",
+            Self::Reviewer => "reviewer",
+            Self::Admin => "admin",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "viewer" => Some(Self::Viewer),
+            "editor" => Some(Self::Editor),
+            "reviewer" => Some(Self::Reviewer),
+            "admin" => Some(Self::Admin),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct RoleAssignment {
+    pub email: String,
+    pub role: Role,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug)]
+pub enum RoleError {
+    NotFound,
+    Invalid(String),
+    /// The change would leave the CMS without an admin.
+    LastAdmin,
+    Database(String),
+}
+
+impl RoleError {
+    pub fn message(&self) -> String {
+        match self {
+            Self::NotFound => "No role assigned to this email".to_string(),
+            Self::LastAdmin => "At least one admin must remain".to_string(),
+            Self::Invalid(err) | Self::Database(err) => err.clone(),
+        }
+    }
+}
+
+pub fn normalise_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Gives every email in `emails` the admin role unless it already has a
+/// role, so deployments that listed admins in `ADMIN_ALLOWED_EMAILS` keep
+/// access. Returns how many emails were added.
+pub fn seed_admins(db: &Db, emails: &HashSet<String>) -> Result<usize, String> {
+    let conn = db.write()?;
+    let mut added = 0;
+    for email in emails {
+        added += conn
+            .execute(
+                "
+                INSERT OR IGNORE INTO admin_roles (email, role, created_at, updated_at)
+                VALUES (?1, ?2, datetime('now'), datetime('now'))
+                ",
+                params![normalise_email(email), Role::Admin.as_str()],
+            )
+            .map_err(|err| format!("Failed to seed admin roles: {err}"))?;
+    }
+    Ok(added)
+}
+
+pub async fn role_for(db: &Db, email: &str) -> Result<Option<Role>, String> {
+    let email = normalise_email(email);
+    let db = db.clone();
+    task::spawn_blocking(move || {
+        let role = db
+            .read()?
+            .query_row(
+                "SELECT role FROM admin_roles WHERE email = ?1",
+                [email],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()
+            .map_err(|err| format!("Failed to read role: {err}"))?;
+        Ok(role.as_deref().and_then(Role::parse))
+    })
+    .await
+    .map_err(|_| "Failed to join blocking task".to_string())?
+}
+
+pub async fn list_roles(db: &Db) -> Result<Vec<RoleAssignment>, RoleError> {
+    let db = db.clone();
+    task::spawn_blocking(move || {
+        let conn = db.read().map_err(RoleError::Database)?;
+        let mut stmt = conn
+            .prepare(&format!("{SELECT_ROLE} ORDER BY email"))
+            .map_err(|err| RoleError::Database(format!("Failed to prepare statement: {err}")))?;
+        let rows = stmt
+            .query_map([], read_assignment)
+            .map_err(|err| RoleError::Database(format!("Failed to query roles: {err}")))?;
+        rows.filter_map(Result::transpose)
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(|err| RoleError::Database(format!("Failed to read row: {err}")))
+    })
+    .await
+    .map_err(|_| RoleError::Database("Failed to join blocking task".to_string()))?
+}
+
+/// Assigns `role` to `email`, creating the assignment if needed.
+pub async fn set_role(db: &Db, email: &str, role: Role) -> Result<RoleAssignment, RoleError> {
+    let email = normalise_email(email);
+    if !email.contains('@') {
+        return Err(RoleError::Invalid("A valid email is required".to_string()));
+    }
+    let db = db.clone();
+    task::spawn_blocking(move || {
+        let mut conn = db.write().map_err(RoleError::Database)?;
+        let tx = conn
+            .transaction()
+            .map_err(|err| RoleError::Database(format!("Failed to start transaction: {err}")))?;
+        if role != Role::Admin {
+            ensure_other_admin(&tx, &email)?;
+        }
+        tx.execute(
+            "
+            INSERT INTO admin_roles (email, role, created_at, updated_at)
+            VALUES (?1, ?2, datetime('now'), datetime('now'))
+            ON CONFLICT(email) DO UPDATE SET
+                role = excluded.role,
+                updated_at = excluded.updated_at
+            ",
+            params![email, role.as_str()],
+        )
+        .map_err(|err| RoleError::Database(format!("Failed to save role: {err}")))?;
+        let assignment = tx
+            .query_row(&format!("{SELECT_ROLE} WHERE email = ?1"), [&email], read_assignment)
+            .map_err(|err| RoleError::Database(format!("Failed to read role: {err}")))?
+            .ok_or_else(|| RoleError::Database("Saved an unknown role".to_string()))?;
+        tx.commit()
+            .map_err(|err| RoleError::Database(format!("Failed to commit role: {err}")))?;
+        Ok(assignment)
+    })
+    .await
+    .map_err(|_| RoleError::Database("Failed to join blocking task".to_string()))?
+}
+
+/// Takes away every CMS permission from `email`.
+pub async fn remove_role(db: &Db, email: &str) -> Result<(), RoleError> {
+    let email = normalise_email(email);
+    let db = db.clone();
+    task::spawn_blocking(move || {
+        let mut conn = db.write().map_err(RoleError::Database)?;
+        let tx = conn
+            .transaction()
+            .map_err(|err| RoleError::Database(format!("Failed to start transaction: {err}")))?;
+        ensure_other_admin(&tx, &email)?;
+        let removed = tx
+            .execute("DELETE FROM admin_roles WHERE email = ?1", [&email])
+            .map_err(|err| RoleError::Database(format!("Failed to remove role: {err}")))?;
+        if removed == 0 {
+            return Err(RoleError::NotFound);
+        }
+        tx.commit()
+            .map_err(|err| RoleError::Database(format!("Failed to commit role: {err}")))?;
+        Ok(())
+    })
+    .await
+    .map_err(|_| RoleError::Database("Failed to join blocking task".to_string()))?
+}
+
+const SELECT_ROLE: &str = "SELECT email, role, created_at, updated_at FROM admin_roles";
+
+/// Rows with a role this build does not know are skipped.
+fn read_assignment(row: &Row<'_>) -> rusqlite::Result<Option<RoleAssignment>> {
+    let Some(role) = Role::parse(&row.get::<_, String>(1)?) else {
+        return Ok(None);
+    };
+    Ok(Some(RoleAssignment {
+        email: row.get(0)?,
+        role,
+        created_at: row.get(2)?,
+        updated_at: row.get(3)?,
+    }))
+}
+
+/// Fails when `email` is the only admin, so it cannot be demoted or removed.
+fn ensure_other_admin(conn: &Connection, email: &str) -> Result<(), RoleError> {
+    let other_admins: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM admin_roles WHERE role = 'admin' AND email != ?1",
+            [email],
+            |row| row.get(0),
+        )
+        .map_err(|err| RoleError::Database(format!("Failed to count admins: {err}")))?;
+    let is_admin = conn
+        .query_row(
+            "SELECT 1 FROM admin_roles WHERE role = 'admin' AND email = ?1",
+            [email],
+            |_| Ok(()),
+        )
+        .optional()
+        .map_err(|err| RoleError::Database(format!("Failed to read role: {err}")))?
+        .is_some();
+    if is_admin && other_admins == 0 {
+        return Err(RoleError::LastAdmin);
+    }
+    Ok(())
+}
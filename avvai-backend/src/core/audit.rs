@@ -0,0 +1,204 @@
+use rusqlite::params;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::task;
+
+use crate::core::db::Db;
+
+const DEFAULT_LIST_LIMIT: usize = 50;
+const MAX_LIST_LIMIT: usize = 500;
+
+/// One change made through the CMS. `before` and `after` are short summaries
+/// of the target, not full copies; either is absent when the target did not
+/// exist on that side of the change.
+pub struct AuditEvent<'a> {
+    pub actor: &'a str,
+    pub action: &'a str,
+    pub target_type: &'a str,
+    pub target: &'a str,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+#[derive(Serialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub actor: String,
+    pub action: String,
+    pub target_type: String,
+    pub target: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<Value>,
+    pub created_at: String,
+}
+
+#[derive(Serialize)]
+pub struct AuditPage {
+    pub entries: Vec<AuditEntry>,
+    /// Pass as `before` to fetch the next (older) page; absent on the last page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_before: Option<i64>,
+}
+
+/// Narrows [`list`]. `since` and `until` are compared with `created_at`
+/// (`YYYY-MM-DD HH:MM:SS`, UTC) as text, so a bare date means midnight.
+pub struct AuditFilter {
+    pub actor: Option<String>,
+    pub action: Option<String>,
+    pub target_type: Option<String>,
+    pub target: Option<String>,
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub before: Option<i64>,
+    pub limit: Option<usize>,
+}
+
+/// Appends `event` to the audit log. The change it describes has already
+/// happened, so a failure is logged rather than returned.
+pub async fn record(db: &Db, event: AuditEvent<'_>) {
+    let actor = event.actor.to_string();
+    let action = event.action.to_string();
+    let target_type = event.target_type.to_string();
+    let target = event.target.to_string();
+    let before = event.before.map(|value| value.to_string());
+    let after = event.after.map(|value| value.to_string());
+    let db = db.clone();
+    let result = task::spawn_blocking(move || {
+        db.write()?
+            .execute(
+                "
+                INSERT INTO admin_audit_log
+                    (actor, action, target_type, target, before, after, created_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, datetime('now'))
+                ",
+                params![actor, action, target_type, target, before, after],
+            )
+            .map_err(|err| format!("Failed to write audit entry: {err}"))
+    })
+    .await
+    .map_err(|_| "Failed to join blocking task".to_string())
+    .and_then(|result| result);
+    if let Err(err) = result {
+        eprintln!("{err}");
+    }
+}
+
+/// Newest entries first.
+pub async fn list(db: &Db, filter: AuditFilter) -> Result<AuditPage, String> {
+    let limit = filter
+        .limit
+        .unwrap_or(DEFAULT_LIST_LIMIT)
+        .clamp(1, MAX_LIST_LIMIT);
+    let db = db.clone();
+    let mut entries = task::spawn_blocking(move || {
+        let conn = db.read()?;
+        let mut stmt = conn
+            .prepare(
+                "
+                SELECT id, actor, action, target_type, target, before, after, created_at
+                FROM admin_audit_log
+                WHERE (?1 IS NULL OR actor = ?1)
+                    AND (?2 IS NULL OR action = ?2)
+                    AND (?3 IS NULL OR target_type = ?3)
+                    AND (?4 IS NULL OR target = ?4)
+                    AND (?5 IS NULL OR created_at >= ?5)
+                    AND (?6 IS NULL OR created_at <= ?6)
+                    AND (?7 IS NULL OR id < ?7)
+                ORDER BY id DESC
+                LIMIT ?8
+                ",
+            )
+            .map_err(|err| format!("Failed to prepare statement: {err}"))?;
+        let rows = stmt
+            .query_map(
+                params![
+                    filter.actor,
+                    filter.action,
+                    filter.target_type,
+                    filter.target,
+                    filter.since,
+                    filter.until,
+                    filter.before,
+                    i64::try_from(limit + 1).unwrap_or(i64::MAX),
+                ],
+                |row| {
+                    Ok(AuditEntry {
+                        id: row.get(0)?,
+                        actor: row.get(1)?,
+                        action: row.get(2)?,
+                        target_type: row.get(3)?,
+                        target: row.get(4)?,
+                        before: parse_summary(row.get(5)?),
+                        after: parse_summary(row.get(6)?),
+                        created_at: row.get(7)?,
+                    })
+                },
+            )
+            .map_err(|err| format!("Failed to query audit log: {err}"))?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(|err| format!("Failed to read row: {err}"))
+    })
+    .await
+    .map_err(|_| "Failed to join blocking task".to_string())??;
+
+    let next_before = if entries.len() > limit {
+        entries.truncate(limit);
+        entries.last().map(|entry| entry.id)
+    } else {
+        None
+    };
+    Ok(AuditPage {
+        entries,
+        next_before,
+    })
+}
+
+fn parse_summary(raw: Option<String>) -> Option<Value> {
+    raw.map(|raw| serde_json::from_str(&raw).unwrap_or(Value::String(raw)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn entries_cannot_be_changed_or_removed() {
+        let db = Db::temporary();
+        record(&db, AuditEvent {
+            actor: "admin@example.com",
+            action: "lesson.delete",
+            target_type: "lesson",
+            target: "l1",
+            before: Some(serde_json::json!({"title": "L1"})),
+            after: None,
+        })
+        .await;
+
+        let conn = db.write().unwrap();
+        let update = conn.execute("UPDATE admin_audit_log SET actor = 'someone-else'", []);
+        let delete = conn.execute("DELETE FROM admin_audit_log", []);
+        for result in [update, delete] {
+            let error = result.expect_err("the audit log should be append-only");
+            assert!(error.to_string().contains("append-only"), "{error}");
+        }
+        drop(conn);
+
+        let page = list(&db, AuditFilter {
+            actor: None,
+            action: None,
+            target_type: None,
+            target: None,
+            since: None,
+            until: None,
+            before: None,
+            limit: None,
+        })
+        .await
+        .unwrap();
+        assert_eq!(page.entries.len(), 1);
+        assert_eq!(page.entries[0].actor, "admin@example.com");
+        assert_eq!(page.entries[0].before, Some(serde_json::json!({"title": "L1"})));
+    }
+}
@@ -1,11 +1,23 @@
-use rusqlite::Connection;
+use r2d2::{Pool, PooledConnection};
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{Connection, OpenFlags};
 use std::{
     env,
     path::{Path, PathBuf},
-    sync::{Arc, Mutex, OnceLock},
+    time::Duration,
 };
 
-static DB: OnceLock<Arc<Mutex<Connection>>> = OnceLock::new();
+use crate::core::migrations;
+
+/// How long a statement waits for another connection's write lock before
+/// failing with `SQLITE_BUSY`.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a caller waits for a free pooled connection.
+const CHECKOUT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Read connections kept open. WAL lets them read while a write is running.
+const READ_CONNECTIONS: u32 = 8;
+
+pub type DbConnection = PooledConnection<SqliteConnectionManager>;
 
 pub fn db_path() -> PathBuf {
     if let Ok(path) = env::var("APP_DB_PATH") {
@@ -20,48 +32,94 @@ pub fn db_path() -> PathBuf {
         .join("fsrs.sqlite3")
 }
 
-pub fn db() -> Arc<Mutex<Connection>> {
-    DB.get_or_init(|| {
-        let path = db_path();
-        if let Some(parent) = path.parent() {
-            let _ = std::fs::create_dir_all(parent);
-        }
-        let conn = Connection::open(path).expect("Failed to open database");
-        init_tables(&conn).expect("Failed to initialize database tables");
-        Arc::new(Mutex::new(conn))
-    })
-    .clone()
+/// Opens a single connection without migrating the database.
+pub fn open() -> rusqlite::Result<Connection> {
+    open_at(&db_path())
+}
+
+fn open_at(path: &Path) -> rusqlite::Result<Connection> {
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    Connection::open(path)
+}
+
+/// Handle to the database, shared through router state. SQLite allows one
+/// writer at a time, so writes go through a single pooled connection and
+/// queue for it, while reads use a separate pool of read-only connections.
+#[derive(Clone)]
+pub struct Db {
+    writer: Pool<SqliteConnectionManager>,
+    reader: Pool<SqliteConnectionManager>,
 }
 
-fn init_tables(conn: &Connection) -> rusqlite::Result<()> {
-    conn.execute_batch(
-        "
-        CREATE TABLE IF NOT EXISTS fsrs_cards (
-            card_id TEXT PRIMARY KEY,
-            stability REAL NOT NULL,
-            difficulty REAL NOT NULL,
-            last_review TEXT,
-            due_date TEXT,
-            interval_days INTEGER NOT NULL
-        );
-        CREATE TABLE IF NOT EXISTS fsrs_settings (
-            key TEXT PRIMARY KEY,
-            value TEXT NOT NULL
-        );
-        CREATE TABLE IF NOT EXISTS progress (
-            lesson_id TEXT PRIMARY KEY,
-            completed INTEGER NOT NULL,
-            updated_at TEXT NOT NULL DEFAULT (datetime('now'))
-        );
-        CREATE TABLE IF NOT EXISTS dictionary_cache (
-            cache_key TEXT PRIMARY KEY,
-            word TEXT NOT NULL,
-            definition TEXT NOT NULL,
-            examples TEXT NOT NULL,
-            updated_at TEXT NOT NULL DEFAULT (datetime('now'))
-        );
-        ",
-    )?;
+impl Db {
+    /// Migrates the database, switches it to WAL and opens the pools.
+    pub fn connect() -> Result<Self, String> {
+        Self::connect_at(&db_path())
+    }
+
+    /// [`Db::connect`] for the database file at `path`.
+    pub fn connect_at(path: &Path) -> Result<Self, String> {
+        let mut conn = open_at(path).map_err(|err| format!("Failed to open database: {err}"))?;
+        conn.pragma_update(None, "journal_mode", "WAL")
+            .map_err(|err| format!("Failed to enable WAL: {err}"))?;
+        for migration in migrations::migrate(&mut conn)? {
+            println!("Applied database migration {} ({})", migration.version, migration.name);
+        }
+        drop(conn);
+
+        let configure = |conn: &mut Connection| {
+            conn.busy_timeout(BUSY_TIMEOUT)?;
+            conn.pragma_update(None, "synchronous", "NORMAL")
+        };
+        let writer = Pool::builder()
+            .max_size(1)
+            .connection_timeout(CHECKOUT_TIMEOUT)
+            .build(SqliteConnectionManager::file(path).with_init(configure))
+            .map_err(|err| format!("Failed to open write connection: {err}"))?;
+        let reader = Pool::builder()
+            .max_size(READ_CONNECTIONS)
+            .connection_timeout(CHECKOUT_TIMEOUT)
+            .build(
+                SqliteConnectionManager::file(path)
+                    .with_flags(OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)
+                    .with_init(configure),
+            )
+            .map_err(|err| format!("Failed to open read connections: {err}"))?;
+        Ok(Self { writer, reader })
+    }
+
+    /// The write connection, for anything that changes the database.
+    /// Blocks until it is free, so call it inside `spawn_blocking`.
+    pub fn write(&self) -> Result<DbConnection, String> {
+        self.writer
+            .get()
+            .map_err(|err| format!("Database unavailable: {err}"))
+    }
 
-    Ok(())
+    /// A read-only connection. Blocks like [`Db::write`].
+    pub fn read(&self) -> Result<DbConnection, String> {
+        self.reader
+            .get()
+            .map_err(|err| format!("Database unavailable: {err}"))
+    }
+}
+
+#[cfg(test)]
+impl Db {
+    /// A fresh, migrated database in the temp directory.
+    pub fn temporary() -> Self {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let path = env::temp_dir().join(format!(
+            "avvai-test-{}-{}.sqlite3",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+        Self::connect_at(&path).expect("temporary database")
+    }
 }
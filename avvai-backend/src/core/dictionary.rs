@@ -1,7 +1,8 @@
 use std::env;
 
+use crate::core::db::Db;
 use crate::core::dictionary_cache::{self, DictionaryEntry};
-use crate::core::openrouter;
+use crate::core::{lesson, media, openrouter};
 
 const MODEL: &str = "google/gemini-2.5-flash-lite";
 
@@ -13,14 +14,14 @@ pub enum DictionaryError {
     InvalidJson,
 }
 
-pub async fn lookup(word: &str) -> Result<DictionaryEntry, DictionaryError> {
+pub async fn lookup(db: &Db, word: &str) -> Result<DictionaryEntry, DictionaryError> {
     let normalised = dictionary_cache::normalise(word);
     if normalised.is_empty() {
         return Err(DictionaryError::EmptyWord);
     }
 
-    if let Some(entry) = dictionary_cache::get(&normalised).await {
-        return Ok(entry);
+    if let Some(entry) = dictionary_cache::get(db, &normalised).await {
+        return Ok(with_pronunciation(&normalised, entry).await);
     }
 
     let api_key = env::var("OPENROUTER_API_KEY").map_err(|_| DictionaryError::ApiKeyMissing)?;
@@ -37,9 +38,17 @@ pub async fn lookup(word: &str) -> Result<DictionaryEntry, DictionaryError> {
     let entry = serde_json::from_str::<DictionaryEntry>(&cleaned)
         .map_err(|_| DictionaryError::InvalidJson)?;
 
-    dictionary_cache::set(&normalised, entry.clone()).await;
+    dictionary_cache::set(db, &normalised, entry.clone()).await;
 
-    Ok(entry)
+    Ok(with_pronunciation(&normalised, entry).await)
+}
+
+async fn with_pronunciation(word: &str, mut entry: DictionaryEntry) -> DictionaryEntry {
+    entry.audio_url = lesson::find_vocabulary_entry(word)
+        .await
+        .and_then(|vocab| vocab.audio)
+        .map(|audio| media::media_url(&audio));
+    entry
 }
 
 fn clean_json_response(raw: &str) -> String {
@@ -2,13 +2,17 @@ use rusqlite::OptionalExtension;
 use serde::{Deserialize, Serialize};
 use tokio::task;
 
-use crate::core::db;
+use crate::core::db::Db;
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct DictionaryEntry {
     pub word: String,
     pub definition: String,
     pub examples: Vec<String>,
+    /// Pronunciation clip from a matching lesson vocabulary entry. Derived on
+    /// lookup and never stored in the cache.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_url: Option<String>,
 }
 
 #[derive(Serialize, Clone)]
@@ -21,15 +25,15 @@ pub fn normalise(word: &str) -> String {
     word.trim().to_lowercase()
 }
 
-pub async fn get(word_key: &str) -> Option<DictionaryEntry> {
+pub async fn get(db: &Db, word_key: &str) -> Option<DictionaryEntry> {
     let key = normalise(word_key);
     if key.is_empty() {
         return None;
     }
-    let db = db::db();
+    let db = db.clone();
     task::spawn_blocking(move || {
         let row = {
-            let conn = db.lock().map_err(|_| "DB lock poisoned".to_string())?;
+            let conn = db.read()?;
             conn.query_row(
                 "SELECT word, definition, examples FROM dictionary_cache WHERE cache_key = ?1",
                 [key],
@@ -43,6 +47,7 @@ pub async fn get(word_key: &str) -> Option<DictionaryEntry> {
                         word,
                         definition,
                         examples,
+                        audio_url: None,
                     })
                 },
             )
@@ -58,7 +63,7 @@ pub async fn get(word_key: &str) -> Option<DictionaryEntry> {
     .flatten()
 }
 
-pub async fn set(word_key: &str, mut entry: DictionaryEntry) {
+pub async fn set(db: &Db, word_key: &str, mut entry: DictionaryEntry) {
     let key = normalise(word_key);
     if key.is_empty() {
         return;
@@ -68,13 +73,12 @@ pub async fn set(word_key: &str, mut entry: DictionaryEntry) {
         entry.word.clone_from(&key);
     }
 
-    let db = db::db();
+    let db = db.clone();
     let examples_json = serde_json::to_string(&entry.examples).unwrap_or_else(|_| "[]".to_string());
     let word = entry.word.clone();
     let definition = entry.definition.clone();
     task::spawn_blocking(move || {
-        db.lock()
-            .map_err(|_| "DB lock poisoned".to_string())?
+        db.write()?
             .execute(
             "
             INSERT INTO dictionary_cache (cache_key, word, definition, examples, updated_at)
@@ -94,16 +98,15 @@ pub async fn set(word_key: &str, mut entry: DictionaryEntry) {
     .ok();
 }
 
-pub async fn remove(word_key: &str) -> bool {
+pub async fn remove(db: &Db, word_key: &str) -> bool {
     let key = normalise(word_key);
     if key.is_empty() {
         return false;
     }
-    let db = db::db();
+    let db = db.clone();
     task::spawn_blocking(move || {
         let rows = db
-            .lock()
-            .map_err(|_| "DB lock poisoned".to_string())?
+            .write()?
             .execute("DELETE FROM dictionary_cache WHERE cache_key = ?1", [key])
             .map_err(|err| format!("Failed to delete dictionary cache: {err}"))?;
         Ok::<bool, String>(rows > 0)
@@ -114,10 +117,10 @@ pub async fn remove(word_key: &str) -> bool {
     .unwrap_or(false)
 }
 
-pub async fn list() -> Vec<DictionaryCacheEntry> {
-    let db = db::db();
+pub async fn list(db: &Db) -> Vec<DictionaryCacheEntry> {
+    let db = db.clone();
     task::spawn_blocking(move || {
-        let conn = db.lock().map_err(|_| "DB lock poisoned".to_string())?;
+        let conn = db.read()?;
         let entries = {
             let mut stmt = conn
                 .prepare(
@@ -139,6 +142,7 @@ pub async fn list() -> Vec<DictionaryCacheEntry> {
                             word,
                             definition,
                             examples,
+                            audio_url: None,
                         },
                     })
                 })
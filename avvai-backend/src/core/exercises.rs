@@ -0,0 +1,131 @@
+use rusqlite::params;
+use serde::Serialize;
+use tokio::task;
+
+use crate::core::db::Db;
+use crate::core::lesson::{ContentSection, Lesson};
+
+/// One submitted answer to a lesson exercise.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExerciseAttempt {
+    pub id: i64,
+    pub exercise_id: String,
+    pub answer: String,
+    /// Unset for exercises the client cannot mark, such as long answers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub correct: Option<bool>,
+    pub attempted_at: String,
+}
+
+/// Whether the lesson has an exercise with this id.
+pub fn has_exercise(lesson: &Lesson, exercise_id: &str) -> bool {
+    lesson.sections.iter().any(|section| match section {
+        ContentSection::Exercises(exercises) => exercises
+            .exercise_groups
+            .iter()
+            .flat_map(|group| &group.exercises)
+            .any(|exercise| exercise.id == exercise_id),
+        _ => false,
+    })
+}
+
+pub async fn record_attempt(
+    db: Db,
+    user_id: i64,
+    lesson_id: &str,
+    exercise_id: &str,
+    answer: String,
+    correct: Option<bool>,
+) -> Result<ExerciseAttempt, String> {
+    let lesson_id = lesson_id.to_string();
+    let exercise_id = exercise_id.to_string();
+    task::spawn_blocking(move || {
+        let (id, attempted_at) = db
+            .write()?
+            .query_row(
+                "
+                INSERT INTO exercise_attempts (user_id, lesson_id, exercise_id, answer, correct)
+                VALUES (?1, ?2, ?3, ?4, ?5)
+                RETURNING id, attempted_at
+                ",
+                params![user_id, lesson_id, exercise_id, answer, correct],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .map_err(|err| format!("Failed to save attempt: {err}"))?;
+        Ok(ExerciseAttempt {
+            id,
+            exercise_id,
+            answer,
+            correct,
+            attempted_at,
+        })
+    })
+    .await
+    .map_err(|_| "Failed to join blocking task".to_string())?
+}
+
+/// The learner's attempts at a lesson's exercises, oldest first.
+pub async fn load_attempts(
+    db: Db,
+    user_id: i64,
+    lesson_id: &str,
+) -> Result<Vec<ExerciseAttempt>, String> {
+    let lesson_id = lesson_id.to_string();
+    task::spawn_blocking(move || {
+        let conn = db.read()?;
+        let mut stmt = conn
+            .prepare(
+                "
+                SELECT id, exercise_id, answer, correct, attempted_at
+                FROM exercise_attempts
+                WHERE user_id = ?1 AND lesson_id = ?2
+                ORDER BY id
+                ",
+            )
+            .map_err(|err| format!("Failed to prepare query: {err}"))?;
+        let rows = stmt
+            .query_map(params![user_id, lesson_id], |row| {
+                Ok(ExerciseAttempt {
+                    id: row.get(0)?,
+                    exercise_id: row.get(1)?,
+                    answer: row.get(2)?,
+                    correct: row.get(3)?,
+                    attempted_at: row.get(4)?,
+                })
+            })
+            .map_err(|err| format!("Failed to query attempts: {err}"))?;
+
+        let mut attempts = Vec::new();
+        for row in rows {
+            attempts.push(row.map_err(|err| format!("Failed to read row: {err}"))?);
+        }
+        Ok(attempts)
+    })
+    .await
+    .map_err(|_| "Failed to join blocking task".to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn attempts_are_scoped_to_the_learner() {
+        let db = Db::temporary();
+        record_attempt(db.clone(), 1, "l1", "e1", "a".to_string(), Some(true))
+            .await
+            .unwrap();
+        record_attempt(db.clone(), 2, "l1", "e1", "b".to_string(), Some(false))
+            .await
+            .unwrap();
+        record_attempt(db.clone(), 1, "l2", "e1", "c".to_string(), None)
+            .await
+            .unwrap();
+
+        let attempts = load_attempts(db.clone(), 1, "l1").await.unwrap();
+        assert_eq!(attempts.len(), 1);
+        assert_eq!((attempts[0].answer.as_str(), attempts[0].correct), ("a", Some(true)));
+        assert!(load_attempts(db, 3, "l1").await.unwrap().is_empty());
+    }
+}
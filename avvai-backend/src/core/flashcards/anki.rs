@@ -0,0 +1,694 @@
+//! Reading and writing Anki collection packages (`.apkg`).
+//!
+//! Exports use the legacy schema-11 `collection.anki2` layout, which every
+//! Anki release from 2.1 onwards imports. Each avvai card becomes its own
+//! note of an "Avvai" note type, so cards keep independent schedules, and the
+//! card's FSRS memory state is written to `cards.data` the way Anki stores it.
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use rusqlite::{params, Connection, OpenFlags};
+use sha1::{Digest as _, Sha1};
+use sha2::Sha256;
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs,
+    io::{self, Cursor, Read, Write},
+    path::PathBuf,
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use super::{export::ExportItem, scheduler::StudyClock, CardState, CardTemplate};
+use crate::core::media;
+
+/// Fixed so re-exports reuse the note type already in the learner's Anki.
+const AVVAI_MODEL_ID: i64 = 1_729_000_000_000;
+const DEFAULT_DECK_ID: i64 = 1;
+const DECK_PREFIX: &str = "Avvai";
+const FIELD_SEPARATOR: char = '\u{1f}';
+const FIELD_NAMES: [&str; 5] = ["Front", "Back", "Hint", "Romanisation", "AvvaiId"];
+pub const AVVAI_ID_FIELD: &str = "AvvaiId";
+const DEFAULT_EASE: i64 = 2500;
+/// Largest collection database accepted from a package once decompressed,
+/// so a small zip cannot expand without bound. A little above the import
+/// route's body limit, since collections compress well.
+const MAX_COLLECTION_BYTES: u64 = 128 * 1024 * 1024;
+
+const SCHEMA: &str = "
+    CREATE TABLE col (
+        id integer primary key, crt integer not null, mod integer not null,
+        scm integer not null, ver integer not null, dty integer not null,
+        usn integer not null, ls integer not null, conf text not null,
+        models text not null, decks text not null, dconf text not null, tags text not null
+    );
+    CREATE TABLE notes (
+        id integer primary key, guid text not null, mid integer not null,
+        mod integer not null, usn integer not null, tags text not null,
+        flds text not null, sfld integer not null, csum integer not null,
+        flags integer not null, data text not null
+    );
+    CREATE TABLE cards (
+        id integer primary key, nid integer not null, did integer not null,
+        ord integer not null, mod integer not null, usn integer not null,
+        type integer not null, queue integer not null, due integer not null,
+        ivl integer not null, factor integer not null, reps integer not null,
+        lapses integer not null, left integer not null, odue integer not null,
+        odid integer not null, flags integer not null, data text not null
+    );
+    CREATE TABLE revlog (
+        id integer primary key, cid integer not null, usn integer not null,
+        ease integer not null, ivl integer not null, lastIvl integer not null,
+        factor integer not null, time integer not null, type integer not null
+    );
+    CREATE TABLE graves (usn integer not null, oid integer not null, type integer not null);
+    CREATE INDEX ix_notes_usn on notes (usn);
+    CREATE INDEX ix_cards_usn on cards (usn);
+    CREATE INDEX ix_revlog_usn on revlog (usn);
+    CREATE INDEX ix_cards_nid on cards (nid);
+    CREATE INDEX ix_cards_sched on cards (did, queue, due);
+    CREATE INDEX ix_revlog_cid on revlog (cid);
+    CREATE INDEX ix_notes_csum on notes (csum);
+";
+
+/// One review from an Anki `revlog`.
+pub struct AnkiReview {
+    pub at: DateTime<Utc>,
+    pub ease: u32,
+    /// Days when positive, seconds when negative (learning steps).
+    pub ivl: i64,
+    /// 0 learn, 1 review, 2 relearn, 3 filtered, 4 manual, 5 rescheduled.
+    pub kind: i64,
+}
+
+/// A card read from an Anki collection, with its note's identifying field.
+pub struct AnkiCard {
+    pub note_id: i64,
+    /// Set when the card came from an avvai export.
+    pub avvai_id: Option<String>,
+    /// The note's first field with HTML removed.
+    pub front: String,
+    pub ord: i64,
+    /// 0 new, 1 learning, 2 review, 3 relearning.
+    pub card_type: i64,
+    pub due: i64,
+    pub ivl: i64,
+    pub factor: i64,
+    /// FSRS stability and difficulty, when Anki had FSRS enabled.
+    pub memory: Option<(f32, f32)>,
+    pub reviews: Vec<AnkiReview>,
+}
+
+pub struct AnkiCollection {
+    pub created: DateTime<Utc>,
+    pub cards: Vec<AnkiCard>,
+}
+
+impl AnkiCollection {
+    /// Interprets a card's `due` field: a day number relative to the
+    /// collection's creation for review cards, a Unix timestamp for cards in
+    /// a learning step.
+    pub fn due_date(&self, card: &AnkiCard, clock: StudyClock) -> Option<DateTime<Utc>> {
+        match card.card_type {
+            1 | 3 if card.due > 1_000_000_000 => Utc.timestamp_opt(card.due, 0).single(),
+            1..=3 => {
+                let day = clock.study_day(self.created) + Duration::days(card.due);
+                Some(clock.start_of(day))
+            }
+            _ => None,
+        }
+    }
+}
+
+pub fn write_apkg(
+    items: &[ExportItem<'_>],
+    desired_retention: f32,
+    clock: StudyClock,
+    now: DateTime<Utc>,
+) -> Result<Vec<u8>, String> {
+    let (file, path) = create_temp_file("export")?;
+    drop(file);
+    let result = build_collection(&path, items, desired_retention, clock, now)
+        .and_then(|()| fs::read(&path).map_err(|err| format!("Failed to read collection: {err}")));
+    let _ = fs::remove_file(&path);
+    let collection = result?;
+
+    let mut media_files = Vec::new();
+    let mut seen = HashSet::new();
+    for item in items {
+        if let Some(filename) = &item.card.audio
+            && seen.insert(filename.clone())
+        {
+            let path = media::media_root().join(media::sanitize_filename(filename));
+            if path.is_file() {
+                media_files.push((filename.clone(), path));
+            }
+        }
+    }
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let zip_err = |err: zip::result::ZipError| format!("Failed to write package: {err}");
+    let io_err = |err: std::io::Error| format!("Failed to write package: {err}");
+
+    zip.start_file("collection.anki2", options).map_err(zip_err)?;
+    zip.write_all(&collection).map_err(io_err)?;
+
+    let mut manifest = serde_json::Map::new();
+    for (index, (filename, path)) in media_files.iter().enumerate() {
+        let bytes = fs::read(path).map_err(io_err)?;
+        zip.start_file(index.to_string(), options).map_err(zip_err)?;
+        zip.write_all(&bytes).map_err(io_err)?;
+        manifest.insert(index.to_string(), serde_json::Value::String(filename.clone()));
+    }
+    zip.start_file("media", options).map_err(zip_err)?;
+    zip.write_all(serde_json::Value::Object(manifest).to_string().as_bytes())
+        .map_err(io_err)?;
+
+    let cursor = zip.finish().map_err(zip_err)?;
+    Ok(cursor.into_inner())
+}
+
+pub fn read_apkg(bytes: &[u8]) -> Result<AnkiCollection, String> {
+    let mut archive =
+        ZipArchive::new(Cursor::new(bytes)).map_err(|err| format!("Not an Anki package: {err}"))?;
+
+    // `collection.anki21` holds the real data when both are present; the
+    // `.anki2` beside it is then a stub asking the user to upgrade.
+    let name = ["collection.anki21", "collection.anki2"]
+        .into_iter()
+        .find(|name| archive.index_for_name(name).is_some());
+    let Some(name) = name else {
+        return Err(if archive.index_for_name("collection.anki21b").is_some() {
+            "This package uses Anki's newest format; export again with \"Support older Anki versions\" enabled".to_string()
+        } else {
+            "Package does not contain an Anki collection".to_string()
+        });
+    };
+
+    let too_large = || format!("Collection exceeds the {MAX_COLLECTION_BYTES} byte limit");
+    let file = archive
+        .by_name(name)
+        .map_err(|err| format!("Failed to read package: {err}"))?;
+    if file.size() > MAX_COLLECTION_BYTES {
+        return Err(too_large());
+    }
+    // The declared size can lie, so the copy itself is bounded too.
+    let (mut staged, path) = create_temp_file("import")?;
+    let copied = io::copy(&mut file.take(MAX_COLLECTION_BYTES + 1), &mut staged)
+        .map_err(|err| format!("Failed to stage collection: {err}"));
+    drop(staged);
+    let result = match copied {
+        Ok(copied) if copied > MAX_COLLECTION_BYTES => Err(too_large()),
+        Ok(_) => read_collection(&path),
+        Err(err) => Err(err),
+    };
+    let _ = fs::remove_file(&path);
+    result
+}
+
+/// Creates a file in the system temp directory that did not exist before,
+/// so a file or link planted under a predictable name is never reused.
+fn create_temp_file(purpose: &str) -> Result<(fs::File, PathBuf), String> {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    loop {
+        let nanos = Utc::now().timestamp_nanos_opt().unwrap_or_default();
+        let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let path = env::temp_dir().join(format!(
+            "avvai-{purpose}-{}-{nanos}-{count}.anki2",
+            process::id()
+        ));
+        match fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((file, path)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(err) => return Err(format!("Failed to create temporary file: {err}")),
+        }
+    }
+}
+
+// =============================================================================
+// EXPORT
+// =============================================================================
+
+fn build_collection(
+    path: &PathBuf,
+    items: &[ExportItem<'_>],
+    desired_retention: f32,
+    clock: StudyClock,
+    now: DateTime<Utc>,
+) -> Result<(), String> {
+    let db_err = |err: rusqlite::Error| format!("Failed to build collection: {err}");
+    let mut conn = Connection::open(path).map_err(db_err)?;
+    conn.execute_batch(SCHEMA).map_err(db_err)?;
+
+    // The collection's creation day anchors review due days, so it must not
+    // be later than any date written.
+    let earliest = items
+        .iter()
+        .flat_map(|item| {
+            item.state
+                .and_then(|state| state.due_date)
+                .into_iter()
+                .chain(item.log.iter().map(|row| row.reviewed_at))
+        })
+        .fold(now, DateTime::min);
+    let created_day = clock.study_day(earliest);
+    let created = clock.start_of(created_day);
+    let now_secs = now.timestamp();
+    let now_ms = now.timestamp_millis();
+
+    let mut deck_ids: HashMap<&str, i64> = HashMap::new();
+    for item in items {
+        let next_id = now_ms + 1 + i64::try_from(deck_ids.len()).unwrap_or_default();
+        deck_ids.entry(item.card.lesson_title.as_str()).or_insert(next_id);
+    }
+
+    let tx = conn.transaction().map_err(db_err)?;
+    let mut revlog_ids = HashSet::new();
+    for (index, item) in items.iter().enumerate() {
+        let id = now_ms + i64::try_from(index).unwrap_or_default();
+        let card = &item.card.card;
+
+        let front = match (card.template, &item.card.audio) {
+            (CardTemplate::Audio, Some(filename)) => format!("[sound:{filename}]"),
+            _ => card.front.clone(),
+        };
+        let fields = [
+            front,
+            card.back.clone(),
+            card.hint.clone().unwrap_or_default(),
+            card.romanisation.clone().unwrap_or_default(),
+            card.id.clone(),
+        ];
+        let sort_field = strip_html(&fields[0]);
+        let tags = if item.card.tags.is_empty() {
+            String::new()
+        } else {
+            format!(" {} ", item.card.tags.join(" "))
+        };
+
+        tx.execute(
+            "INSERT INTO notes VALUES (?1, ?2, ?3, ?4, -1, ?5, ?6, ?7, ?8, 0, '')",
+            params![
+                id,
+                note_guid(&card.id),
+                AVVAI_MODEL_ID,
+                now_secs,
+                tags,
+                fields.join(&FIELD_SEPARATOR.to_string()),
+                sort_field,
+                field_checksum(&sort_field),
+            ],
+        )
+        .map_err(db_err)?;
+
+        let state = item.state.filter(|state| state.state != CardState::New);
+        let (card_type, queue, due) = match state {
+            None => (0, 0, i64::try_from(index).unwrap_or_default() + 1),
+            Some(state) => {
+                let due_at = state.due_date.unwrap_or(now);
+                match state.state {
+                    CardState::Learning => (1, 1, due_at.timestamp()),
+                    CardState::Relearning => (3, 1, due_at.timestamp()),
+                    _ => (2, 2, (clock.study_day(due_at) - created_day).num_days()),
+                }
+            }
+        };
+        let data = state.map_or_else(
+            || "{}".to_string(),
+            |state| {
+                serde_json::json!({
+                    "s": state.stability,
+                    "d": state.difficulty,
+                    "dr": desired_retention,
+                })
+                .to_string()
+            },
+        );
+
+        tx.execute(
+            "INSERT INTO cards VALUES (?1, ?2, ?3, 0, ?4, -1, ?5, ?6, ?7, ?8, ?9, ?10, ?11, 0, 0, 0, 0, ?12)",
+            params![
+                id,
+                id,
+                deck_ids[item.card.lesson_title.as_str()],
+                now_secs,
+                card_type,
+                queue,
+                due,
+                state.map_or(0, |state| state.interval_days),
+                if state.is_some() { DEFAULT_EASE } else { 0 },
+                i64::try_from(item.log.len()).unwrap_or_default(),
+                i64::try_from(item.lapses()).unwrap_or_default(),
+                data,
+            ],
+        )
+        .map_err(db_err)?;
+
+        for row in item.log {
+            let mut revlog_id = row.reviewed_at.timestamp_millis();
+            while !revlog_ids.insert(revlog_id) {
+                revlog_id += 1;
+            }
+            let ivl = if row.interval_days > 0 {
+                row.interval_days
+            } else {
+                -row
+                    .due_date
+                    .map_or(0, |due| (due - row.reviewed_at).num_seconds().max(0))
+            };
+            let kind = match row.state_before {
+                CardState::New | CardState::Learning => 0,
+                CardState::Review => 1,
+                CardState::Relearning => 2,
+            };
+            tx.execute(
+                "INSERT INTO revlog VALUES (?1, ?2, -1, ?3, ?4, ?5, 0, 0, ?6)",
+                params![
+                    revlog_id,
+                    id,
+                    row.rating,
+                    ivl,
+                    row.interval_before.unwrap_or_default(),
+                    kind,
+                ],
+            )
+            .map_err(db_err)?;
+        }
+    }
+
+    let mut decks = serde_json::Map::new();
+    decks.insert(DEFAULT_DECK_ID.to_string(), deck_json(DEFAULT_DECK_ID, "Default", now_secs));
+    for (title, id) in &deck_ids {
+        decks.insert(
+            id.to_string(),
+            deck_json(*id, &format!("{DECK_PREFIX}::{title}"), now_secs),
+        );
+    }
+    let conf = serde_json::json!({
+        "activeDecks": [DEFAULT_DECK_ID],
+        "curDeck": DEFAULT_DECK_ID,
+        "newSpread": 0,
+        "collapseTime": 1200,
+        "timeLim": 0,
+        "estTimes": true,
+        "dueCounts": true,
+        "curModel": AVVAI_MODEL_ID,
+        "nextPos": items.len() + 1,
+        "sortType": "noteFld",
+        "sortBackwards": false,
+        "addToCur": true,
+    });
+
+    tx.execute(
+        "INSERT INTO col VALUES (1, ?1, ?2, ?2, 11, 0, 0, 0, ?3, ?4, ?5, ?6, '{}')",
+        params![
+            created.timestamp(),
+            now_ms,
+            conf.to_string(),
+            model_json(now_secs).to_string(),
+            serde_json::Value::Object(decks).to_string(),
+            deck_config_json(now_secs, desired_retention).to_string(),
+        ],
+    )
+    .map_err(db_err)?;
+    tx.commit().map_err(db_err)?;
+    Ok(())
+}
+
+fn note_guid(card_id: &str) -> String {
+    Sha256::digest(card_id.as_bytes())
+        .iter()
+        .take(8)
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Anki's duplicate-check checksum: the first 8 hex digits of the SHA-1 of
+/// the sort field.
+fn field_checksum(field: &str) -> i64 {
+    let digest = Sha1::digest(field.as_bytes());
+    i64::from(u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]))
+}
+
+fn strip_html(value: &str) -> String {
+    let mut text = String::with_capacity(value.len());
+    let mut in_tag = false;
+    for c in value.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&")
+        .trim()
+        .to_string()
+}
+
+fn model_json(now_secs: i64) -> serde_json::Value {
+    let fields = FIELD_NAMES
+        .iter()
+        .enumerate()
+        .map(|(ord, name)| {
+            serde_json::json!({
+                "name": name,
+                "ord": ord,
+                "sticky": false,
+                "rtl": false,
+                "font": "Arial",
+                "size": 20,
+                "media": [],
+            })
+        })
+        .collect::<Vec<_>>();
+
+    serde_json::json!({
+        AVVAI_MODEL_ID.to_string(): {
+            "id": AVVAI_MODEL_ID,
+            "name": "Avvai",
+            "type": 0,
+            "mod": now_secs,
+            "usn": -1,
+            "sortf": 0,
+            "did": DEFAULT_DECK_ID,
+            "tmpls": [{
+                "name": "Card",
+                "ord": 0,
+                "qfmt": "{{Front}}{{#Hint}}<div class=hint>{{Hint}}</div>{{/Hint}}",
+                "afmt": "{{FrontSide}}<hr id=answer>{{Back}}{{#Romanisation}}<div class=romanisation>{{Romanisation}}</div>{{/Romanisation}}",
+                "bqfmt": "",
+                "bafmt": "",
+                "did": null,
+                "bfont": "",
+                "bsize": 0,
+            }],
+            "flds": fields,
+            "css": ".card { font-family: sans-serif; font-size: 24px; text-align: center; }\n.hint, .romanisation { font-size: 16px; color: #666; }",
+            "latexPre": "\\documentclass[12pt]{article}\n\\begin{document}\n",
+            "latexPost": "\\end{document}",
+            "latexsvg": false,
+            "req": [[0, "any", [0]]],
+            "tags": [],
+            "vers": [],
+        }
+    })
+}
+
+fn deck_json(id: i64, name: &str, now_secs: i64) -> serde_json::Value {
+    serde_json::json!({
+        "id": id,
+        "name": name,
+        "mod": now_secs,
+        "usn": -1,
+        "desc": "",
+        "dyn": 0,
+        "conf": 1,
+        "collapsed": false,
+        "browserCollapsed": false,
+        "extendNew": 0,
+        "extendRev": 0,
+        "newToday": [0, 0],
+        "revToday": [0, 0],
+        "lrnToday": [0, 0],
+        "timeToday": [0, 0],
+    })
+}
+
+fn deck_config_json(now_secs: i64, desired_retention: f32) -> serde_json::Value {
+    serde_json::json!({
+        "1": {
+            "id": 1,
+            "name": "Default",
+            "mod": now_secs,
+            "usn": -1,
+            "maxTaken": 60,
+            "autoplay": true,
+            "timer": 0,
+            "replayq": true,
+            "dyn": false,
+            "desiredRetention": desired_retention,
+            "new": {
+                "delays": [1.0, 10.0],
+                "ints": [1, 4, 0],
+                "initialFactor": DEFAULT_EASE,
+                "order": 1,
+                "perDay": 20,
+                "bury": false,
+            },
+            "lapse": {
+                "delays": [10.0],
+                "mult": 0.0,
+                "minInt": 1,
+                "leechFails": 8,
+                "leechAction": 1,
+            },
+            "rev": {
+                "perDay": 200,
+                "ease4": 1.3,
+                "maxIvl": 36500,
+                "hardFactor": 1.2,
+                "bury": false,
+            },
+        }
+    })
+}
+
+// =============================================================================
+// IMPORT
+// =============================================================================
+
+fn read_collection(path: &PathBuf) -> Result<AnkiCollection, String> {
+    let db_err = |err: rusqlite::Error| format!("Failed to read Anki collection: {err}");
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(db_err)?;
+
+    let (created, models): (i64, String) = conn
+        .query_row("SELECT crt, models FROM col", [], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })
+        .map_err(db_err)?;
+    let created = Utc
+        .timestamp_opt(created, 0)
+        .single()
+        .ok_or_else(|| "Invalid collection creation time".to_string())?;
+
+    // Position of the AvvaiId field in each note type that has one.
+    let models: serde_json::Value =
+        serde_json::from_str(&models).map_err(|err| format!("Invalid note types: {err}"))?;
+    let id_fields = models
+        .as_object()
+        .into_iter()
+        .flat_map(|models| models.values())
+        .filter_map(|model| {
+            let id = model.get("id")?.as_i64()?;
+            let ord = model
+                .get("flds")?
+                .as_array()?
+                .iter()
+                .position(|field| field.get("name").and_then(|name| name.as_str()) == Some(AVVAI_ID_FIELD))?;
+            Some((id, ord))
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut reviews: HashMap<i64, Vec<AnkiReview>> = HashMap::new();
+    {
+        let mut stmt = conn
+            .prepare("SELECT cid, id, ease, ivl, type FROM revlog ORDER BY cid, id")
+            .map_err(db_err)?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, u32>(2)?,
+                    row.get::<_, i64>(3)?,
+                    row.get::<_, i64>(4)?,
+                ))
+            })
+            .map_err(db_err)?;
+        for row in rows {
+            let (card_id, id, ease, ivl, kind) = row.map_err(db_err)?;
+            if let Some(at) = Utc.timestamp_millis_opt(id).single() {
+                reviews.entry(card_id).or_default().push(AnkiReview { at, ease, ivl, kind });
+            }
+        }
+    }
+
+    let mut stmt = conn
+        .prepare(
+            "
+            SELECT cards.id, notes.mid, notes.flds, cards.ord, cards.type,
+                cards.due, cards.ivl, cards.factor, cards.data, cards.nid
+            FROM cards JOIN notes ON notes.id = cards.nid
+            ORDER BY cards.id
+            ",
+        )
+        .map_err(db_err)?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, i64>(4)?,
+                row.get::<_, i64>(5)?,
+                row.get::<_, i64>(6)?,
+                row.get::<_, i64>(7)?,
+                row.get::<_, String>(8)?,
+                row.get::<_, i64>(9)?,
+            ))
+        })
+        .map_err(db_err)?;
+
+    let mut cards = Vec::new();
+    for row in rows {
+        let (id, model_id, fields, ord, card_type, due, ivl, factor, data, note_id) =
+            row.map_err(db_err)?;
+        let fields = fields.split(FIELD_SEPARATOR).collect::<Vec<_>>();
+        let avvai_id = id_fields
+            .get(&model_id)
+            .and_then(|ord| fields.get(*ord))
+            .map(|value| strip_html(value))
+            .filter(|value| !value.is_empty());
+        let memory = serde_json::from_str::<serde_json::Value>(&data)
+            .ok()
+            .and_then(|data| {
+                #[allow(clippy::cast_possible_truncation)]
+                let value = |key: &str| data.get(key).and_then(serde_json::Value::as_f64).map(|v| v as f32);
+                Some((value("s")?, value("d")?))
+            });
+
+        cards.push(AnkiCard {
+            note_id,
+            avvai_id,
+            front: fields.first().map(|field| strip_html(field)).unwrap_or_default(),
+            ord,
+            card_type,
+            due,
+            ivl,
+            factor,
+            memory,
+            reviews: reviews.remove(&id).unwrap_or_default(),
+        });
+    }
+
+    Ok(AnkiCollection { created, cards })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_back_an_exported_package() {
+        let package = write_apkg(&[], 0.9, StudyClock::new(0), Utc::now()).unwrap();
+        let collection = read_apkg(&package).unwrap();
+        assert!(collection.cards.is_empty());
+    }
+}
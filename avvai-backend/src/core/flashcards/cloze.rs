@@ -0,0 +1,243 @@
+use crate::core::lesson::{ContentSection, Lesson, VocabularyEntry};
+
+const CLOZE_BLANK: &str = "____";
+
+/// Stems shorter than this (in letters) are only matched exactly, so a
+/// short word such as கல் does not blank every longer word that starts with
+/// it, like கல்வி.
+const MIN_STEM_LETTERS: usize = 3;
+
+/// Tamil vowel signs that end a noun but are replaced by the case suffix's
+/// vowel, e.g. அன்பு → அன்பை.
+const SWAPPED_FINAL_VOWEL_SIGNS: &[char] = &['\u{0BC1}', '\u{0BC2}', '\u{0BBF}'];
+
+pub struct Cloze {
+    /// The sentence with the matched word replaced by a blank.
+    pub text: String,
+    /// The full sentence, shown on the back.
+    pub sentence: String,
+}
+
+/// Sentences from prose paragraphs (split on sentence-ending punctuation)
+/// followed by verse lines, in lesson order.
+pub fn lesson_sentences(lesson: &Lesson) -> Vec<String> {
+    let mut sentences = Vec::new();
+    for section in &lesson.sections {
+        match section {
+            ContentSection::Prose(prose) => {
+                for paragraph in &prose.paragraphs {
+                    sentences.extend(
+                        paragraph
+                            .split_inclusive(['.', '?', '!'])
+                            .map(str::trim)
+                            .filter(|sentence| !sentence.is_empty())
+                            .map(str::to_string),
+                    );
+                }
+            }
+            ContentSection::Poetry(poetry) => {
+                for verse in &poetry.verses {
+                    sentences.extend(
+                        verse
+                            .lines
+                            .iter()
+                            .map(|line| line.trim())
+                            .filter(|line| !line.is_empty())
+                            .map(str::to_string),
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+    sentences
+}
+
+/// Finds the first sentence containing the entry's word, its lemma, or a
+/// case-inflected form guessed by [`guess_noun_forms`], and blanks that whole
+/// word, suffixes included.
+pub fn find_cloze(entry: &VocabularyEntry, sentences: &[String]) -> Option<Cloze> {
+    let forms = guess_noun_forms(entry);
+    if forms.exact.is_empty() {
+        return None;
+    }
+
+    sentences.iter().find_map(|sentence| {
+        let (start, end) = words(sentence).find(|&(start, end)| forms.matches(&sentence[start..end]))?;
+        Some(Cloze {
+            text: format!("{}{CLOZE_BLANK}{}", &sentence[..start], &sentence[end..]),
+            sentence: sentence.clone(),
+        })
+    })
+}
+
+/// Surface forms a sentence word is compared against.
+struct NounForms {
+    /// The word and lemma as written.
+    exact: Vec<String>,
+    /// Stems an inflected form starts with.
+    stems: Vec<Stem>,
+}
+
+struct Stem {
+    text: String,
+    /// Set when the stem's final vowel was dropped, so the suffix has to
+    /// supply another one: அன்பை, but not அன்பர்.
+    vowel_follows: bool,
+}
+
+impl NounForms {
+    /// A stem only matches a word it makes up at least half of, so the rest
+    /// can be a case suffix rather than the bulk of a different word.
+    fn matches(&self, word: &str) -> bool {
+        self.exact.iter().any(|form| form == word)
+            || self.stems.iter().any(|stem| {
+                word.strip_prefix(stem.text.as_str()).is_some_and(|rest| {
+                    (!stem.vowel_follows || rest.starts_with(is_tamil_vowel_sign))
+                        && letter_count(&stem.text) * 2 >= letter_count(word)
+                })
+            })
+    }
+}
+
+/// Guesses case-inflected forms of the entry's word and lemma. This is a
+/// rule of thumb for two common noun patterns, not a lemmatiser (which needs
+/// a model call per word, too slow while building a deck): nouns in -ம் take
+/// an oblique -த்த- before case endings (அறம் → அறத்தை), and nouns ending in
+/// -உ, -ஊ or -இ swap that vowel for the suffix's (அன்பு → அன்பை).
+///
+/// It misses verbs and adjectives, nouns whose final consonant doubles
+/// (வீடு → வீட்டை), and anything else irregular; those sentences are only
+/// found when they contain the word or lemma exactly.
+fn guess_noun_forms(entry: &VocabularyEntry) -> NounForms {
+    let mut exact = Vec::new();
+    for form in [Some(entry.word.as_str()), entry.lemma.as_deref()]
+        .into_iter()
+        .flatten()
+        .map(str::trim)
+    {
+        if !form.is_empty() && !exact.iter().any(|existing| existing == form) {
+            exact.push(form.to_string());
+        }
+    }
+
+    let mut stems: Vec<Stem> = Vec::new();
+    for form in &exact {
+        let (text, vowel_follows) = if let Some(stem) = form.strip_suffix("ம்") {
+            (format!("{stem}த்"), false)
+        } else if let Some(stem) = form.strip_suffix(SWAPPED_FINAL_VOWEL_SIGNS) {
+            (stem.to_string(), true)
+        } else {
+            (form.clone(), false)
+        };
+        if letter_count(&text) >= MIN_STEM_LETTERS
+            && !stems.iter().any(|existing| existing.text == text)
+        {
+            stems.push(Stem { text, vowel_follows });
+        }
+    }
+
+    NounForms { exact, stems }
+}
+
+/// Letters as a reader counts them: a Tamil consonant with its vowel sign or
+/// virama is one letter (grapheme cluster), though it is several code points.
+fn letter_count(word: &str) -> usize {
+    word.chars().filter(|&c| !is_tamil_combining_mark(c)).count()
+}
+
+fn is_tamil_vowel_sign(c: char) -> bool {
+    matches!(c, '\u{0BBE}'..='\u{0BCC}')
+}
+
+/// Tamil anusvara, vowel signs, virama and the au length mark.
+fn is_tamil_combining_mark(c: char) -> bool {
+    matches!(c, '\u{0B82}' | '\u{0BBE}'..='\u{0BCD}' | '\u{0BD7}')
+}
+
+/// Byte ranges of the words in a sentence. Tamil vowel signs and virama are
+/// combining marks, so anything that is not whitespace or punctuation counts
+/// as part of a word.
+fn words(sentence: &str) -> impl Iterator<Item = (usize, usize)> + '_ {
+    let mut start = None;
+    let mut chars = sentence.char_indices().chain(std::iter::once((sentence.len(), ' ')));
+    std::iter::from_fn(move || {
+        for (index, c) in chars.by_ref() {
+            let is_word = !(c.is_whitespace() || c.is_ascii_punctuation() || is_typographic_punctuation(c));
+            match (start, is_word) {
+                (None, true) => start = Some(index),
+                (Some(begin), false) => {
+                    start = None;
+                    return Some((begin, index));
+                }
+                _ => {}
+            }
+        }
+        None
+    })
+}
+
+fn is_typographic_punctuation(c: char) -> bool {
+    matches!(c, '“' | '”' | '‘' | '’' | '—' | '–' | '…' | '।')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(word: &str) -> VocabularyEntry {
+        VocabularyEntry {
+            id: None,
+            word: word.to_string(),
+            meaning: String::new(),
+            romanisation: None,
+            audio: None,
+            lemma: None,
+            tags: Vec::new(),
+        }
+    }
+
+    fn blank(word: &str, sentence: &str) -> Option<String> {
+        find_cloze(&entry(word), &[sentence.to_string()]).map(|cloze| cloze.text)
+    }
+
+    #[test]
+    fn blanks_inflected_forms() {
+        assert_eq!(blank("அன்பு", "அவள் அன்பை மறந்தாள்.").as_deref(), Some("அவள் ____ மறந்தாள்."));
+        assert_eq!(blank("அறம்", "அறத்தை செய்.").as_deref(), Some("____ செய்."));
+    }
+
+    #[test]
+    fn short_word_does_not_match_longer_word() {
+        // கல் is two letters but three code points.
+        assert_eq!(letter_count("கல்"), 2);
+        assert_eq!(blank("கல்", "கல்வி கரையில."), None);
+        assert_eq!(blank("கல்", "ஒரு கல் கிடந்தது.").as_deref(), Some("ஒரு ____ கிடந்தது."));
+    }
+
+    #[test]
+    fn stem_must_cover_most_of_the_word() {
+        // பாடம் (lesson) takes a case ending in பாடத்தினால், but is only the
+        // start of பாடத்திட்டம் (curriculum).
+        assert_eq!(blank("பாடம்", "பாடத்தினால் பயன்.").as_deref(), Some("____ பயன்."));
+        assert_eq!(blank("பாடம்", "பாடத்திட்டம் மாறியது."), None);
+    }
+
+    #[test]
+    fn guessed_forms_do_not_blank_unrelated_words() {
+        // அன்ப is only the start of அன்பர் (devotee); the dropped -உ must be
+        // replaced by a suffix vowel.
+        assert_eq!(blank("அன்பு", "அன்பர் வந்தார்."), None);
+        assert_eq!(blank("அன்பு", "அன்புக்கு இணை இல்லை.").as_deref(), Some("____ இணை இல்லை."));
+        // The oblique stem அறத் is half of அறத்துப்பால் at most.
+        assert_eq!(blank("அறம்", "அறத்துப்பால் படி."), None);
+        // A two-letter noun is only ever matched exactly.
+        assert_eq!(blank("மரு", "மருந்து குடி."), None);
+    }
+
+    #[test]
+    fn misses_doubled_consonant_inflections() {
+        // வீடு → வீட்டை doubles the ட், which the guessed forms do not cover.
+        assert_eq!(blank("வீடு", "வீட்டை பார்."), None);
+    }
+}
@@ -0,0 +1,380 @@
+use chrono::Utc;
+use rusqlite::params;
+use serde::Serialize;
+use std::collections::HashMap;
+use tokio::task;
+
+use crate::core::db::Db;
+use super::{FlashcardsError, FlashcardsState, VocabularyCard};
+
+const LESSON_PREFIX: &str = "lesson:";
+const COURSE_PREFIX: &str = "course:";
+const FILTERED_PREFIX: &str = "filtered:";
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeckKind {
+    Lesson,
+    Course,
+    Filtered,
+}
+
+/// A user-defined deck selects cards by tag, by lemma, or by both.
+#[derive(Serialize, Clone)]
+pub struct DeckFilter {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lemma: Option<String>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct Deck {
+    pub id: String,
+    pub name: String,
+    pub kind: DeckKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<DeckFilter>,
+    pub card_count: usize,
+    /// Overrides the global desired retention for reviews done in this deck.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub desired_retention: Option<f32>,
+}
+
+pub struct NewDeck {
+    pub name: String,
+    pub tag: Option<String>,
+    pub lemma: Option<String>,
+}
+
+impl Deck {
+    pub(super) fn contains(&self, card: &VocabularyCard) -> bool {
+        match self.kind {
+            DeckKind::Lesson => self.id.strip_prefix(LESSON_PREFIX) == Some(&card.lesson_id),
+            DeckKind::Course => card
+                .course
+                .as_deref()
+                .is_some_and(|course| self.id.strip_prefix(COURSE_PREFIX) == Some(&course_key(course))),
+            DeckKind::Filtered => self.filter.as_ref().is_some_and(|filter| {
+                filter
+                    .tag
+                    .as_ref()
+                    .is_none_or(|tag| card.tags.iter().any(|candidate| candidate == tag))
+                    && filter.lemma.as_ref().is_none_or(|lemma| card.lemma == *lemma)
+            }),
+        }
+    }
+}
+
+/// Lesson decks in curriculum order, then one deck per course (the lesson's
+/// source text), then the learner's filtered decks.
+pub async fn list_decks(
+    state: &FlashcardsState,
+    user_id: i64,
+    cards: &[VocabularyCard],
+) -> Result<Vec<Deck>, FlashcardsError> {
+    let overrides = load_retention_overrides(state.db.clone(), user_id)
+        .await
+        .map_err(FlashcardsError::Internal)?;
+    let filtered = load_filtered_decks(state.db.clone(), user_id)
+        .await
+        .map_err(FlashcardsError::Internal)?;
+
+    let mut decks: Vec<Deck> = Vec::new();
+    let mut courses: Vec<Deck> = Vec::new();
+    for card in cards {
+        let lesson_deck = format!("{LESSON_PREFIX}{}", card.lesson_id);
+        if decks.last().is_none_or(|deck| deck.id != lesson_deck) {
+            decks.push(auto_deck(lesson_deck, card.lesson_title.clone(), DeckKind::Lesson));
+        }
+
+        if let Some(course) = &card.course {
+            let course_deck = format!("{COURSE_PREFIX}{}", course_key(course));
+            if !courses.iter().any(|deck| deck.id == course_deck) {
+                courses.push(auto_deck(course_deck, course.clone(), DeckKind::Course));
+            }
+        }
+    }
+    decks.extend(courses);
+    decks.extend(filtered);
+
+    for deck in &mut decks {
+        deck.card_count = cards.iter().filter(|card| deck.contains(card)).count();
+        deck.desired_retention = overrides.get(&deck.id).copied();
+    }
+
+    Ok(decks)
+}
+
+/// Looks a deck up by id, failing with `NotFound` for unknown ids.
+pub async fn find_deck(
+    state: &FlashcardsState,
+    user_id: i64,
+    cards: &[VocabularyCard],
+    deck_id: &str,
+) -> Result<Deck, FlashcardsError> {
+    list_decks(state, user_id, cards)
+        .await?
+        .into_iter()
+        .find(|deck| deck.id == deck_id)
+        .ok_or_else(|| FlashcardsError::NotFound(format!("Deck not found: {deck_id}")))
+}
+
+pub async fn create_deck(
+    state: &FlashcardsState,
+    user_id: i64,
+    deck: NewDeck,
+) -> Result<Deck, FlashcardsError> {
+    let name = deck.name.trim().to_string();
+    let tag = deck.tag.map(|tag| tag.trim().to_string()).filter(|tag| !tag.is_empty());
+    let lemma = deck
+        .lemma
+        .map(|lemma| lemma.trim().to_string())
+        .filter(|lemma| !lemma.is_empty());
+
+    if name.is_empty() {
+        return Err(FlashcardsError::BadRequest("name is required".to_string()));
+    }
+    if tag.is_none() && lemma.is_none() {
+        return Err(FlashcardsError::BadRequest(
+            "A filtered deck needs a tag or a lemma".to_string(),
+        ));
+    }
+
+    let db = state.db.clone();
+    let filter = DeckFilter { tag, lemma };
+    let (row_name, row_tag, row_lemma) = (name.clone(), filter.tag.clone(), filter.lemma.clone());
+    let id = task::spawn_blocking(move || {
+        let conn = db.write()?;
+        conn.execute(
+            "INSERT INTO flashcard_decks (user_id, name, tag, lemma, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![user_id, row_name, row_tag, row_lemma, Utc::now().to_rfc3339()],
+        )
+        .map_err(|err| format!("Failed to create deck: {err}"))?;
+        Ok::<_, String>(conn.last_insert_rowid())
+    })
+    .await
+    .map_err(|_| FlashcardsError::Internal("Failed to join blocking task".to_string()))?
+    .map_err(FlashcardsError::Internal)?;
+
+    Ok(Deck {
+        id: format!("{FILTERED_PREFIX}{id}"),
+        name,
+        kind: DeckKind::Filtered,
+        filter: Some(filter),
+        card_count: 0,
+        desired_retention: None,
+    })
+}
+
+/// Deletes a filtered deck. Lesson and course decks follow the lesson files
+/// and cannot be deleted.
+pub async fn delete_deck(
+    state: &FlashcardsState,
+    user_id: i64,
+    deck_id: &str,
+) -> Result<(), FlashcardsError> {
+    let Some(id) = deck_id
+        .strip_prefix(FILTERED_PREFIX)
+        .and_then(|id| id.parse::<i64>().ok())
+    else {
+        return Err(FlashcardsError::BadRequest(
+            "Only filtered decks can be deleted".to_string(),
+        ));
+    };
+
+    let db = state.db.clone();
+    let deck_id = deck_id.to_string();
+    let deleted = task::spawn_blocking(move || {
+        let mut conn = db.write()?;
+        let tx = conn
+            .transaction()
+            .map_err(|err| format!("Failed to start transaction: {err}"))?;
+        let deleted = tx
+            .execute(
+                "DELETE FROM flashcard_decks WHERE id = ?1 AND user_id = ?2",
+                [id, user_id],
+            )
+            .map_err(|err| format!("Failed to delete deck: {err}"))?;
+        tx.execute(
+            "DELETE FROM flashcard_deck_options WHERE user_id = ?1 AND deck_id = ?2",
+            params![user_id, deck_id],
+        )
+        .map_err(|err| format!("Failed to delete deck options: {err}"))?;
+        tx.commit()
+            .map_err(|err| format!("Failed to commit deck deletion: {err}"))?;
+        Ok::<_, String>(deleted)
+    })
+    .await
+    .map_err(|_| FlashcardsError::Internal("Failed to join blocking task".to_string()))?
+    .map_err(FlashcardsError::Internal)?;
+
+    if deleted == 0 {
+        return Err(FlashcardsError::NotFound(format!(
+            "Deck not found: {FILTERED_PREFIX}{id}"
+        )));
+    }
+    Ok(())
+}
+
+pub async fn save_deck_retention(
+    db: Db,
+    user_id: i64,
+    deck_id: &str,
+    desired_retention: f32,
+) -> Result<(), String> {
+    let deck_id = deck_id.to_string();
+    task::spawn_blocking(move || {
+        db.write()?
+            .execute(
+                "
+                INSERT INTO flashcard_deck_options (user_id, deck_id, desired_retention)
+                VALUES (?1, ?2, ?3)
+                ON CONFLICT(user_id, deck_id) DO UPDATE SET desired_retention = excluded.desired_retention
+                ",
+                params![user_id, deck_id, desired_retention],
+            )
+            .map_err(|err| format!("Failed to save deck options: {err}"))?;
+        Ok(())
+    })
+    .await
+    .map_err(|_| "Failed to join blocking task".to_string())?
+}
+
+fn auto_deck(id: String, name: String, kind: DeckKind) -> Deck {
+    Deck {
+        id,
+        name,
+        kind,
+        filter: None,
+        card_count: 0,
+        desired_retention: None,
+    }
+}
+
+/// Course decks are keyed by the source title, lowercased with whitespace
+/// and punctuation collapsed to hyphens. Tamil combining marks are kept.
+fn course_key(title: &str) -> String {
+    title
+        .to_lowercase()
+        .split(|c: char| c.is_whitespace() || c.is_ascii_punctuation())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+async fn load_retention_overrides(db: Db, user_id: i64) -> Result<HashMap<String, f32>, String> {
+    task::spawn_blocking(move || {
+        let conn = db.read()?;
+        let map = {
+            let mut stmt = conn
+                .prepare("SELECT deck_id, desired_retention FROM flashcard_deck_options WHERE user_id = ?1")
+                .map_err(|err| format!("Failed to prepare statement: {err}"))?;
+            let rows = stmt
+                .query_map([user_id], |row| Ok((row.get(0)?, row.get(1)?)))
+                .map_err(|err| format!("Failed to query deck options: {err}"))?;
+
+            let mut map = HashMap::new();
+            for row in rows {
+                let (deck_id, retention) = row.map_err(|err| format!("Failed to read row: {err}"))?;
+                map.insert(deck_id, retention);
+            }
+            map
+        };
+        drop(conn);
+
+        Ok(map)
+    })
+    .await
+    .map_err(|_| "Failed to join blocking task".to_string())?
+}
+
+async fn load_filtered_decks(db: Db, user_id: i64) -> Result<Vec<Deck>, String> {
+    task::spawn_blocking(move || {
+        let conn = db.read()?;
+        let decks = {
+            let mut stmt = conn
+                .prepare("SELECT id, name, tag, lemma FROM flashcard_decks WHERE user_id = ?1 ORDER BY id")
+                .map_err(|err| format!("Failed to prepare statement: {err}"))?;
+            let rows = stmt
+                .query_map([user_id], |row| {
+                    let id: i64 = row.get(0)?;
+                    Ok(Deck {
+                        id: format!("{FILTERED_PREFIX}{id}"),
+                        name: row.get(1)?,
+                        kind: DeckKind::Filtered,
+                        filter: Some(DeckFilter {
+                            tag: row.get(2)?,
+                            lemma: row.get(3)?,
+                        }),
+                        card_count: 0,
+                        desired_retention: None,
+                    })
+                })
+                .map_err(|err| format!("Failed to query decks: {err}"))?;
+
+            let mut decks = Vec::new();
+            for row in rows {
+                decks.push(row.map_err(|err| format!("Failed to read row: {err}"))?);
+            }
+            decks
+        };
+        drop(conn);
+
+        Ok(decks)
+    })
+    .await
+    .map_err(|_| "Failed to join blocking task".to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::flashcards::{
+        lesson_cards,
+        tests::{fixture_lesson, test_state},
+    };
+
+    fn retention(decks: &[Deck], id: &str) -> Option<f32> {
+        decks.iter().find(|deck| deck.id == id).unwrap().desired_retention
+    }
+
+    #[tokio::test]
+    async fn retention_override_applies_to_one_deck_of_one_learner() {
+        let state = test_state();
+        let cards = lesson_cards(&fixture_lesson());
+        let filtered = create_deck(&state, 1, NewDeck {
+            name: "Virtues".to_string(),
+            tag: Some("virtue".to_string()),
+            lemma: None,
+        })
+        .await
+        .unwrap();
+        save_deck_retention(state.db.clone(), 1, "lesson:fixture", 0.95).await.unwrap();
+        save_deck_retention(state.db.clone(), 1, &filtered.id, 0.8).await.unwrap();
+
+        let decks = list_decks(&state, 1, &cards).await.unwrap();
+        let ids = decks.iter().map(|deck| deck.id.as_str()).collect::<Vec<_>>();
+        assert_eq!(ids, ["lesson:fixture", "course:thirukkural", filtered.id.as_str()]);
+        assert_eq!(retention(&decks, "lesson:fixture"), Some(0.95));
+        assert_eq!(retention(&decks, "course:thirukkural"), None);
+        assert_eq!(retention(&decks, &filtered.id), Some(0.8));
+        assert_eq!(decks[0].card_count, decks[1].card_count);
+        assert!(decks[2].card_count > 0 && decks[2].card_count < decks[0].card_count);
+
+        let other = list_decks(&state, 2, &cards).await.unwrap();
+        assert!(other.iter().all(|deck| deck.desired_retention.is_none()));
+
+        // Deleting a filtered deck drops its override with it.
+        delete_deck(&state, 1, &filtered.id).await.unwrap();
+        let remaining: i64 = state
+            .db
+            .read()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM flashcard_deck_options WHERE user_id = 1", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(remaining, 1);
+    }
+}
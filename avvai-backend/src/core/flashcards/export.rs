@@ -0,0 +1,211 @@
+use chrono::{DateTime, Utc};
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+};
+use tokio::task;
+
+use crate::core::db::Db;
+use super::{CardState, StoredState, VocabularyCard};
+
+/// File formats a collection can be exported to.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// An Anki package with notes, scheduling, review history and audio.
+    Apkg,
+    /// One row per card, for spreadsheets.
+    Csv,
+}
+
+impl ExportFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "apkg" => Some(Self::Apkg),
+            "csv" => Some(Self::Csv),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Apkg => "apkg",
+            Self::Csv => "csv",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Apkg => "application/octet-stream",
+            Self::Csv => "text/csv; charset=utf-8",
+        }
+    }
+}
+
+/// An exported collection ready to be sent as a download.
+pub struct ExportFile {
+    pub filename: String,
+    pub content_type: &'static str,
+    pub bytes: Vec<u8>,
+}
+
+/// One logged review, as carried into an export.
+pub struct LogRow {
+    pub rating: u32,
+    pub reviewed_at: DateTime<Utc>,
+    pub interval_days: i64,
+    pub interval_before: Option<i64>,
+    pub due_date: Option<DateTime<Utc>>,
+    pub state_before: CardState,
+}
+
+/// A card with everything an export needs: content, deck, current state and
+/// full review history (oldest first).
+pub struct ExportItem<'a> {
+    pub card: &'a VocabularyCard,
+    pub state: Option<&'a StoredState>,
+    pub log: &'a [LogRow],
+}
+
+impl ExportItem<'_> {
+    /// Times the card was forgotten after graduating.
+    pub fn lapses(&self) -> usize {
+        self.log
+            .iter()
+            .filter(|row| row.state_before == CardState::Review && row.rating == 1)
+            .count()
+    }
+}
+
+const CSV_HEADER: &str = "card_id,template,front,back,hint,deck,tags,state,due_date,interval_days,stability,difficulty,last_review,reviews,lapses";
+
+/// One row per card with its current FSRS state. Reviews are summarised as
+/// counts; the `.apkg` export carries the full history.
+pub fn to_csv(items: &[ExportItem<'_>]) -> String {
+    let mut csv = String::from(CSV_HEADER);
+    csv.push_str("\r\n");
+
+    for item in items {
+        let card = &item.card.card;
+        let state = item.state;
+        let fields = [
+            card.id.clone(),
+            card.template.as_str().to_string(),
+            card.front.clone(),
+            card.back.clone(),
+            card.hint.clone().unwrap_or_default(),
+            item.card.lesson_title.clone(),
+            item.card.tags.join(" "),
+            state.map_or(CardState::New, |state| state.state).as_str().to_string(),
+            state
+                .and_then(|state| state.due_date)
+                .map(|due| due.to_rfc3339())
+                .unwrap_or_default(),
+            state.map(|state| state.interval_days.to_string()).unwrap_or_default(),
+            state.map(|state| state.stability.to_string()).unwrap_or_default(),
+            state.map(|state| state.difficulty.to_string()).unwrap_or_default(),
+            state
+                .and_then(|state| state.last_review)
+                .map(|last| last.to_rfc3339())
+                .unwrap_or_default(),
+            item.log.len().to_string(),
+            item.lapses().to_string(),
+        ];
+
+        let line = fields
+            .iter()
+            .map(|field| csv_field(field))
+            .collect::<Vec<_>>()
+            .join(",");
+        let _ = write!(csv, "{line}\r\n");
+    }
+
+    csv
+}
+
+/// Quotes a cell when needed. Cells a spreadsheet would read as a formula
+/// get a leading `'` so they stay text.
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@']) {
+        format!("'{value}")
+    } else {
+        value.to_string()
+    };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+/// The whole review log grouped by card, oldest review first.
+pub async fn load_log_rows(db: Db, user_id: i64) -> Result<HashMap<String, Vec<LogRow>>, String> {
+    task::spawn_blocking(move || {
+        let conn = db.read()?;
+        let log = {
+            let mut stmt = conn
+                .prepare(
+                    "
+                    SELECT card_id, rating, reviewed_at, interval_days, interval_before, due_date, state_before
+                    FROM fsrs_review_log
+                    WHERE user_id = ?1
+                    ORDER BY card_id, id
+                    ",
+                )
+                .map_err(|err| format!("Failed to prepare statement: {err}"))?;
+            let rows = stmt
+                .query_map([user_id], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, u32>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, i64>(3)?,
+                        row.get::<_, Option<i64>>(4)?,
+                        row.get::<_, String>(5)?,
+                        row.get::<_, Option<String>>(6)?,
+                    ))
+                })
+                .map_err(|err| format!("Failed to query review log: {err}"))?;
+
+            let mut log: HashMap<String, Vec<LogRow>> = HashMap::new();
+            for row in rows {
+                let (card_id, rating, reviewed_at, interval_days, interval_before, due_date, state_before) =
+                    row.map_err(|err| format!("Failed to read row: {err}"))?;
+                let Ok(reviewed_at) = DateTime::parse_from_rfc3339(&reviewed_at) else {
+                    continue;
+                };
+                log.entry(card_id).or_default().push(LogRow {
+                    rating,
+                    reviewed_at: reviewed_at.with_timezone(&Utc),
+                    interval_days,
+                    interval_before,
+                    due_date: DateTime::parse_from_rfc3339(&due_date)
+                        .ok()
+                        .map(|due| due.with_timezone(&Utc)),
+                    state_before: state_before
+                        .as_deref()
+                        .map_or(CardState::Review, CardState::parse),
+                });
+            }
+            log
+        };
+        drop(conn);
+
+        Ok(log)
+    })
+    .await
+    .map_err(|_| "Failed to join blocking task".to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_cells_cannot_start_a_formula() {
+        assert_eq!(csv_field("=HYPERLINK(\"http://x\")"), "\"'=HYPERLINK(\"\"http://x\"\")\"");
+        assert_eq!(csv_field("+1"), "'+1");
+        assert_eq!(csv_field("-1"), "'-1");
+        assert_eq!(csv_field("@SUM(A1)"), "'@SUM(A1)");
+        assert_eq!(csv_field("வணக்கம், நண்பா"), "\"வணக்கம், நண்பா\"");
+    }
+}
@@ -0,0 +1,328 @@
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tokio::task;
+
+use crate::core::db::Db;
+use super::{CardTemplate, StoredState, VocabularyCard, SETTINGS_KEY_LEECH_THRESHOLD};
+
+pub const DEFAULT_LEECH_THRESHOLD: usize = 8;
+pub const MAX_LEECH_THRESHOLD: usize = 99;
+pub const MAX_FLAG_CARDS: usize = 500;
+
+/// What happens to a card once it becomes a leech. It is always reported as
+/// one; `suspend` also takes it out of the queue until it is unsuspended.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LeechAction {
+    Tag,
+    Suspend,
+}
+
+/// A learner's manual flags on one card. Buried cards come back on their
+/// own at the next study day; suspended cards stay out until unsuspended.
+#[derive(Default)]
+pub struct CardFlags {
+    pub suspended: bool,
+    pub buried_until: Option<DateTime<Utc>>,
+}
+
+impl CardFlags {
+    pub fn is_hidden(&self, now: DateTime<Utc>) -> bool {
+        self.suspended || self.buried_until.is_some_and(|until| until > now)
+    }
+}
+
+/// A card that has been forgotten at least `threshold` times after
+/// graduating.
+#[derive(Serialize)]
+pub struct Leech {
+    pub id: String,
+    pub template: CardTemplate,
+    pub front: String,
+    pub back: String,
+    pub lesson_id: String,
+    pub lesson_title: String,
+    pub lapses: usize,
+    pub suspended: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_review: Option<String>,
+}
+
+/// A card that is a leech for at least one learner, so teachers can see
+/// which words learners struggle with.
+#[derive(Serialize)]
+pub struct LeechSummary {
+    pub id: String,
+    pub template: CardTemplate,
+    pub front: String,
+    pub back: String,
+    pub lesson_id: String,
+    pub lesson_title: String,
+    /// Learners for whom the card is a leech, each by their own threshold.
+    pub learners: usize,
+    /// How many of those learners have it suspended.
+    pub suspended: usize,
+    /// Lapses summed over those learners.
+    pub lapses: usize,
+}
+
+pub struct LeechCounts {
+    pub learners: usize,
+    pub suspended: usize,
+    pub lapses: usize,
+}
+
+/// Leeches among `cards`, most lapses first.
+pub fn leeches(
+    cards: &[VocabularyCard],
+    states: &HashMap<String, StoredState>,
+    flags: &HashMap<String, CardFlags>,
+    lapses: &HashMap<String, usize>,
+    threshold: usize,
+) -> Vec<Leech> {
+    let mut leeches = cards
+        .iter()
+        .filter_map(|card| {
+            let count = lapses.get(&card.card.id).copied().unwrap_or_default();
+            (count >= threshold).then(|| Leech {
+                id: card.card.id.clone(),
+                template: card.card.template,
+                front: card.card.front.clone(),
+                back: card.card.back.clone(),
+                lesson_id: card.lesson_id.clone(),
+                lesson_title: card.lesson_title.clone(),
+                lapses: count,
+                suspended: flags.get(&card.card.id).is_some_and(|flags| flags.suspended),
+                last_review: states
+                    .get(&card.card.id)
+                    .and_then(|state| state.last_review)
+                    .map(|last| last.to_rfc3339()),
+            })
+        })
+        .collect::<Vec<_>>();
+    leeches.sort_by(|a, b| b.lapses.cmp(&a.lapses).then_with(|| a.id.cmp(&b.id)));
+    leeches
+}
+
+/// Summaries of the `cards` that are leeches for any learner, the most
+/// widespread first. Cards no longer in any lesson are left out.
+pub fn leech_summaries(
+    cards: &[VocabularyCard],
+    counts: &HashMap<String, LeechCounts>,
+) -> Vec<LeechSummary> {
+    let mut summaries = cards
+        .iter()
+        .filter_map(|card| {
+            let counts = counts.get(&card.card.id)?;
+            Some(LeechSummary {
+                id: card.card.id.clone(),
+                template: card.card.template,
+                front: card.card.front.clone(),
+                back: card.card.back.clone(),
+                lesson_id: card.lesson_id.clone(),
+                lesson_title: card.lesson_title.clone(),
+                learners: counts.learners,
+                suspended: counts.suspended,
+                lapses: counts.lapses,
+            })
+        })
+        .collect::<Vec<_>>();
+    summaries.sort_by(|a, b| {
+        b.learners
+            .cmp(&a.learners)
+            .then_with(|| b.lapses.cmp(&a.lapses))
+            .then_with(|| a.id.cmp(&b.id))
+    });
+    summaries
+}
+
+pub async fn load_flags(db: Db, user_id: i64) -> Result<HashMap<String, CardFlags>, String> {
+    task::spawn_blocking(move || {
+        let conn = db.read()?;
+        let flags = {
+            let mut stmt = conn
+                .prepare("SELECT card_id, suspended, buried_until FROM flashcard_flags WHERE user_id = ?1")
+                .map_err(|err| format!("Failed to prepare statement: {err}"))?;
+            let rows = stmt
+                .query_map([user_id], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, bool>(1)?,
+                        row.get::<_, Option<String>>(2)?,
+                    ))
+                })
+                .map_err(|err| format!("Failed to query card flags: {err}"))?;
+
+            let mut flags = HashMap::new();
+            for row in rows {
+                let (card_id, suspended, buried_until) =
+                    row.map_err(|err| format!("Failed to read row: {err}"))?;
+                flags.insert(
+                    card_id,
+                    CardFlags {
+                        suspended,
+                        buried_until: buried_until
+                            .and_then(|until| DateTime::parse_from_rfc3339(&until).ok())
+                            .map(|until| until.with_timezone(&Utc)),
+                    },
+                );
+            }
+            flags
+        };
+        drop(conn);
+
+        Ok(flags)
+    })
+    .await
+    .map_err(|_| "Failed to join blocking task".to_string())?
+}
+
+pub async fn set_suspended(
+    db: Db,
+    user_id: i64,
+    card_ids: Vec<String>,
+    suspended: bool,
+) -> Result<(), String> {
+    task::spawn_blocking(move || {
+        let mut conn = db.write()?;
+        let tx = conn
+            .transaction()
+            .map_err(|err| format!("Failed to start transaction: {err}"))?;
+        for card_id in &card_ids {
+            tx.execute(
+                "
+                INSERT INTO flashcard_flags (user_id, card_id, suspended)
+                VALUES (?1, ?2, ?3)
+                ON CONFLICT(user_id, card_id) DO UPDATE SET suspended = excluded.suspended
+                ",
+                params![user_id, card_id, suspended],
+            )
+            .map_err(|err| format!("Failed to save card flags: {err}"))?;
+        }
+        tx.commit()
+            .map_err(|err| format!("Failed to commit card flags: {err}"))?;
+        Ok(())
+    })
+    .await
+    .map_err(|_| "Failed to join blocking task".to_string())?
+}
+
+/// Hides the cards until `until`; `None` unburies them.
+pub async fn set_buried(
+    db: Db,
+    user_id: i64,
+    card_ids: Vec<String>,
+    until: Option<DateTime<Utc>>,
+) -> Result<(), String> {
+    let until = until.map(|until| until.to_rfc3339());
+    task::spawn_blocking(move || {
+        let mut conn = db.write()?;
+        let tx = conn
+            .transaction()
+            .map_err(|err| format!("Failed to start transaction: {err}"))?;
+        for card_id in &card_ids {
+            tx.execute(
+                "
+                INSERT INTO flashcard_flags (user_id, card_id, buried_until)
+                VALUES (?1, ?2, ?3)
+                ON CONFLICT(user_id, card_id) DO UPDATE SET buried_until = excluded.buried_until
+                ",
+                params![user_id, card_id, until],
+            )
+            .map_err(|err| format!("Failed to save card flags: {err}"))?;
+        }
+        tx.commit()
+            .map_err(|err| format!("Failed to commit card flags: {err}"))?;
+        Ok(())
+    })
+    .await
+    .map_err(|_| "Failed to join blocking task".to_string())?
+}
+
+/// Lapses per card, counted from the review log: failed reviews of cards
+/// that had graduated. Undone reviews are gone from the log, so they no
+/// longer count.
+pub async fn load_lapse_counts(db: Db, user_id: i64) -> Result<HashMap<String, usize>, String> {
+    task::spawn_blocking(move || {
+        let conn = db.read()?;
+        let counts = {
+            let mut stmt = conn
+                .prepare(
+                    "
+                    SELECT card_id, COUNT(*)
+                    FROM fsrs_review_log
+                    WHERE user_id = ?1 AND state_before = 'review' AND rating = 1
+                    GROUP BY card_id
+                    ",
+                )
+                .map_err(|err| format!("Failed to prepare statement: {err}"))?;
+            let rows = stmt
+                .query_map([user_id], |row| Ok((row.get::<_, String>(0)?, row.get::<_, usize>(1)?)))
+                .map_err(|err| format!("Failed to query lapses: {err}"))?;
+            rows.collect::<Result<HashMap<_, _>, _>>()
+                .map_err(|err| format!("Failed to read row: {err}"))?
+        };
+        drop(conn);
+
+        Ok(counts)
+    })
+    .await
+    .map_err(|_| "Failed to join blocking task".to_string())?
+}
+
+pub fn count_lapses(conn: &Connection, user_id: i64, card_id: &str) -> Result<usize, String> {
+    conn.query_row(
+        "
+        SELECT COUNT(*) FROM fsrs_review_log
+        WHERE user_id = ?1 AND card_id = ?2 AND state_before = 'review' AND rating = 1
+        ",
+        params![user_id, card_id],
+        |row| row.get::<_, usize>(0),
+    )
+    .map_err(|err| format!("Failed to count lapses: {err}"))
+}
+
+/// Per card, the learners it is a leech for under their own
+/// `leech_threshold`, with their suspensions and lapses.
+pub async fn load_leech_counts(db: Db) -> Result<HashMap<String, LeechCounts>, String> {
+    task::spawn_blocking(move || {
+        let conn = db.read()?;
+        let mut stmt = conn
+            .prepare(&format!(
+                "
+                SELECT lapses.card_id, COUNT(*), COALESCE(SUM(flags.suspended), 0), SUM(lapses.count)
+                FROM (
+                    SELECT user_id, card_id, COUNT(*) AS count
+                    FROM fsrs_review_log
+                    WHERE state_before = 'review' AND rating = 1
+                    GROUP BY user_id, card_id
+                ) AS lapses
+                LEFT JOIN fsrs_settings AS threshold
+                    ON threshold.user_id = lapses.user_id AND threshold.key = ?1
+                LEFT JOIN flashcard_flags AS flags
+                    ON flags.user_id = lapses.user_id AND flags.card_id = lapses.card_id
+                WHERE lapses.count >= COALESCE(CAST(threshold.value AS INTEGER), {DEFAULT_LEECH_THRESHOLD})
+                GROUP BY lapses.card_id
+                "
+            ))
+            .map_err(|err| format!("Failed to prepare statement: {err}"))?;
+        let rows = stmt
+            .query_map([SETTINGS_KEY_LEECH_THRESHOLD], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    LeechCounts {
+                        learners: row.get(1)?,
+                        suspended: row.get(2)?,
+                        lapses: row.get(3)?,
+                    },
+                ))
+            })
+            .map_err(|err| format!("Failed to query leeches: {err}"))?;
+        rows.collect::<Result<HashMap<_, _>, _>>()
+            .map_err(|err| format!("Failed to read row: {err}"))
+    })
+    .await
+    .map_err(|_| "Failed to join blocking task".to_string())?
+}
@@ -0,0 +1,381 @@
+use chrono::{DateTime, Duration, Utc};
+use fsrs::{MemoryState, FSRS};
+use rusqlite::{params, OptionalExtension, TransactionBehavior};
+use serde::Serialize;
+use std::collections::HashMap;
+use tokio::task;
+
+use crate::core::db::Db;
+use super::{
+    anki::{AnkiCard, AnkiCollection},
+    scheduler::StudyClock, CardState, CardTemplate, VocabularyCard,
+};
+
+/// How many unmatched note fronts to echo back so the learner can see what
+/// was left out.
+const UNMATCHED_SAMPLE: usize = 20;
+
+#[derive(Serialize, Default)]
+pub struct ImportReport {
+    pub cards_imported: usize,
+    pub reviews_imported: usize,
+    /// Matched cards that already had avvai history, which is kept.
+    pub skipped_existing: usize,
+    /// Matched cards Anki had never shown.
+    pub skipped_new: usize,
+    pub unmatched: usize,
+    pub unmatched_examples: Vec<String>,
+    /// Every note with cards left out, with how many and why.
+    pub skipped_notes: Vec<SkippedNote>,
+    #[serde(skip)]
+    skipped_index: HashMap<(i64, SkipReason), usize>,
+}
+
+#[derive(Serialize)]
+pub struct SkippedNote {
+    pub note: String,
+    pub reason: SkipReason,
+    pub cards: usize,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// No avvai card has the note's id or word.
+    Unmatched,
+    /// Only the first card of a note matched by its word is imported.
+    ExtraCard,
+    /// Anki had never shown the card, or it has no usable schedule.
+    NeverStudied,
+    /// The avvai card already has history, which is kept.
+    ExistingHistory,
+}
+
+impl ImportReport {
+    fn skip(&mut self, note_id: i64, note: &str, reason: SkipReason) {
+        match self.skipped_index.get(&(note_id, reason)) {
+            Some(&index) => self.skipped_notes[index].cards += 1,
+            None => {
+                self.skipped_index.insert((note_id, reason), self.skipped_notes.len());
+                self.skipped_notes.push(SkippedNote {
+                    note: note.to_string(),
+                    reason,
+                    cards: 1,
+                });
+            }
+        }
+    }
+}
+
+/// A matched Anki card turned into avvai rows.
+struct ImportedCard {
+    card_id: String,
+    stability: f32,
+    difficulty: f32,
+    last_review: Option<DateTime<Utc>>,
+    due_date: DateTime<Utc>,
+    interval_days: i64,
+    state: CardState,
+    log: Vec<ImportedReview>,
+}
+
+struct ImportedReview {
+    rating: u32,
+    reviewed_at: DateTime<Utc>,
+    elapsed_days: u32,
+    before: Option<MemoryState>,
+    after: MemoryState,
+    last_review_before: Option<DateTime<Utc>>,
+    due_before: Option<DateTime<Utc>>,
+    interval_before: Option<i64>,
+    interval_days: i64,
+    due_date: DateTime<Utc>,
+    state_before: CardState,
+    state_after: CardState,
+}
+
+/// Matches Anki cards to avvai cards and replays their review history.
+///
+/// Cards exported from avvai carry their card id. Other notes are matched on
+/// their first field against vocabulary words, and only their first card
+/// (Anki's front→back) is taken, landing on the recognition card. The review
+/// log is replayed through FSRS with the current parameters so every
+/// imported log row has before/after memory states; the card's final memory
+/// state is Anki's own when it used FSRS.
+pub fn plan_import(
+    collection: &AnkiCollection,
+    cards: &[VocabularyCard],
+    fsrs: &FSRS,
+    desired_retention: f32,
+    clock: StudyClock,
+    now: DateTime<Utc>,
+) -> (Vec<ImportedCardPlan>, ImportReport) {
+    let known = cards
+        .iter()
+        .map(|card| card.card.id.as_str())
+        .collect::<std::collections::HashSet<_>>();
+    let by_word = cards
+        .iter()
+        .filter(|card| card.card.template == CardTemplate::Recognition)
+        .map(|card| (normalise(&card.card.front), card.card.id.as_str()))
+        .collect::<HashMap<_, _>>();
+
+    let mut report = ImportReport::default();
+    let mut plans = Vec::new();
+    for anki_card in &collection.cards {
+        let target = match &anki_card.avvai_id {
+            Some(id) => known.get(id.as_str()).copied(),
+            None if anki_card.ord == 0 => by_word.get(&normalise(&anki_card.front)).copied(),
+            None => {
+                report.skip(anki_card.note_id, &anki_card.front, SkipReason::ExtraCard);
+                continue;
+            }
+        };
+        let Some(card_id) = target else {
+            report.skip(anki_card.note_id, &anki_card.front, SkipReason::Unmatched);
+            report.unmatched += 1;
+            if report.unmatched_examples.len() < UNMATCHED_SAMPLE {
+                report
+                    .unmatched_examples
+                    .push(anki_card.avvai_id.clone().unwrap_or_else(|| anki_card.front.clone()));
+            }
+            continue;
+        };
+
+        match replay(collection, anki_card, card_id, fsrs, desired_retention, clock, now) {
+            Some(imported) => plans.push(ImportedCardPlan {
+                card: imported,
+                note_id: anki_card.note_id,
+                note: anki_card.front.clone(),
+            }),
+            None => {
+                report.skip(anki_card.note_id, &anki_card.front, SkipReason::NeverStudied);
+                report.skipped_new += 1;
+            }
+        }
+    }
+
+    (plans, report)
+}
+
+/// An imported card waiting to be written, with the note it came from.
+pub struct ImportedCardPlan {
+    card: ImportedCard,
+    note_id: i64,
+    note: String,
+}
+
+fn normalise(word: &str) -> String {
+    word.trim().to_lowercase()
+}
+
+fn state_for_kind(kind: i64, first: bool) -> CardState {
+    match kind {
+        0 if first => CardState::New,
+        0 => CardState::Learning,
+        2 => CardState::Relearning,
+        _ => CardState::Review,
+    }
+}
+
+fn state_for_type(card_type: i64) -> CardState {
+    match card_type {
+        1 => CardState::Learning,
+        3 => CardState::Relearning,
+        _ => CardState::Review,
+    }
+}
+
+fn pick(next: fsrs::NextStates, rating: u32) -> MemoryState {
+    match rating {
+        1 => next.again.memory,
+        2 => next.hard.memory,
+        4 => next.easy.memory,
+        _ => next.good.memory,
+    }
+}
+
+fn replay(
+    collection: &AnkiCollection,
+    anki_card: &AnkiCard,
+    card_id: &str,
+    fsrs: &FSRS,
+    desired_retention: f32,
+    clock: StudyClock,
+    now: DateTime<Utc>,
+) -> Option<ImportedCard> {
+    if anki_card.card_type == 0 {
+        return None;
+    }
+
+    // Filtered-deck, manual and rescheduling entries are not real recalls.
+    let reviews = anki_card
+        .reviews
+        .iter()
+        .filter(|review| (1..=4).contains(&review.ease) && (0..=2).contains(&review.kind))
+        .collect::<Vec<_>>();
+
+    let mut log = Vec::new();
+    let mut memory: Option<MemoryState> = None;
+    let mut previous: Option<(DateTime<Utc>, DateTime<Utc>, i64)> = None;
+    for (index, review) in reviews.iter().enumerate() {
+        let elapsed_days = previous.map_or(0, |(at, _, _)| clock.elapsed_days(at, review.at));
+        let Ok(next) = fsrs.next_states(memory, desired_retention, elapsed_days) else {
+            continue;
+        };
+        let after = pick(next, review.ease);
+        let interval_days = review.ivl.max(0);
+        let due_date = if review.ivl >= 0 {
+            review.at + Duration::days(review.ivl)
+        } else {
+            review.at + Duration::seconds(-review.ivl)
+        };
+        let state_after = reviews.get(index + 1).map_or_else(
+            || state_for_type(anki_card.card_type),
+            |next| state_for_kind(next.kind, false),
+        );
+
+        log.push(ImportedReview {
+            rating: review.ease,
+            reviewed_at: review.at,
+            elapsed_days,
+            before: memory,
+            after,
+            last_review_before: previous.map(|(at, _, _)| at),
+            due_before: previous.map(|(_, due, _)| due),
+            interval_before: previous.map(|(_, _, interval)| interval),
+            interval_days,
+            due_date,
+            state_before: state_for_kind(review.kind, index == 0),
+            state_after,
+        });
+        memory = Some(after);
+        previous = Some((review.at, due_date, interval_days));
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    let final_memory = anki_card
+        .memory
+        .map(|(stability, difficulty)| MemoryState {
+            stability,
+            difficulty,
+        })
+        .or(memory)
+        .or_else(|| {
+            fsrs.memory_state_from_sm2(
+                anki_card.factor as f32 / 1000.0,
+                anki_card.ivl.max(1) as f32,
+                desired_retention,
+            )
+            .ok()
+        })?;
+
+    Some(ImportedCard {
+        card_id: card_id.to_string(),
+        stability: final_memory.stability,
+        difficulty: final_memory.difficulty,
+        last_review: log.last().map(|review| review.reviewed_at),
+        due_date: collection.due_date(anki_card, clock).unwrap_or(now),
+        interval_days: if anki_card.card_type == 2 {
+            anki_card.ivl.max(0)
+        } else {
+            0
+        },
+        state: state_for_type(anki_card.card_type),
+        log,
+    })
+}
+
+/// Writes the planned cards in one transaction. Cards that already have an
+/// avvai state are skipped so existing history is never overwritten.
+pub async fn apply_import(
+    db: Db,
+    user_id: i64,
+    plans: Vec<ImportedCardPlan>,
+    mut report: ImportReport,
+) -> Result<ImportReport, String> {
+    task::spawn_blocking(move || {
+        let mut conn = db.write()?;
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(|err| format!("Failed to start transaction: {err}"))?;
+        let db_err = |err: rusqlite::Error| format!("Failed to import card: {err}");
+
+        for ImportedCardPlan { card, note_id, note } in plans {
+            let exists = tx
+                .query_row(
+                    "SELECT 1 FROM fsrs_cards WHERE user_id = ?1 AND card_id = ?2",
+                    params![user_id, card.card_id],
+                    |_| Ok(()),
+                )
+                .optional()
+                .map_err(db_err)?
+                .is_some();
+            if exists {
+                report.skip(note_id, &note, SkipReason::ExistingHistory);
+                report.skipped_existing += 1;
+                continue;
+            }
+
+            tx.execute(
+                "
+                INSERT INTO fsrs_cards (user_id, card_id, stability, difficulty, last_review, due_date, interval_days, state, step)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, 0)
+                ",
+                params![
+                    user_id,
+                    card.card_id,
+                    card.stability,
+                    card.difficulty,
+                    card.last_review.map(|at| at.to_rfc3339()),
+                    card.due_date.to_rfc3339(),
+                    card.interval_days,
+                    card.state.as_str(),
+                ],
+            )
+            .map_err(db_err)?;
+
+            for review in &card.log {
+                tx.execute(
+                    "
+                    INSERT INTO fsrs_review_log (
+                        card_id, rating, reviewed_at, elapsed_days,
+                        stability_before, difficulty_before, stability_after, difficulty_after,
+                        last_review_before, due_before, interval_before, interval_days, due_date,
+                        state_before, step_before, state_after, user_id
+                    )
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, NULL, ?15, ?16)
+                    ",
+                    params![
+                        card.card_id,
+                        review.rating,
+                        review.reviewed_at.to_rfc3339(),
+                        review.elapsed_days,
+                        review.before.map(|memory| memory.stability),
+                        review.before.map(|memory| memory.difficulty),
+                        review.after.stability,
+                        review.after.difficulty,
+                        review.last_review_before.map(|at| at.to_rfc3339()),
+                        review.due_before.map(|at| at.to_rfc3339()),
+                        review.interval_before,
+                        review.interval_days,
+                        review.due_date.to_rfc3339(),
+                        review.state_before.as_str(),
+                        review.state_after.as_str(),
+                        user_id,
+                    ],
+                )
+                .map_err(db_err)?;
+            }
+
+            report.cards_imported += 1;
+            report.reviews_imported += card.log.len();
+        }
+
+        tx.commit()
+            .map_err(|err| format!("Failed to commit import: {err}"))?;
+        Ok(report)
+    })
+    .await
+    .map_err(|_| "Failed to join blocking task".to_string())?
+}
@@ -0,0 +1,1730 @@
+use chrono::{DateTime, Duration, Utc};
+use fsrs::{FSRS, MemoryState, DEFAULT_PARAMETERS};
+use rusqlite::{params, Connection, OptionalExtension, Transaction, TransactionBehavior};
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+use tokio::task;
+
+use crate::core::db::Db;
+use crate::core::lesson::{self, ContentSection, Lesson};
+use crate::core::media;
+
+mod anki;
+mod cloze;
+mod decks;
+mod export;
+mod flags;
+mod import;
+mod optimiser;
+mod queue;
+mod rekey;
+mod reschedule;
+mod scheduler;
+mod simulation;
+mod stats;
+mod templates;
+mod undo;
+
+pub use decks::{Deck, NewDeck};
+pub use export::{ExportFile, ExportFormat};
+pub use flags::{Leech, LeechAction, LeechSummary};
+pub use import::ImportReport;
+pub use optimiser::{last_optimisation, optimise_parameters};
+pub use queue::DueSummary;
+pub use rekey::OrphanedCard;
+pub use reschedule::RescheduleReport;
+pub use scheduler::CardState;
+pub use simulation::{Simulation, SimulationRequest};
+pub use stats::FlashcardStats;
+pub use templates::CardTemplate;
+pub use undo::UndoneReview;
+
+use queue::{DailyLimits, DailyProgress, DueOrder, DueQueue};
+use scheduler::{CardPosition, StudyClock, LEARN_AHEAD_MINUTES};
+
+const DEFAULT_DESIRED_RETENTION: f32 = 0.9;
+const SETTINGS_KEY_RETENTION: &str = "desired_retention";
+const SETTINGS_KEY_PARAMETERS: &str = "parameters";
+const SETTINGS_KEY_LEARNING_STEPS: &str = "learning_steps";
+const SETTINGS_KEY_RELEARNING_STEPS: &str = "relearning_steps";
+const SETTINGS_KEY_NEW_PER_DAY: &str = "new_cards_per_day";
+const SETTINGS_KEY_REVIEWS_PER_DAY: &str = "reviews_per_day";
+const SETTINGS_KEY_TEMPLATES: &str = "enabled_templates";
+const SETTINGS_KEY_LEECH_THRESHOLD: &str = "leech_threshold";
+const SETTINGS_KEY_LEECH_ACTION: &str = "leech_action";
+const SETTINGS_KEY_UTC_OFFSET: &str = "utc_offset_minutes";
+const DEFAULT_NEW_PER_DAY: usize = 20;
+const DEFAULT_REVIEWS_PER_DAY: usize = 200;
+const MAX_DAILY_LIMIT: usize = 9999;
+const DEFAULT_LEARNING_STEPS: &[u32] = &[1, 10];
+const DEFAULT_RELEARNING_STEPS: &[u32] = &[10];
+const MAX_STEPS: usize = 10;
+const MAX_STEP_MINUTES: u32 = 24 * 60;
+const DEFAULT_LIMIT: usize = 30;
+const DEFAULT_HISTORY_LIMIT: usize = 50;
+const MAX_HISTORY_LIMIT: usize = 500;
+
+#[derive(Clone)]
+pub struct FlashcardsState {
+    pub db: Db,
+    /// Learners with an optimisation run in progress.
+    optimising: Arc<Mutex<HashSet<i64>>>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct Flashcard {
+    pub id: String,
+    pub template: CardTemplate,
+    /// Empty for `audio` cards, whose prompt is `audio_url`.
+    pub front: String,
+    pub back: String,
+    /// Shown on the front to narrow the answer, e.g. the meaning on cloze
+    /// cards.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub romanisation: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audio_url: Option<String>,
+    pub state: CardState,
+    /// Set once the card has lapsed `leech_threshold` times.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub leech: bool,
+}
+
+/// A vocabulary card together with where it came from, which is what decks
+/// filter on.
+struct VocabularyCard {
+    card: Flashcard,
+    lesson_id: String,
+    lesson_title: String,
+    /// Title of the lesson's source text; lessons sharing one form a course.
+    course: Option<String>,
+    tags: Vec<String>,
+    /// The entry's audio file name, bundled as media in `.apkg` exports.
+    audio: Option<String>,
+    /// The entry's lemma, or the word itself when no lemma is given.
+    lemma: String,
+    /// Position-based id (`{lesson}:vocab:{index}`) used before entries had
+    /// stable ids; only needed to re-key old recognition history.
+    legacy_id: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ReviewResult {
+    pub due_date: String,
+    /// Zero while the card is in a (re)learning step.
+    pub interval_days: i64,
+    pub state: CardState,
+    pub leech: bool,
+    /// True when this review made the card a leech and the leech action
+    /// suspended it.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub suspended: bool,
+}
+
+#[derive(Serialize)]
+pub struct FlashcardSettings {
+    /// The deck these settings were resolved for, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deck: Option<String>,
+    pub desired_retention: f32,
+    /// Delays in minutes between the steps a new card goes through before
+    /// graduating to day-based review.
+    pub learning_steps: Vec<u32>,
+    /// Delays in minutes for a review card that was forgotten.
+    pub relearning_steps: Vec<u32>,
+    pub new_cards_per_day: usize,
+    pub reviews_per_day: usize,
+    /// Templates whose cards are shown; cards of other templates keep their
+    /// schedule but are left out of the queue.
+    pub templates: Vec<CardTemplate>,
+    /// Lapses after which a card counts as a leech.
+    pub leech_threshold: usize,
+    pub leech_action: LeechAction,
+    /// The learner's offset from UTC, which decides when their study day
+    /// rolls over.
+    pub utc_offset_minutes: i32,
+}
+
+impl FlashcardSettings {
+    pub fn clock(&self) -> StudyClock {
+        StudyClock::new(self.utc_offset_minutes)
+    }
+}
+
+pub struct SettingsUpdate {
+    /// When set, only `desired_retention` may be given and it is stored as
+    /// an override for that deck.
+    pub deck: Option<String>,
+    pub desired_retention: Option<f32>,
+    pub learning_steps: Option<Vec<u32>>,
+    pub relearning_steps: Option<Vec<u32>>,
+    pub new_cards_per_day: Option<usize>,
+    pub reviews_per_day: Option<usize>,
+    pub templates: Option<Vec<CardTemplate>>,
+    pub leech_threshold: Option<usize>,
+    pub leech_action: Option<LeechAction>,
+    pub utc_offset_minutes: Option<i32>,
+}
+
+/// Query options for building today's queue.
+pub struct DueOptions {
+    pub deck: Option<String>,
+    pub limit: Option<usize>,
+    pub order: Option<String>,
+    pub seed: Option<u64>,
+}
+
+/// One row of `fsrs_review_log`. The log is append-only apart from undo,
+/// which removes the rows it takes back: every rating is recorded together
+/// with the memory state before and after it.
+#[derive(Serialize)]
+pub struct ReviewLogEntry {
+    pub id: i64,
+    pub card_id: String,
+    pub rating: u32,
+    pub reviewed_at: String,
+    pub elapsed_days: u32,
+    pub stability_before: Option<f32>,
+    pub difficulty_before: Option<f32>,
+    pub stability_after: f32,
+    pub difficulty_after: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state_before: Option<CardState>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state_after: Option<CardState>,
+    pub interval_days: i64,
+    pub due_date: String,
+}
+
+#[derive(Serialize)]
+pub struct ReviewHistoryPage {
+    pub entries: Vec<ReviewLogEntry>,
+    /// Pass as `before` to fetch the next (older) page; absent on the last page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_before: Option<i64>,
+}
+
+#[derive(Clone)]
+struct StoredState {
+    stability: f32,
+    difficulty: f32,
+    last_review: Option<DateTime<Utc>>,
+    due_date: Option<DateTime<Utc>>,
+    interval_days: i64,
+    state: CardState,
+    step: usize,
+}
+
+impl StoredState {
+    /// Whether the card should be shown at `now`. Cards in a learning step
+    /// are pulled forward by the learn-ahead window.
+    fn is_due(&self, now: DateTime<Utc>) -> bool {
+        let cutoff = if self.state.is_learning() {
+            now + Duration::minutes(LEARN_AHEAD_MINUTES)
+        } else {
+            now
+        };
+        self.due_date.is_none_or(|due| due <= cutoff)
+    }
+}
+
+/// Everything needed to persist one review: the new card state plus the log
+/// row describing the transition.
+struct ReviewRecord {
+    card_id: String,
+    session_id: Option<String>,
+    rating: u32,
+    elapsed_days: u32,
+    previous: Option<StoredState>,
+    memory: MemoryState,
+    state: CardState,
+    step: usize,
+    reviewed_at: DateTime<Utc>,
+    due_date: DateTime<Utc>,
+    interval_days: i64,
+    /// The review made the card a leech and the leech action suspends it.
+    suspend: bool,
+}
+
+#[derive(Debug)]
+pub enum FlashcardsError {
+    BadRequest(String),
+    NotFound(String),
+    Conflict(String),
+    Internal(String),
+}
+
+impl FlashcardsError {
+    pub fn message(&self) -> String {
+        match self {
+            Self::BadRequest(msg)
+            | Self::NotFound(msg)
+            | Self::Conflict(msg)
+            | Self::Internal(msg) => msg.clone(),
+        }
+    }
+}
+
+/// Builds the state and moves any review history still keyed on
+/// position-based card ids over to the stable ids.
+pub fn init_state(db: Db) -> FlashcardsState {
+    let state = FlashcardsState {
+        db,
+        optimising: Arc::new(Mutex::new(HashSet::new())),
+    };
+    match load_vocabulary_cards().and_then(|cards| rekey::rekey_legacy_cards(&state.db, &cards)) {
+        Ok(0) => {}
+        Ok(count) => println!("Re-keyed {count} flashcard(s) to stable vocabulary ids"),
+        Err(err) => eprintln!("Failed to re-key legacy flashcards: {err}"),
+    }
+    state
+}
+
+pub async fn get_due(
+    state: &FlashcardsState,
+    user_id: i64,
+    options: &DueOptions,
+) -> Result<Vec<Flashcard>, FlashcardsError> {
+    let limit = options.limit.unwrap_or(DEFAULT_LIMIT);
+    let mut queue = build_due_queue(state, user_id, options).await?;
+    queue.cards.truncate(limit);
+    Ok(queue.cards)
+}
+
+/// How many cards are left for today in each pile, honouring the daily
+/// limits.
+pub async fn get_summary(
+    state: &FlashcardsState,
+    user_id: i64,
+    options: &DueOptions,
+) -> Result<DueSummary, FlashcardsError> {
+    Ok(build_due_queue(state, user_id, options).await?.summary)
+}
+
+async fn build_due_queue(
+    state: &FlashcardsState,
+    user_id: i64,
+    options: &DueOptions,
+) -> Result<DueQueue, FlashcardsError> {
+    let now = Utc::now();
+    let settings = get_settings(state, user_id, None).await?;
+    let clock = settings.clock();
+    let order = DueOrder::parse(options.order.as_deref(), options.seed, clock.study_day(now))
+        .map_err(FlashcardsError::BadRequest)?;
+
+    let mut cards = load_vocabulary_cards().map_err(FlashcardsError::Internal)?;
+    cards.retain(|card| settings.templates.contains(&card.card.template));
+    if let Some(deck_id) = options.deck.as_deref() {
+        let deck = decks::find_deck(state, user_id, &cards, deck_id).await?;
+        cards.retain(|card| deck.contains(card));
+    }
+
+    let flags = flags::load_flags(state.db.clone(), user_id)
+        .await
+        .map_err(FlashcardsError::Internal)?;
+    let lapses = flags::load_lapse_counts(state.db.clone(), user_id)
+        .await
+        .map_err(FlashcardsError::Internal)?;
+    let cards = cards
+        .into_iter()
+        .filter(|card| !flags.get(&card.card.id).is_some_and(|flags| flags.is_hidden(now)))
+        .map(|card| Flashcard {
+            leech: lapses.get(&card.card.id).is_some_and(|count| *count >= settings.leech_threshold),
+            ..card.card
+        })
+        .collect();
+
+    let stored_states = load_states(state.db.clone(), user_id)
+        .await
+        .map_err(FlashcardsError::Internal)?;
+    let progress = load_daily_progress(state.db.clone(), user_id, clock.day_start(now))
+        .await
+        .map_err(FlashcardsError::Internal)?;
+
+    let limits = DailyLimits {
+        new_per_day: settings.new_cards_per_day,
+        reviews_per_day: settings.reviews_per_day,
+    };
+    Ok(queue::build_queue(
+        cards,
+        &stored_states,
+        &limits,
+        &progress,
+        order,
+        now,
+    ))
+}
+
+/// Rates a card. When the review happens inside a deck, that deck's desired
+/// retention (if overridden) is used to schedule it. Reviews tagged with a
+/// session id can later be undone per session.
+pub async fn review_card(
+    state: &FlashcardsState,
+    user_id: i64,
+    card_id: &str,
+    rating: u32,
+    deck: Option<&str>,
+    session_id: Option<String>,
+) -> Result<ReviewResult, FlashcardsError> {
+    if !(1..=4).contains(&rating) {
+        return Err(FlashcardsError::BadRequest(
+            "rating must be 1..=4".to_string(),
+        ));
+    }
+
+    let settings = get_settings(state, user_id, deck).await?;
+    let parameters = load_parameters(state.db.clone(), user_id)
+        .await
+        .map_err(FlashcardsError::Internal)?;
+
+    // The card's state and lapses are read and the review written in one
+    // immediate transaction, so a concurrent review of the same card waits
+    // for this one instead of scheduling from the same old state.
+    let db = state.db.clone();
+    let card_id = card_id.to_string();
+    task::spawn_blocking(move || {
+        let internal = FlashcardsError::Internal;
+        let fsrs = FSRS::new(Some(&parameters))
+            .map_err(|_| internal("failed to initialize FSRS".to_string()))?;
+
+        let mut conn = db.write().map_err(internal)?;
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(|err| internal(format!("Failed to start transaction: {err}")))?;
+        let now = Utc::now();
+
+        let stored_state = read_state(&tx, user_id, &card_id).map_err(internal)?;
+
+        let previous_memory = stored_state.as_ref().map(|state| MemoryState {
+            stability: state.stability,
+            difficulty: state.difficulty,
+        });
+
+        let elapsed_days = stored_state
+            .as_ref()
+            .and_then(|state| state.last_review)
+            .map_or(0, |last| settings.clock().elapsed_days(last, now));
+
+        let next_states = fsrs
+            .next_states(previous_memory, settings.desired_retention, elapsed_days)
+            .map_err(|_| FlashcardsError::BadRequest("invalid FSRS state".to_string()))?;
+
+        let position = stored_state.as_ref().map_or(
+            CardPosition {
+                state: CardState::New,
+                step: 0,
+            },
+            |stored| CardPosition {
+                state: stored.state,
+                step: stored.step,
+            },
+        );
+        let scheduled = scheduler::schedule(
+            &position,
+            rating,
+            next_states,
+            &settings.learning_steps,
+            &settings.relearning_steps,
+            now,
+        );
+
+        let lapsed = position.state == CardState::Review && rating == 1;
+        let lapses =
+            flags::count_lapses(&tx, user_id, &card_id).map_err(internal)? + usize::from(lapsed);
+        let leech = lapses >= settings.leech_threshold;
+
+        let suspended = write_review(
+            &tx,
+            user_id,
+            &ReviewRecord {
+                card_id,
+                session_id,
+                rating,
+                elapsed_days,
+                previous: stored_state,
+                memory: scheduled.item.memory,
+                state: scheduled.state,
+                step: scheduled.step,
+                reviewed_at: now,
+                due_date: scheduled.due,
+                interval_days: scheduled.interval_days,
+                suspend: lapsed && leech && settings.leech_action == LeechAction::Suspend,
+            },
+        )
+        .map_err(internal)?;
+        tx.commit()
+            .map_err(|err| internal(format!("Failed to commit review: {err}")))?;
+
+        Ok(ReviewResult {
+            due_date: scheduled.due.to_rfc3339(),
+            interval_days: scheduled.interval_days,
+            state: scheduled.state,
+            leech,
+            suspended,
+        })
+    })
+    .await
+    .map_err(|_| FlashcardsError::Internal("Failed to join blocking task".to_string()))?
+}
+
+pub async fn get_history(
+    state: &FlashcardsState,
+    user_id: i64,
+    card_id: &str,
+    limit: Option<usize>,
+    before: Option<i64>,
+) -> Result<ReviewHistoryPage, FlashcardsError> {
+    let limit = limit
+        .unwrap_or(DEFAULT_HISTORY_LIMIT)
+        .clamp(1, MAX_HISTORY_LIMIT);
+
+    let mut entries = load_history(state.db.clone(), user_id, card_id, limit + 1, before)
+        .await
+        .map_err(FlashcardsError::Internal)?;
+
+    let next_before = if entries.len() > limit {
+        entries.truncate(limit);
+        entries.last().map(|entry| entry.id)
+    } else {
+        None
+    };
+
+    Ok(ReviewHistoryPage {
+        entries,
+        next_before,
+    })
+}
+
+/// Scheduling settings, with the deck's retention override applied when a
+/// deck is given. Limits and steps are shared by every deck.
+pub async fn get_settings(
+    state: &FlashcardsState,
+    user_id: i64,
+    deck: Option<&str>,
+) -> Result<FlashcardSettings, FlashcardsError> {
+    let mut desired_retention = load_desired_retention(state.db.clone(), user_id)
+        .await
+        .map_err(FlashcardsError::Internal)?;
+    if let Some(deck_id) = deck {
+        let cards = load_vocabulary_cards().map_err(FlashcardsError::Internal)?;
+        let deck = decks::find_deck(state, user_id, &cards, deck_id).await?;
+        if let Some(retention) = deck.desired_retention {
+            desired_retention = retention;
+        }
+    }
+    let learning_steps = load_steps(
+        state.db.clone(),
+        user_id,
+        SETTINGS_KEY_LEARNING_STEPS,
+        DEFAULT_LEARNING_STEPS,
+    )
+    .await
+    .map_err(FlashcardsError::Internal)?;
+    let relearning_steps = load_steps(
+        state.db.clone(),
+        user_id,
+        SETTINGS_KEY_RELEARNING_STEPS,
+        DEFAULT_RELEARNING_STEPS,
+    )
+    .await
+    .map_err(FlashcardsError::Internal)?;
+
+    let new_cards_per_day = load_count(
+        state.db.clone(),
+        user_id,
+        SETTINGS_KEY_NEW_PER_DAY,
+        DEFAULT_NEW_PER_DAY,
+    )
+    .await
+    .map_err(FlashcardsError::Internal)?;
+    let reviews_per_day = load_count(
+        state.db.clone(),
+        user_id,
+        SETTINGS_KEY_REVIEWS_PER_DAY,
+        DEFAULT_REVIEWS_PER_DAY,
+    )
+    .await
+    .map_err(FlashcardsError::Internal)?;
+
+    let templates = load_templates(state.db.clone(), user_id)
+        .await
+        .map_err(FlashcardsError::Internal)?;
+
+    let leech_threshold = load_count(
+        state.db.clone(),
+        user_id,
+        SETTINGS_KEY_LEECH_THRESHOLD,
+        flags::DEFAULT_LEECH_THRESHOLD,
+    )
+    .await
+    .map_err(FlashcardsError::Internal)?;
+    let leech_action = load_leech_action(state.db.clone(), user_id)
+        .await
+        .map_err(FlashcardsError::Internal)?;
+    let utc_offset_minutes = load_utc_offset(state.db.clone(), user_id)
+        .await
+        .map_err(FlashcardsError::Internal)?;
+
+    Ok(FlashcardSettings {
+        deck: deck.map(str::to_string),
+        desired_retention,
+        learning_steps,
+        relearning_steps,
+        new_cards_per_day,
+        reviews_per_day,
+        templates,
+        leech_threshold,
+        leech_action,
+        utc_offset_minutes,
+    })
+}
+
+pub async fn update_settings(
+    state: &FlashcardsState,
+    user_id: i64,
+    update: SettingsUpdate,
+) -> Result<(), FlashcardsError> {
+    if let Some(desired_retention) = update.desired_retention
+        && !(0.7..=0.99).contains(&desired_retention)
+    {
+        return Err(FlashcardsError::BadRequest(
+            "desiredRetention must be between 0.7 and 0.99".to_string(),
+        ));
+    }
+    for (name, steps) in [
+        ("learningSteps", &update.learning_steps),
+        ("relearningSteps", &update.relearning_steps),
+    ] {
+        if let Some(steps) = steps {
+            validate_steps(name, steps)?;
+        }
+    }
+    for (name, count) in [
+        ("newCardsPerDay", update.new_cards_per_day),
+        ("reviewsPerDay", update.reviews_per_day),
+    ] {
+        if count.is_some_and(|count| count > MAX_DAILY_LIMIT) {
+            return Err(FlashcardsError::BadRequest(format!(
+                "{name} must be at most {MAX_DAILY_LIMIT}"
+            )));
+        }
+    }
+
+    if update
+        .templates
+        .as_ref()
+        .is_some_and(|templates| templates.is_empty())
+    {
+        return Err(FlashcardsError::BadRequest(
+            "templates must enable at least one template".to_string(),
+        ));
+    }
+
+    if update
+        .leech_threshold
+        .is_some_and(|threshold| !(1..=flags::MAX_LEECH_THRESHOLD).contains(&threshold))
+    {
+        return Err(FlashcardsError::BadRequest(format!(
+            "leechThreshold must be between 1 and {}",
+            flags::MAX_LEECH_THRESHOLD
+        )));
+    }
+
+    if update.utc_offset_minutes.is_some_and(|offset| {
+        !(scheduler::MIN_UTC_OFFSET_MINUTES..=scheduler::MAX_UTC_OFFSET_MINUTES).contains(&offset)
+    }) {
+        return Err(FlashcardsError::BadRequest(
+            "utcOffsetMinutes must be between -720 and 840".to_string(),
+        ));
+    }
+
+    if let Some(deck_id) = update.deck.as_deref() {
+        return update_deck_settings(state, user_id, deck_id, &update).await;
+    }
+
+    if let Some(desired_retention) = update.desired_retention {
+        save_desired_retention(state.db.clone(), user_id, desired_retention)
+            .await
+            .map_err(FlashcardsError::Internal)?;
+    }
+    if let Some(steps) = update.learning_steps {
+        save_steps(state.db.clone(), user_id, SETTINGS_KEY_LEARNING_STEPS, &steps)
+            .await
+            .map_err(FlashcardsError::Internal)?;
+    }
+    if let Some(steps) = update.relearning_steps {
+        save_steps(state.db.clone(), user_id, SETTINGS_KEY_RELEARNING_STEPS, &steps)
+            .await
+            .map_err(FlashcardsError::Internal)?;
+    }
+    if let Some(count) = update.new_cards_per_day {
+        save_setting(state.db.clone(), user_id, SETTINGS_KEY_NEW_PER_DAY, count.to_string())
+            .await
+            .map_err(FlashcardsError::Internal)?;
+    }
+    if let Some(count) = update.reviews_per_day {
+        save_setting(state.db.clone(), user_id, SETTINGS_KEY_REVIEWS_PER_DAY, count.to_string())
+            .await
+            .map_err(FlashcardsError::Internal)?;
+    }
+    if let Some(templates) = update.templates {
+        let value = serde_json::to_string(&templates)
+            .map_err(|err| FlashcardsError::Internal(err.to_string()))?;
+        save_setting(state.db.clone(), user_id, SETTINGS_KEY_TEMPLATES, value)
+            .await
+            .map_err(FlashcardsError::Internal)?;
+    }
+    if let Some(threshold) = update.leech_threshold {
+        save_setting(state.db.clone(), user_id, SETTINGS_KEY_LEECH_THRESHOLD, threshold.to_string())
+            .await
+            .map_err(FlashcardsError::Internal)?;
+    }
+    if let Some(action) = update.leech_action {
+        let value = serde_json::to_string(&action)
+            .map_err(|err| FlashcardsError::Internal(err.to_string()))?;
+        save_setting(state.db.clone(), user_id, SETTINGS_KEY_LEECH_ACTION, value)
+            .await
+            .map_err(FlashcardsError::Internal)?;
+    }
+    if let Some(offset) = update.utc_offset_minutes {
+        save_setting(state.db.clone(), user_id, SETTINGS_KEY_UTC_OFFSET, offset.to_string())
+            .await
+            .map_err(FlashcardsError::Internal)?;
+    }
+
+    Ok(())
+}
+
+async fn update_deck_settings(
+    state: &FlashcardsState,
+    user_id: i64,
+    deck_id: &str,
+    update: &SettingsUpdate,
+) -> Result<(), FlashcardsError> {
+    if update.learning_steps.is_some()
+        || update.relearning_steps.is_some()
+        || update.new_cards_per_day.is_some()
+        || update.reviews_per_day.is_some()
+        || update.templates.is_some()
+        || update.leech_threshold.is_some()
+        || update.leech_action.is_some()
+        || update.utc_offset_minutes.is_some()
+    {
+        return Err(FlashcardsError::BadRequest(
+            "Only desiredRetention can be set per deck".to_string(),
+        ));
+    }
+
+    let cards = load_vocabulary_cards().map_err(FlashcardsError::Internal)?;
+    decks::find_deck(state, user_id, &cards, deck_id).await?;
+
+    if let Some(desired_retention) = update.desired_retention {
+        decks::save_deck_retention(state.db.clone(), user_id, deck_id, desired_retention)
+            .await
+            .map_err(FlashcardsError::Internal)?;
+    }
+    Ok(())
+}
+
+/// Dashboard numbers for the enabled cards, optionally narrowed to a deck:
+/// counts by state, daily retention over the last `days` study days, a
+/// 30-day due forecast, average memory state and the hardest cards.
+pub async fn get_stats(
+    state: &FlashcardsState,
+    user_id: i64,
+    deck: Option<&str>,
+    days: Option<u32>,
+) -> Result<FlashcardStats, FlashcardsError> {
+    let days = days.unwrap_or(stats::DEFAULT_RETENTION_DAYS);
+    if !(1..=stats::MAX_RETENTION_DAYS).contains(&days) {
+        return Err(FlashcardsError::BadRequest(format!(
+            "days must be between 1 and {}",
+            stats::MAX_RETENTION_DAYS
+        )));
+    }
+
+    let mut cards = load_enabled_cards(state, user_id).await?;
+    if let Some(deck_id) = deck {
+        let deck = decks::find_deck(state, user_id, &cards, deck_id).await?;
+        cards.retain(|card| deck.contains(card));
+    }
+    let stored_states = load_states(state.db.clone(), user_id)
+        .await
+        .map_err(FlashcardsError::Internal)?;
+    let log = stats::load_log(state.db.clone(), user_id)
+        .await
+        .map_err(FlashcardsError::Internal)?;
+    let clock = load_study_clock(state.db.clone(), user_id).await?;
+
+    Ok(stats::compute(&cards, &stored_states, &log, days, clock, Utc::now()))
+}
+
+/// Takes back the latest `count` reviews (default 1) of a session, or of all
+/// reviews when no session is given.
+pub async fn undo_reviews(
+    state: &FlashcardsState,
+    user_id: i64,
+    session_id: Option<String>,
+    count: Option<usize>,
+) -> Result<Vec<UndoneReview>, FlashcardsError> {
+    let count = count.unwrap_or(undo::DEFAULT_UNDO_COUNT);
+    if !(1..=undo::MAX_UNDO_COUNT).contains(&count) {
+        return Err(FlashcardsError::BadRequest(format!(
+            "count must be between 1 and {}",
+            undo::MAX_UNDO_COUNT
+        )));
+    }
+    undo::undo_reviews(state.db.clone(), user_id, session_id, count).await
+}
+
+/// Card states that no longer match any vocabulary entry, usually because
+/// the entry was deleted or its lesson removed.
+pub async fn find_orphans(
+    state: &FlashcardsState,
+    user_id: i64,
+) -> Result<Vec<OrphanedCard>, FlashcardsError> {
+    let cards = load_vocabulary_cards().map_err(FlashcardsError::Internal)?;
+    rekey::find_orphans(state.db.clone(), user_id, &cards)
+        .await
+        .map_err(FlashcardsError::Internal)
+}
+
+pub async fn list_decks(
+    state: &FlashcardsState,
+    user_id: i64,
+) -> Result<Vec<Deck>, FlashcardsError> {
+    let cards = load_enabled_cards(state, user_id).await?;
+    decks::list_decks(state, user_id, &cards).await
+}
+
+pub async fn create_deck(
+    state: &FlashcardsState,
+    user_id: i64,
+    deck: NewDeck,
+) -> Result<Deck, FlashcardsError> {
+    let mut created = decks::create_deck(state, user_id, deck).await?;
+    let cards = load_enabled_cards(state, user_id).await?;
+    created.card_count = cards.iter().filter(|card| created.contains(card)).count();
+    Ok(created)
+}
+
+pub async fn delete_deck(
+    state: &FlashcardsState,
+    user_id: i64,
+    deck_id: &str,
+) -> Result<(), FlashcardsError> {
+    decks::delete_deck(state, user_id, deck_id).await
+}
+
+/// Exports the enabled cards, optionally narrowed to a deck, with their
+/// scheduling state. `.apkg` packages also carry the review history and
+/// audio so they open in Anki as-is.
+pub async fn export_collection(
+    state: &FlashcardsState,
+    user_id: i64,
+    deck: Option<&str>,
+    format: ExportFormat,
+) -> Result<ExportFile, FlashcardsError> {
+    let mut cards = load_enabled_cards(state, user_id).await?;
+    let mut name = "avvai-flashcards".to_string();
+    if let Some(deck_id) = deck {
+        let deck = decks::find_deck(state, user_id, &cards, deck_id).await?;
+        cards.retain(|card| deck.contains(card));
+        name = format!("{name}-{}", deck_id.replace(':', "-"));
+    }
+    let stored_states = load_states(state.db.clone(), user_id)
+        .await
+        .map_err(FlashcardsError::Internal)?;
+    let log = export::load_log_rows(state.db.clone(), user_id)
+        .await
+        .map_err(FlashcardsError::Internal)?;
+
+    let items = cards
+        .iter()
+        .map(|card| export::ExportItem {
+            card,
+            state: stored_states.get(&card.card.id),
+            log: log.get(&card.card.id).map_or(&[], Vec::as_slice),
+        })
+        .collect::<Vec<_>>();
+
+    let bytes = match format {
+        ExportFormat::Csv => export::to_csv(&items).into_bytes(),
+        ExportFormat::Apkg => {
+            let desired_retention = load_desired_retention(state.db.clone(), user_id)
+                .await
+                .map_err(FlashcardsError::Internal)?;
+            let clock = load_study_clock(state.db.clone(), user_id).await?;
+            anki::write_apkg(&items, desired_retention, clock, Utc::now())
+                .map_err(FlashcardsError::Internal)?
+        }
+    };
+
+    Ok(ExportFile {
+        filename: format!("{name}.{}", format.extension()),
+        content_type: format.content_type(),
+        bytes,
+    })
+}
+
+/// Imports scheduling state and review history from an Anki `.apkg`.
+/// Cards are matched by the avvai id carried in our own exports, or by the
+/// note's front against vocabulary words; cards with existing avvai history
+/// are left alone.
+pub async fn import_anki(
+    state: &FlashcardsState,
+    user_id: i64,
+    bytes: Vec<u8>,
+) -> Result<ImportReport, FlashcardsError> {
+    let collection = task::spawn_blocking(move || anki::read_apkg(&bytes))
+        .await
+        .map_err(|_| FlashcardsError::Internal("Failed to join blocking task".to_string()))?
+        .map_err(FlashcardsError::BadRequest)?;
+
+    let cards = load_vocabulary_cards().map_err(FlashcardsError::Internal)?;
+    let parameters = load_parameters(state.db.clone(), user_id)
+        .await
+        .map_err(FlashcardsError::Internal)?;
+    let fsrs = FSRS::new(Some(&parameters))
+        .map_err(|_| FlashcardsError::Internal("failed to initialize FSRS".to_string()))?;
+    let desired_retention = load_desired_retention(state.db.clone(), user_id)
+        .await
+        .map_err(FlashcardsError::Internal)?;
+
+    let clock = load_study_clock(state.db.clone(), user_id).await?;
+
+    let (plans, report) =
+        import::plan_import(&collection, &cards, &fsrs, desired_retention, clock, Utc::now());
+    import::apply_import(state.db.clone(), user_id, plans, report)
+        .await
+        .map_err(FlashcardsError::Internal)
+}
+
+/// Projects workload and retention for a candidate desired retention (and
+/// optionally candidate parameters) from the learner's current cards, using
+/// the current limits and steps. Nothing is saved.
+pub async fn simulate(
+    state: &FlashcardsState,
+    user_id: i64,
+    request: SimulationRequest,
+) -> Result<Simulation, FlashcardsError> {
+    if !(0.7..=0.99).contains(&request.desired_retention) {
+        return Err(FlashcardsError::BadRequest(
+            "desiredRetention must be between 0.7 and 0.99".to_string(),
+        ));
+    }
+    let days = request.days.unwrap_or(simulation::DEFAULT_SIMULATION_DAYS);
+    if !(1..=simulation::MAX_SIMULATION_DAYS).contains(&days) {
+        return Err(FlashcardsError::BadRequest(format!(
+            "days must be between 1 and {}",
+            simulation::MAX_SIMULATION_DAYS
+        )));
+    }
+
+    let settings = get_settings(state, user_id, request.deck.as_deref()).await?;
+    let mut cards = load_enabled_cards(state, user_id).await?;
+    if let Some(deck_id) = request.deck.as_deref() {
+        let deck = decks::find_deck(state, user_id, &cards, deck_id).await?;
+        cards.retain(|card| deck.contains(card));
+    }
+    let parameters = match request.parameters {
+        Some(parameters) => parameters,
+        None => load_parameters(state.db.clone(), user_id)
+            .await
+            .map_err(FlashcardsError::Internal)?,
+    };
+    let stored_states = load_states(state.db.clone(), user_id)
+        .await
+        .map_err(FlashcardsError::Internal)?;
+    let card_flags = flags::load_flags(state.db.clone(), user_id)
+        .await
+        .map_err(FlashcardsError::Internal)?;
+    let lapses = flags::load_lapse_counts(state.db.clone(), user_id)
+        .await
+        .map_err(FlashcardsError::Internal)?;
+
+    let now = Utc::now();
+    let (config, existing) =
+        simulation::build_input(&cards, &stored_states, &card_flags, &lapses, &settings, days, now);
+    if config.deck_size == 0 {
+        return Err(FlashcardsError::BadRequest(
+            "There are no cards to simulate".to_string(),
+        ));
+    }
+
+    let desired_retention = request.desired_retention;
+    let clock = settings.clock();
+    task::spawn_blocking(move || {
+        let result = fsrs::simulate(
+            &config,
+            &parameters,
+            desired_retention,
+            Some(simulation::SIMULATION_SEED),
+            Some(existing),
+        )
+        .map_err(|err| {
+            FlashcardsError::BadRequest(format!("Cannot simulate with these parameters: {err:?}"))
+        })?;
+        Ok(simulation::summarise(&result, &config, desired_retention, clock, now))
+    })
+    .await
+    .map_err(|_| FlashcardsError::Internal("Failed to join blocking task".to_string()))?
+}
+
+/// Recomputes the due dates of review cards from their review history under
+/// the current parameters and desired retention: the deck's when a deck is
+/// given, otherwise each card's deck override or the global one. A dry run
+/// only reports how many cards would move.
+pub async fn reschedule(
+    state: &FlashcardsState,
+    user_id: i64,
+    deck: Option<&str>,
+    dry_run: bool,
+) -> Result<RescheduleReport, FlashcardsError> {
+    let settings = get_settings(state, user_id, deck).await?;
+    let mut cards = load_vocabulary_cards().map_err(FlashcardsError::Internal)?;
+    // Inside a deck, its retention applies to every card; otherwise each
+    // card takes the override of a deck it belongs to, if any.
+    let overrides = if let Some(deck_id) = deck {
+        let deck = decks::find_deck(state, user_id, &cards, deck_id).await?;
+        cards.retain(|card| deck.contains(card));
+        Vec::new()
+    } else {
+        decks::list_decks(state, user_id, &cards)
+            .await?
+            .into_iter()
+            .filter(|deck| deck.desired_retention.is_some())
+            .collect()
+    };
+    let retention = reschedule::Retention {
+        default: settings.desired_retention,
+        overrides,
+    };
+    let parameters = load_parameters(state.db.clone(), user_id)
+        .await
+        .map_err(FlashcardsError::Internal)?;
+    let stored_states = load_states(state.db.clone(), user_id)
+        .await
+        .map_err(FlashcardsError::Internal)?;
+    let histories = reschedule::load_histories(state.db.clone(), user_id)
+        .await
+        .map_err(FlashcardsError::Internal)?;
+
+    let clock = settings.clock();
+    let (moves, mut report) = task::spawn_blocking(move || {
+        let fsrs = FSRS::new(Some(&parameters))
+            .map_err(|_| FlashcardsError::Internal("failed to initialize FSRS".to_string()))?;
+        Ok::<_, FlashcardsError>(reschedule::plan(
+            &cards,
+            &stored_states,
+            &histories,
+            &fsrs,
+            &retention,
+            clock,
+            dry_run,
+        ))
+    })
+    .await
+    .map_err(|_| FlashcardsError::Internal("Failed to join blocking task".to_string()))??;
+    if !dry_run {
+        let updated = reschedule::apply(state.db.clone(), user_id, moves)
+            .await
+            .map_err(FlashcardsError::Internal)?;
+        report.updated = Some(updated);
+    }
+    Ok(report)
+}
+
+/// Suspends or unsuspends cards. Suspended cards keep their schedule but
+/// stay out of the queue until unsuspended.
+pub async fn suspend_cards(
+    state: &FlashcardsState,
+    user_id: i64,
+    card_ids: Vec<String>,
+    suspended: bool,
+) -> Result<(), FlashcardsError> {
+    validate_card_ids(&card_ids)?;
+    flags::set_suspended(state.db.clone(), user_id, card_ids, suspended)
+        .await
+        .map_err(FlashcardsError::Internal)
+}
+
+/// Buries cards until the next study day starts, or unburies them.
+pub async fn bury_cards(
+    state: &FlashcardsState,
+    user_id: i64,
+    card_ids: Vec<String>,
+    buried: bool,
+) -> Result<(), FlashcardsError> {
+    validate_card_ids(&card_ids)?;
+    let clock = load_study_clock(state.db.clone(), user_id).await?;
+    let until = buried.then(|| clock.day_start(Utc::now()) + Duration::days(1));
+    flags::set_buried(state.db.clone(), user_id, card_ids, until)
+        .await
+        .map_err(FlashcardsError::Internal)
+}
+
+/// Cards that have lapsed at least `leech_threshold` times, optionally
+/// narrowed to a deck, including suspended ones.
+pub async fn get_leeches(
+    state: &FlashcardsState,
+    user_id: i64,
+    deck: Option<&str>,
+) -> Result<Vec<Leech>, FlashcardsError> {
+    let settings = get_settings(state, user_id, None).await?;
+    let mut cards = load_enabled_cards(state, user_id).await?;
+    if let Some(deck_id) = deck {
+        let deck = decks::find_deck(state, user_id, &cards, deck_id).await?;
+        cards.retain(|card| deck.contains(card));
+    }
+    let stored_states = load_states(state.db.clone(), user_id)
+        .await
+        .map_err(FlashcardsError::Internal)?;
+    let card_flags = flags::load_flags(state.db.clone(), user_id)
+        .await
+        .map_err(FlashcardsError::Internal)?;
+    let lapses = flags::load_lapse_counts(state.db.clone(), user_id)
+        .await
+        .map_err(FlashcardsError::Internal)?;
+
+    Ok(flags::leeches(
+        &cards,
+        &stored_states,
+        &card_flags,
+        &lapses,
+        settings.leech_threshold,
+    ))
+}
+
+/// Leeches across every learner, for the CMS.
+pub async fn get_leech_summaries(db: &Db) -> Result<Vec<LeechSummary>, FlashcardsError> {
+    let cards = load_vocabulary_cards().map_err(FlashcardsError::Internal)?;
+    let counts = flags::load_leech_counts(db.clone())
+        .await
+        .map_err(FlashcardsError::Internal)?;
+    Ok(flags::leech_summaries(&cards, &counts))
+}
+
+fn validate_card_ids(card_ids: &[String]) -> Result<(), FlashcardsError> {
+    if card_ids.is_empty() || card_ids.len() > flags::MAX_FLAG_CARDS {
+        return Err(FlashcardsError::BadRequest(format!(
+            "cardIds must list between 1 and {} cards",
+            flags::MAX_FLAG_CARDS
+        )));
+    }
+    let cards = load_vocabulary_cards().map_err(FlashcardsError::Internal)?;
+    if let Some(unknown) = card_ids
+        .iter()
+        .find(|card_id| !cards.iter().any(|card| &card.card.id == *card_id))
+    {
+        return Err(FlashcardsError::NotFound(format!("Unknown card {unknown}")));
+    }
+    Ok(())
+}
+
+fn validate_steps(name: &str, steps: &[u32]) -> Result<(), FlashcardsError> {
+    if steps.len() > MAX_STEPS {
+        return Err(FlashcardsError::BadRequest(format!(
+            "{name} accepts at most {MAX_STEPS} steps"
+        )));
+    }
+    if steps.iter().any(|minutes| !(1..=MAX_STEP_MINUTES).contains(minutes)) {
+        return Err(FlashcardsError::BadRequest(format!(
+            "{name} must be between 1 and {MAX_STEP_MINUTES} minutes"
+        )));
+    }
+    Ok(())
+}
+
+fn lessons_dir() -> PathBuf {
+    lesson::lessons_dir()
+}
+
+/// Cards of the templates the learner has enabled, so deck counts match what
+/// the queue will show.
+async fn load_enabled_cards(
+    state: &FlashcardsState,
+    user_id: i64,
+) -> Result<Vec<VocabularyCard>, FlashcardsError> {
+    let templates = load_templates(state.db.clone(), user_id)
+        .await
+        .map_err(FlashcardsError::Internal)?;
+    let mut cards = load_vocabulary_cards().map_err(FlashcardsError::Internal)?;
+    cards.retain(|card| templates.contains(&card.card.template));
+    Ok(cards)
+}
+
+/// Loads every template's cards for every vocabulary entry in curriculum
+/// order: lesson files sorted by name, then entries in the order they appear
+/// in the lesson. Card ids are `{lesson}:{entry id}` plus a template suffix,
+/// so they survive reordering. Entries without an id, in files that predate
+/// ids and have not been through `lessons assign-ids`, get no cards.
+fn load_vocabulary_cards() -> Result<Vec<VocabularyCard>, String> {
+    let mut cards = Vec::new();
+    let lessons_path = lessons_dir();
+
+    let mut paths = fs::read_dir(&lessons_path)
+        .map_err(|err| format!("Failed to read lessons directory: {err}"))?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    for path in paths {
+        let content = fs::read_to_string(&path)
+            .map_err(|err| format!("Failed to read lesson file: {err}"))?;
+        let lesson: Lesson =
+            serde_json::from_str(&content).map_err(|err| format!("Failed to parse lesson JSON: {err}"))?;
+        cards.extend(lesson_cards(&lesson));
+    }
+
+    Ok(cards)
+}
+
+/// Every template's cards for one lesson's vocabulary entries, in order.
+fn lesson_cards(lesson: &Lesson) -> Vec<VocabularyCard> {
+    let mut cards = Vec::new();
+    let sentences = cloze::lesson_sentences(lesson);
+
+    for section in &lesson.sections {
+        if let ContentSection::Vocabulary(vocab) = section {
+            for (index, entry) in vocab.entries.iter().enumerate() {
+                let Some(entry_id) = entry.id.as_deref() else {
+                    continue;
+                };
+                let base_id = format!("{}:{entry_id}", lesson.id);
+                for faces in templates::expand(entry, &sentences) {
+                    let is_recognition = faces.template == CardTemplate::Recognition;
+                    cards.push(VocabularyCard {
+                        card: Flashcard {
+                            id: faces.template.card_id(&base_id),
+                            template: faces.template,
+                            front: faces.front,
+                            back: faces.back,
+                            hint: faces.hint,
+                            romanisation: entry.romanisation.clone(),
+                            audio_url: entry.audio.as_deref().map(media::media_url),
+                            state: CardState::New,
+                            leech: false,
+                        },
+                        lesson_id: lesson.id.clone(),
+                        lesson_title: lesson.title.clone(),
+                        course: lesson.source.as_ref().map(|source| source.title.clone()),
+                        tags: entry.tags.clone(),
+                        audio: entry.audio.clone(),
+                        lemma: entry.lemma.clone().unwrap_or_else(|| entry.word.clone()),
+                        legacy_id: is_recognition.then(|| format!("{}:vocab:{index}", lesson.id)),
+                    });
+                }
+            }
+        }
+    }
+
+    cards
+}
+
+async fn load_states(db: Db, user_id: i64) -> Result<HashMap<String, StoredState>, String> {
+    task::spawn_blocking(move || {
+        let conn = db.read()?;
+        let map = {
+            let mut stmt = conn
+                .prepare(
+                    "SELECT card_id, stability, difficulty, last_review, due_date, interval_days, state, step FROM fsrs_cards WHERE user_id = ?1",
+                )
+                .map_err(|err| format!("Failed to prepare statement: {err}"))?;
+
+            let rows = stmt
+                .query_map([user_id], |row| {
+                    let card_id: String = row.get(0)?;
+                    let stability: f32 = row.get(1)?;
+                    let difficulty: f32 = row.get(2)?;
+                    let last_review: Option<String> = row.get(3)?;
+                    let due_date: Option<String> = row.get(4)?;
+                    let interval_days: i64 = row.get(5)?;
+                    let card_state: String = row.get(6)?;
+                    let step: usize = row.get(7)?;
+
+                    let last_review = last_review
+                        .and_then(|value| DateTime::parse_from_rfc3339(&value).ok())
+                        .map(|dt| dt.with_timezone(&Utc));
+                    let due_date = due_date
+                        .and_then(|value| DateTime::parse_from_rfc3339(&value).ok())
+                        .map(|dt| dt.with_timezone(&Utc));
+
+                    Ok((
+                        card_id,
+                        StoredState {
+                            stability,
+                            difficulty,
+                            last_review,
+                            due_date,
+                            interval_days,
+                            state: CardState::parse(&card_state),
+                            step,
+                        },
+                    ))
+                })
+                .map_err(|err| format!("Failed to query states: {err}"))?;
+
+            let mut map = HashMap::new();
+            for row in rows {
+                let (card_id, state) = row.map_err(|err| format!("Failed to read row: {err}"))?;
+                map.insert(card_id, state);
+            }
+            map
+        };
+        drop(conn);
+
+        Ok(map)
+    })
+    .await
+    .map_err(|_| "Failed to join blocking task".to_string())?
+}
+
+fn read_state(conn: &Connection, user_id: i64, card_id: &str) -> Result<Option<StoredState>, String> {
+    conn.query_row(
+        "SELECT stability, difficulty, last_review, due_date, interval_days, state, step FROM fsrs_cards WHERE user_id = ?1 AND card_id = ?2",
+        params![user_id, card_id],
+        |row| {
+            let stability: f32 = row.get(0)?;
+            let difficulty: f32 = row.get(1)?;
+            let last_review: Option<String> = row.get(2)?;
+            let due_date: Option<String> = row.get(3)?;
+            let interval_days: i64 = row.get(4)?;
+            let card_state: String = row.get(5)?;
+            let step: usize = row.get(6)?;
+            let last_review = last_review
+                .and_then(|value| DateTime::parse_from_rfc3339(&value).ok())
+                .map(|dt| dt.with_timezone(&Utc));
+            let due_date = due_date
+                .and_then(|value| DateTime::parse_from_rfc3339(&value).ok())
+                .map(|dt| dt.with_timezone(&Utc));
+
+            Ok(StoredState {
+                stability,
+                difficulty,
+                last_review,
+                due_date,
+                interval_days,
+                state: CardState::parse(&card_state),
+                step,
+            })
+        },
+    )
+    .optional()
+    .map_err(|err| format!("Failed to read card state: {err}"))
+}
+
+/// Stores the new card state and appends the review to the log, inside the
+/// caller's transaction so the log never disagrees with `fsrs_cards`.
+/// Returns whether the review suspended the card; one that was already
+/// suspended is left as it is.
+fn write_review(tx: &Transaction<'_>, user_id: i64, review: &ReviewRecord) -> Result<bool, String> {
+    tx.execute(
+        "
+        INSERT INTO fsrs_cards (card_id, stability, difficulty, last_review, due_date, interval_days, state, step, user_id)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+        ON CONFLICT(user_id, card_id) DO UPDATE SET
+            stability = excluded.stability,
+            difficulty = excluded.difficulty,
+            last_review = excluded.last_review,
+            due_date = excluded.due_date,
+            interval_days = excluded.interval_days,
+            state = excluded.state,
+            step = excluded.step
+        ",
+        params![
+            review.card_id,
+            review.memory.stability,
+            review.memory.difficulty,
+            review.reviewed_at.to_rfc3339(),
+            review.due_date.to_rfc3339(),
+            review.interval_days,
+            review.state.as_str(),
+            review.step,
+            user_id,
+        ],
+    )
+    .map_err(|err| format!("Failed to save card state: {err}"))?;
+
+    let suspended = review.suspend
+        && tx
+            .execute(
+                "
+                INSERT INTO flashcard_flags (user_id, card_id, suspended)
+                VALUES (?1, ?2, 1)
+                ON CONFLICT(user_id, card_id) DO UPDATE SET suspended = 1
+                WHERE suspended = 0
+                ",
+                params![user_id, review.card_id],
+            )
+            .map_err(|err| format!("Failed to suspend leech: {err}"))?
+            > 0;
+
+    let previous = review.previous.as_ref();
+    tx.execute(
+        "
+        INSERT INTO fsrs_review_log (
+            card_id, rating, reviewed_at, elapsed_days,
+            stability_before, difficulty_before, stability_after, difficulty_after,
+            last_review_before, due_before, interval_before, interval_days, due_date,
+            state_before, step_before, state_after, session_id, user_id, suspended_card
+        )
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19)
+        ",
+        params![
+            review.card_id,
+            review.rating,
+            review.reviewed_at.to_rfc3339(),
+            review.elapsed_days,
+            previous.map(|state| state.stability),
+            previous.map(|state| state.difficulty),
+            review.memory.stability,
+            review.memory.difficulty,
+            previous.and_then(|state| state.last_review).map(|dt| dt.to_rfc3339()),
+            previous.and_then(|state| state.due_date).map(|dt| dt.to_rfc3339()),
+            previous.map(|state| state.interval_days),
+            review.interval_days,
+            review.due_date.to_rfc3339(),
+            previous.map_or(CardState::New, |state| state.state).as_str(),
+            previous.map(|state| state.step),
+            review.state.as_str(),
+            review.session_id.as_deref(),
+            user_id,
+            suspended,
+        ],
+    )
+    .map_err(|err| format!("Failed to append review log: {err}"))?;
+
+    Ok(suspended)
+}
+
+async fn load_history(
+    db: Db,
+    user_id: i64,
+    card_id: &str,
+    limit: usize,
+    before: Option<i64>,
+) -> Result<Vec<ReviewLogEntry>, String> {
+    let card_id = card_id.to_string();
+    task::spawn_blocking(move || {
+        let conn = db.read()?;
+        let entries = {
+            let mut stmt = conn
+                .prepare(
+                    "
+                    SELECT id, card_id, rating, reviewed_at, elapsed_days,
+                        stability_before, difficulty_before, stability_after, difficulty_after,
+                        interval_days, due_date, state_before, state_after
+                    FROM fsrs_review_log
+                    WHERE user_id = ?1 AND card_id = ?2 AND (?3 IS NULL OR id < ?3)
+                    ORDER BY id DESC
+                    LIMIT ?4
+                    ",
+                )
+                .map_err(|err| format!("Failed to prepare statement: {err}"))?;
+
+            let rows = stmt
+                .query_map(
+                    params![user_id, card_id, before, i64::try_from(limit).unwrap_or(i64::MAX)],
+                    |row| {
+                        Ok(ReviewLogEntry {
+                            id: row.get(0)?,
+                            card_id: row.get(1)?,
+                            rating: row.get(2)?,
+                            reviewed_at: row.get(3)?,
+                            elapsed_days: row.get(4)?,
+                            stability_before: row.get(5)?,
+                            difficulty_before: row.get(6)?,
+                            stability_after: row.get(7)?,
+                            difficulty_after: row.get(8)?,
+                            interval_days: row.get(9)?,
+                            due_date: row.get(10)?,
+                            state_before: row
+                                .get::<_, Option<String>>(11)?
+                                .map(|state| CardState::parse(&state)),
+                            state_after: row
+                                .get::<_, Option<String>>(12)?
+                                .map(|state| CardState::parse(&state)),
+                        })
+                    },
+                )
+                .map_err(|err| format!("Failed to query review log: {err}"))?;
+
+            let mut entries = Vec::new();
+            for row in rows {
+                entries.push(row.map_err(|err| format!("Failed to read row: {err}"))?);
+            }
+            entries
+        };
+        drop(conn);
+
+        Ok(entries)
+    })
+    .await
+    .map_err(|_| "Failed to join blocking task".to_string())?
+}
+
+async fn load_setting(db: Db, user_id: i64, key: &'static str) -> Result<Option<String>, String> {
+    task::spawn_blocking(move || {
+        db.read()?
+            .query_row(
+                "SELECT value FROM fsrs_settings WHERE user_id = ?1 AND key = ?2",
+                params![user_id, key],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|err| format!("Failed to read settings: {err}"))
+    })
+    .await
+    .map_err(|_| "Failed to join blocking task".to_string())?
+}
+
+async fn save_setting(
+    db: Db,
+    user_id: i64,
+    key: &'static str,
+    value: String,
+) -> Result<(), String> {
+    task::spawn_blocking(move || {
+        db.write()?
+            .execute(
+            "
+            INSERT INTO fsrs_settings (user_id, key, value)
+            VALUES (?1, ?2, ?3)
+            ON CONFLICT(user_id, key) DO UPDATE SET value = excluded.value
+            ",
+            params![user_id, key, value],
+            )
+            .map_err(|err| format!("Failed to save settings: {err}"))?;
+        Ok(())
+    })
+    .await
+    .map_err(|_| "Failed to join blocking task".to_string())?
+}
+
+async fn load_desired_retention(db: Db, user_id: i64) -> Result<f32, String> {
+    load_setting(db, user_id, SETTINGS_KEY_RETENTION)
+        .await?
+        .map_or(Ok(DEFAULT_DESIRED_RETENTION), |raw| {
+            raw.parse::<f32>()
+                .map_err(|_| "Invalid desired retention value".to_string())
+        })
+}
+
+async fn save_desired_retention(db: Db, user_id: i64, value: f32) -> Result<(), String> {
+    save_setting(db, user_id, SETTINGS_KEY_RETENTION, value.to_string()).await
+}
+
+/// FSRS weights used for scheduling: the learner's fitted parameters once an
+/// optimisation run has been applied, otherwise the crate defaults.
+async fn load_parameters(db: Db, user_id: i64) -> Result<Vec<f32>, String> {
+    load_setting(db, user_id, SETTINGS_KEY_PARAMETERS)
+        .await?
+        .map_or_else(
+            || Ok(DEFAULT_PARAMETERS.to_vec()),
+            |raw| {
+                serde_json::from_str::<Vec<f32>>(&raw)
+                    .map_err(|_| "Invalid FSRS parameters value".to_string())
+            },
+        )
+}
+
+async fn load_steps(
+    db: Db,
+    user_id: i64,
+    key: &'static str,
+    default: &[u32],
+) -> Result<Vec<u32>, String> {
+    load_setting(db, user_id, key).await?.map_or_else(
+        || Ok(default.to_vec()),
+        |raw| serde_json::from_str::<Vec<u32>>(&raw).map_err(|_| format!("Invalid {key} value")),
+    )
+}
+
+async fn save_steps(db: Db, user_id: i64, key: &'static str, steps: &[u32]) -> Result<(), String> {
+    let value = serde_json::to_string(steps).map_err(|err| err.to_string())?;
+    save_setting(db, user_id, key, value).await
+}
+
+async fn load_count(
+    db: Db,
+    user_id: i64,
+    key: &'static str,
+    default: usize,
+) -> Result<usize, String> {
+    load_setting(db, user_id, key).await?.map_or(Ok(default), |raw| {
+        raw.parse::<usize>().map_err(|_| format!("Invalid {key} value"))
+    })
+}
+
+async fn load_utc_offset(db: Db, user_id: i64) -> Result<i32, String> {
+    load_setting(db, user_id, SETTINGS_KEY_UTC_OFFSET)
+        .await?
+        .map_or(Ok(0), |raw| {
+            raw.parse::<i32>()
+                .map_err(|_| format!("Invalid {SETTINGS_KEY_UTC_OFFSET} value"))
+        })
+}
+
+/// The learner's study clock, for callers that need nothing else from the
+/// settings.
+async fn load_study_clock(db: Db, user_id: i64) -> Result<StudyClock, FlashcardsError> {
+    load_utc_offset(db, user_id)
+        .await
+        .map(StudyClock::new)
+        .map_err(FlashcardsError::Internal)
+}
+
+/// Counts new cards introduced and review cards answered since the start of
+/// the study day. Learning steps do not count against either limit.
+async fn load_daily_progress(
+    db: Db,
+    user_id: i64,
+    since: DateTime<Utc>,
+) -> Result<DailyProgress, String> {
+    task::spawn_blocking(move || {
+        db.read()?
+            .query_row(
+                "
+                SELECT
+                    COUNT(DISTINCT CASE WHEN state_before = 'new' THEN card_id END),
+                    COUNT(CASE WHEN state_before = 'review' THEN 1 END)
+                FROM fsrs_review_log
+                WHERE user_id = ?1 AND reviewed_at >= ?2
+                ",
+                params![user_id, since.to_rfc3339()],
+                |row| {
+                    Ok(DailyProgress {
+                        new_done: row.get(0)?,
+                        reviews_done: row.get(1)?,
+                    })
+                },
+            )
+            .map_err(|err| format!("Failed to count today's reviews: {err}"))
+    })
+    .await
+    .map_err(|_| "Failed to join blocking task".to_string())?
+}
+
+async fn load_templates(db: Db, user_id: i64) -> Result<Vec<CardTemplate>, String> {
+    load_setting(db, user_id, SETTINGS_KEY_TEMPLATES).await?.map_or_else(
+        || Ok(CardTemplate::ALL.to_vec()),
+        |raw| {
+            serde_json::from_str::<Vec<CardTemplate>>(&raw)
+                .map_err(|_| format!("Invalid {SETTINGS_KEY_TEMPLATES} value"))
+        },
+    )
+}
+
+async fn load_leech_action(db: Db, user_id: i64) -> Result<LeechAction, String> {
+    load_setting(db, user_id, SETTINGS_KEY_LEECH_ACTION)
+        .await?
+        .map_or(Ok(LeechAction::Tag), |raw| {
+            serde_json::from_str::<LeechAction>(&raw)
+                .map_err(|_| format!("Invalid {SETTINGS_KEY_LEECH_ACTION} value"))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A small lesson, built in memory so tests never touch `data/lessons`.
+    pub(super) fn fixture_lesson() -> Lesson {
+        serde_json::from_value(serde_json::json!({
+            "id": "fixture",
+            "title": "Fixture",
+            "description": "",
+            "source": {"title": "Thirukkural"},
+            "sections": [
+                {"type": "prose", "paragraphs": ["நல்ல மனிதர் அன்பு காட்டுவார்."]},
+                {"type": "vocabulary", "entries": [
+                    {"id": "anbu", "word": "அன்பு", "meaning": "love", "tags": ["virtue"]},
+                    {"id": "aram", "word": "அறம்", "meaning": "virtue"}
+                ]}
+            ]
+        }))
+        .unwrap()
+    }
+
+    /// The fixture's first card id, as the queue would hand it out.
+    pub(super) fn fixture_card_id() -> String {
+        lesson_cards(&fixture_lesson())[0].card.id.clone()
+    }
+
+    /// State over an empty database, without `init_state`'s start-up work.
+    pub(super) fn test_state() -> FlashcardsState {
+        FlashcardsState {
+            db: Db::temporary(),
+            optimising: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_reviews_build_on_each_other() {
+        let state = test_state();
+        let card_id = fixture_card_id();
+        let reviews = (0..8).map(|_| {
+            let state = state.clone();
+            let card_id = card_id.clone();
+            tokio::spawn(async move { review_card(&state, 1, &card_id, 3, None, None).await })
+        });
+        for review in reviews.collect::<Vec<_>>() {
+            review.await.unwrap().unwrap();
+        }
+
+        let conn = state.db.read().unwrap();
+        let mut stmt = conn
+            .prepare(
+                "
+                SELECT stability_before, stability_after FROM fsrs_review_log
+                WHERE user_id = 1 AND card_id = ?1 ORDER BY id
+                ",
+            )
+            .unwrap();
+        let log = stmt
+            .query_map([&card_id], |row| Ok((row.get::<_, Option<f32>>(0)?, row.get::<_, f32>(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(log.len(), 8);
+        assert_eq!(log[0].0, None);
+        for pair in log.windows(2) {
+            assert_eq!(pair[1].0, Some(pair[0].1), "each review starts from the last one's state");
+        }
+    }
+
+    #[tokio::test]
+    async fn reviews_append_to_the_log_without_touching_earlier_rows() {
+        let state = test_state();
+        let card_id = fixture_card_id();
+        review_card(&state, 1, &card_id, 3, None, None).await.unwrap();
+        let first = get_history(&state, 1, &card_id, None, None).await.unwrap();
+        let first = serde_json::to_value(&first.entries[0]).unwrap();
+
+        review_card(&state, 1, &card_id, 1, None, None).await.unwrap();
+        let latest = get_history(&state, 1, &card_id, Some(1), None).await.unwrap();
+        assert_eq!(latest.entries[0].rating, 1);
+        assert_eq!(latest.entries[0].state_before, Some(CardState::Learning));
+        assert_eq!(
+            latest.entries[0].stability_before,
+            first["stability_after"].as_f64().map(|value| value as f32)
+        );
+
+        let older = get_history(&state, 1, &card_id, Some(1), latest.next_before)
+            .await
+            .unwrap();
+        assert_eq!(serde_json::to_value(&older.entries[0]).unwrap(), first);
+        assert_eq!(older.next_before, None);
+        assert!(get_history(&state, 2, &card_id, None, None).await.unwrap().entries.is_empty());
+    }
+}
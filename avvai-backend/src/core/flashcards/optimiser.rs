@@ -0,0 +1,295 @@
+use chrono::Utc;
+use fsrs::{ComputeParametersInput, FSRSItem, FSRSReview, FSRS};
+use serde::{Deserialize, Serialize};
+use tokio::task;
+
+use crate::core::db::Db;
+use super::{
+    load_parameters, load_setting, save_setting, FlashcardsError, FlashcardsState,
+    SETTINGS_KEY_PARAMETERS,
+};
+
+const SETTINGS_KEY_LAST_OPTIMISATION: &str = "last_optimisation";
+
+/// Below this many training items the fsrs optimiser only fits the initial
+/// stabilities, so a run would not personalise anything meaningful.
+const MIN_TRAINING_ITEMS: usize = 64;
+/// One in this many items, the most recent ones, is held out of the fit and
+/// used to compare the current and fitted parameters.
+const HOLDOUT_FRACTION: usize = 5;
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct EvaluationMetrics {
+    pub log_loss: f32,
+    pub rmse_bins: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct OptimisationReport {
+    pub ran_at: String,
+    pub review_count: usize,
+    pub training_items: usize,
+    /// Items held out of the fit; `before` and `after` are measured on these.
+    #[serde(default)]
+    pub evaluation_items: usize,
+    /// Metrics of the parameters in use before the run.
+    pub before: EvaluationMetrics,
+    /// Metrics of the newly fitted parameters.
+    pub after: EvaluationMetrics,
+    pub parameters: Vec<f32>,
+    /// Fitted parameters are only applied when they predict the held-out
+    /// reviews at least as well as the current ones.
+    pub applied: bool,
+}
+
+struct LoggedReview {
+    id: i64,
+    card_id: String,
+    rating: u32,
+    elapsed_days: u32,
+    first_seen: bool,
+}
+
+/// Marks a learner's optimisation as running until dropped, even if the
+/// optimiser panics.
+struct RunGuard<'a> {
+    state: &'a FlashcardsState,
+    user_id: i64,
+}
+
+impl<'a> RunGuard<'a> {
+    fn acquire(state: &'a FlashcardsState, user_id: i64) -> Result<Self, FlashcardsError> {
+        let mut running = state
+            .optimising
+            .lock()
+            .map_err(|_| FlashcardsError::Internal("Optimisation lock poisoned".to_string()))?;
+        if !running.insert(user_id) {
+            return Err(FlashcardsError::Conflict(
+                "An optimisation run is already in progress".to_string(),
+            ));
+        }
+        Ok(Self { state, user_id })
+    }
+}
+
+impl Drop for RunGuard<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut running) = self.state.optimising.lock() {
+            running.remove(&self.user_id);
+        }
+    }
+}
+
+/// Fits FSRS parameters to the older part of the review log, evaluates them
+/// against the current parameters on the most recent reviews and stores them
+/// for scheduling if they are no worse.
+pub async fn optimise_parameters(
+    state: &FlashcardsState,
+    user_id: i64,
+) -> Result<OptimisationReport, FlashcardsError> {
+    let _guard = RunGuard::acquire(state, user_id)?;
+
+    let reviews = load_logged_reviews(state.db.clone(), user_id)
+        .await
+        .map_err(FlashcardsError::Internal)?;
+    let review_count = reviews.len();
+    let items = build_training_items(reviews);
+    if items.len() < MIN_TRAINING_ITEMS {
+        return Err(FlashcardsError::BadRequest(format!(
+            "Not enough review history to optimise: {} of {MIN_TRAINING_ITEMS} training items",
+            items.len()
+        )));
+    }
+
+    let current = load_parameters(state.db.clone(), user_id)
+        .await
+        .map_err(FlashcardsError::Internal)?;
+
+    let mut training = items;
+    let held_out = training.split_off(training.len() - training.len() / HOLDOUT_FRACTION);
+    let training_items = training.len();
+    let evaluation_items = held_out.len();
+    let (before, fitted, after) =
+        task::spawn_blocking(move || fit_and_evaluate(&current, training, held_out))
+            .await
+            .map_err(|_| FlashcardsError::Internal("Failed to join blocking task".to_string()))??;
+
+    let applied = after.log_loss <= before.log_loss;
+    let report = OptimisationReport {
+        ran_at: Utc::now().to_rfc3339(),
+        review_count,
+        training_items,
+        evaluation_items,
+        before,
+        after,
+        parameters: fitted,
+        applied,
+    };
+
+    if applied {
+        let parameters = serde_json::to_string(&report.parameters)
+            .map_err(|err| FlashcardsError::Internal(err.to_string()))?;
+        save_setting(state.db.clone(), user_id, SETTINGS_KEY_PARAMETERS, parameters)
+            .await
+            .map_err(FlashcardsError::Internal)?;
+    }
+
+    let serialized =
+        serde_json::to_string(&report).map_err(|err| FlashcardsError::Internal(err.to_string()))?;
+    save_setting(state.db.clone(), user_id, SETTINGS_KEY_LAST_OPTIMISATION, serialized)
+        .await
+        .map_err(FlashcardsError::Internal)?;
+
+    Ok(report)
+}
+
+pub async fn last_optimisation(
+    state: &FlashcardsState,
+    user_id: i64,
+) -> Result<Option<OptimisationReport>, FlashcardsError> {
+    let Some(raw) = load_setting(state.db.clone(), user_id, SETTINGS_KEY_LAST_OPTIMISATION)
+        .await
+        .map_err(FlashcardsError::Internal)?
+    else {
+        return Ok(None);
+    };
+
+    serde_json::from_str(&raw)
+        .map(Some)
+        .map_err(|_| FlashcardsError::Internal("Invalid optimisation report".to_string()))
+}
+
+fn fit_and_evaluate(
+    current: &[f32],
+    training: Vec<FSRSItem>,
+    held_out: Vec<FSRSItem>,
+) -> Result<(EvaluationMetrics, Vec<f32>, EvaluationMetrics), FlashcardsError> {
+    let evaluate = |parameters: &[f32], items: Vec<FSRSItem>| {
+        let model = FSRS::new(Some(parameters))
+            .map_err(|err| FlashcardsError::Internal(format!("Invalid FSRS parameters: {err:?}")))?;
+        model
+            .evaluate(items, |_| true)
+            .map(|evaluation| EvaluationMetrics {
+                log_loss: evaluation.log_loss,
+                rmse_bins: evaluation.rmse_bins,
+            })
+            .map_err(|err| FlashcardsError::Internal(format!("Failed to evaluate parameters: {err:?}")))
+    };
+
+    let before = evaluate(current, held_out.clone())?;
+
+    let trainer = FSRS::new(None)
+        .map_err(|err| FlashcardsError::Internal(format!("Failed to initialise FSRS: {err:?}")))?;
+    let fitted = trainer
+        .compute_parameters(ComputeParametersInput {
+            train_set: training,
+            ..ComputeParametersInput::default()
+        })
+        .map_err(|err| FlashcardsError::Internal(format!("Failed to optimise parameters: {err:?}")))?;
+
+    let after = evaluate(&fitted, held_out)?;
+    Ok((before, fitted, after))
+}
+
+/// Turns the log into fsrs training items: one item per review after the
+/// first, holding that card's history up to and including the review. Cards
+/// whose first logged review is not their first ever review (reviewed before
+/// the log existed) are skipped because their history is incomplete. Items are
+/// ordered by review time, which the evaluator's recency weighting expects.
+fn build_training_items(reviews: Vec<LoggedReview>) -> Vec<FSRSItem> {
+    let mut timed_items = Vec::new();
+    let mut current_card: Option<String> = None;
+    let mut history: Vec<FSRSReview> = Vec::new();
+    let mut complete = false;
+
+    for review in reviews {
+        if current_card.as_deref() != Some(review.card_id.as_str()) {
+            current_card = Some(review.card_id.clone());
+            history.clear();
+            complete = review.first_seen;
+        }
+        if !complete {
+            continue;
+        }
+
+        history.push(FSRSReview {
+            rating: review.rating,
+            delta_t: if history.is_empty() { 0 } else { review.elapsed_days },
+        });
+        if history.len() > 1 {
+            timed_items.push((
+                review.id,
+                FSRSItem {
+                    reviews: history.clone(),
+                },
+            ));
+        }
+    }
+
+    timed_items.sort_by_key(|(id, _)| *id);
+    timed_items.into_iter().map(|(_, item)| item).collect()
+}
+
+async fn load_logged_reviews(db: Db, user_id: i64) -> Result<Vec<LoggedReview>, String> {
+    task::spawn_blocking(move || {
+        let conn = db.read()?;
+        let reviews = {
+            let mut stmt = conn
+                .prepare(
+                    "
+                    SELECT id, card_id, rating, elapsed_days, stability_before IS NULL
+                    FROM fsrs_review_log
+                    WHERE user_id = ?1
+                    ORDER BY card_id ASC, id ASC
+                    ",
+                )
+                .map_err(|err| format!("Failed to prepare statement: {err}"))?;
+
+            let rows = stmt
+                .query_map([user_id], |row| {
+                    Ok(LoggedReview {
+                        id: row.get(0)?,
+                        card_id: row.get(1)?,
+                        rating: row.get(2)?,
+                        elapsed_days: row.get(3)?,
+                        first_seen: row.get(4)?,
+                    })
+                })
+                .map_err(|err| format!("Failed to query review log: {err}"))?;
+
+            let mut reviews = Vec::new();
+            for row in rows {
+                reviews.push(row.map_err(|err| format!("Failed to read row: {err}"))?);
+            }
+            reviews
+        };
+        drop(conn);
+
+        Ok(reviews)
+    })
+    .await
+    .map_err(|_| "Failed to join blocking task".to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        collections::HashSet,
+        sync::{Arc, Mutex},
+    };
+
+    #[test]
+    fn runs_are_exclusive_per_learner() {
+        let state = FlashcardsState {
+            db: Db::temporary(),
+            optimising: Arc::new(Mutex::new(HashSet::new())),
+        };
+        let first = RunGuard::acquire(&state, 1).unwrap();
+        assert!(matches!(RunGuard::acquire(&state, 1), Err(FlashcardsError::Conflict(_))));
+        let other = RunGuard::acquire(&state, 2);
+        assert!(other.is_ok());
+        drop(first);
+        assert!(RunGuard::acquire(&state, 1).is_ok());
+    }
+}
@@ -0,0 +1,265 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+
+use super::{CardState, Flashcard, StoredState};
+
+#[derive(Clone, Copy)]
+pub enum DueOrder {
+    /// Most overdue reviews first; new cards in curriculum order.
+    Overdue,
+    /// Everything in lesson order, then vocabulary order within a lesson.
+    Curriculum,
+    /// Shuffled, reproducibly for a given seed.
+    Random(u64),
+}
+
+impl DueOrder {
+    /// Parses the `order` query value. A random order without an explicit
+    /// seed is seeded by the study day, so the queue is stable within a day.
+    pub fn parse(order: Option<&str>, seed: Option<u64>, today: NaiveDate) -> Result<Self, String> {
+        match order.unwrap_or("overdue") {
+            "overdue" => Ok(Self::Overdue),
+            "curriculum" => Ok(Self::Curriculum),
+            "random" => Ok(Self::Random(seed.unwrap_or_else(|| {
+                u64::try_from(today.to_epoch_days()).unwrap_or_default()
+            }))),
+            other => Err(format!(
+                "order must be one of overdue, curriculum or random, got {other}"
+            )),
+        }
+    }
+}
+
+pub struct DailyLimits {
+    pub new_per_day: usize,
+    pub reviews_per_day: usize,
+}
+
+/// Cards already introduced or reviewed during the current study day.
+pub struct DailyProgress {
+    pub new_done: usize,
+    pub reviews_done: usize,
+}
+
+#[derive(Serialize)]
+pub struct DueSummary {
+    /// Cards in a (re)learning step that are due now.
+    pub learning: usize,
+    /// Review cards still to do today, after the daily review limit.
+    pub review: usize,
+    /// New cards still to introduce today, after the daily new-card limit.
+    pub new: usize,
+    pub new_done_today: usize,
+    pub reviews_done_today: usize,
+    pub new_limit: usize,
+    pub review_limit: usize,
+}
+
+pub struct DueQueue {
+    pub cards: Vec<Flashcard>,
+    pub summary: DueSummary,
+}
+
+/// Splits the cards into learning, review and new piles, applies what is
+/// left of today's limits and orders each pile. Learning cards are never
+/// limited because they are already in progress.
+pub fn build_queue(
+    cards: Vec<Flashcard>,
+    states: &HashMap<String, StoredState>,
+    limits: &DailyLimits,
+    progress: &DailyProgress,
+    order: DueOrder,
+    now: DateTime<Utc>,
+) -> DueQueue {
+    let mut learning = Vec::new();
+    let mut review = Vec::new();
+    let mut new = Vec::new();
+
+    for (position, mut card) in cards.into_iter().enumerate() {
+        match states.get(&card.id) {
+            None => new.push((position, None, card)),
+            Some(stored) if stored.state == CardState::New => new.push((position, None, card)),
+            Some(stored) if stored.is_due(now) => {
+                card.state = stored.state;
+                if stored.state.is_learning() {
+                    learning.push((position, stored.due_date, card));
+                } else {
+                    review.push((position, stored.due_date, card));
+                }
+            }
+            Some(_) => {}
+        }
+    }
+
+    learning.sort_by_key(|(position, due, _)| (*due, *position));
+    sort_pile(&mut review, order);
+    sort_pile(&mut new, order);
+
+    let review_remaining = limits.reviews_per_day.saturating_sub(progress.reviews_done);
+    let new_remaining = limits.new_per_day.saturating_sub(progress.new_done);
+    review.truncate(review_remaining);
+    new.truncate(new_remaining);
+
+    let summary = DueSummary {
+        learning: learning.len(),
+        review: review.len(),
+        new: new.len(),
+        new_done_today: progress.new_done,
+        reviews_done_today: progress.reviews_done,
+        new_limit: limits.new_per_day,
+        review_limit: limits.reviews_per_day,
+    };
+
+    let cards = learning
+        .into_iter()
+        .chain(review)
+        .chain(new)
+        .map(|(_, _, card)| card)
+        .collect();
+
+    DueQueue { cards, summary }
+}
+
+type PileEntry = (usize, Option<DateTime<Utc>>, Flashcard);
+
+fn sort_pile(pile: &mut [PileEntry], order: DueOrder) {
+    match order {
+        DueOrder::Overdue => pile.sort_by_key(|(position, due, _)| (*due, *position)),
+        DueOrder::Curriculum => pile.sort_by_key(|(position, _, _)| *position),
+        DueOrder::Random(seed) => {
+            pile.sort_by_cached_key(|(_, _, card)| shuffle_key(seed, &card.id));
+        }
+    }
+}
+
+/// Stable pseudo-random sort key: FNV-1a over the card id, mixed with the
+/// seed through a splitmix64 finaliser.
+fn shuffle_key(seed: u64, card_id: &str) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in card_id.bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+
+    let mut mixed = hash ^ seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    mixed = (mixed ^ (mixed >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    mixed = (mixed ^ (mixed >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    mixed ^ (mixed >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::flashcards::CardTemplate;
+    use chrono::Duration;
+
+    fn card(id: &str) -> Flashcard {
+        Flashcard {
+            id: id.to_string(),
+            template: CardTemplate::Recognition,
+            front: String::new(),
+            back: String::new(),
+            hint: None,
+            romanisation: None,
+            audio_url: None,
+            state: CardState::New,
+            leech: false,
+        }
+    }
+
+    fn stored(state: CardState, due: DateTime<Utc>) -> StoredState {
+        StoredState {
+            stability: 1.0,
+            difficulty: 5.0,
+            last_review: None,
+            due_date: Some(due),
+            interval_days: 1,
+            state,
+            step: 0,
+        }
+    }
+
+    fn ids(queue: &DueQueue) -> Vec<&str> {
+        queue.cards.iter().map(|card| card.id.as_str()).collect()
+    }
+
+    /// r1 is due a day later than r2, r3 is not due yet, l1 is learning and
+    /// n1..n3 are new.
+    fn fixture(now: DateTime<Utc>) -> (Vec<Flashcard>, HashMap<String, StoredState>) {
+        let cards = ["n1", "r1", "n2", "l1", "r2", "r3", "n3"].map(card).into();
+        let states = HashMap::from([
+            ("r1".to_string(), stored(CardState::Review, now - Duration::days(1))),
+            ("r2".to_string(), stored(CardState::Review, now - Duration::days(2))),
+            ("r3".to_string(), stored(CardState::Review, now + Duration::days(1))),
+            ("l1".to_string(), stored(CardState::Learning, now)),
+        ]);
+        (cards, states)
+    }
+
+    #[test]
+    fn applies_what_is_left_of_the_daily_limits() {
+        let now = Utc::now();
+        let (cards, states) = fixture(now);
+        let limits = DailyLimits {
+            new_per_day: 3,
+            reviews_per_day: 2,
+        };
+        let progress = DailyProgress {
+            new_done: 1,
+            reviews_done: 1,
+        };
+        let queue = build_queue(cards, &states, &limits, &progress, DueOrder::Overdue, now);
+
+        // Learning cards are never limited; the most overdue review wins.
+        assert_eq!(ids(&queue), ["l1", "r2", "n1", "n2"]);
+        assert_eq!((queue.summary.learning, queue.summary.review, queue.summary.new), (1, 1, 2));
+
+        let spent = DailyProgress {
+            new_done: 5,
+            reviews_done: 5,
+        };
+        let (cards, states) = fixture(now);
+        let queue = build_queue(cards, &states, &limits, &spent, DueOrder::Overdue, now);
+        assert_eq!(ids(&queue), ["l1"]);
+    }
+
+    #[test]
+    fn orders_piles_deterministically() {
+        let now = Utc::now();
+        let limits = DailyLimits {
+            new_per_day: 10,
+            reviews_per_day: 10,
+        };
+        let progress = DailyProgress {
+            new_done: 0,
+            reviews_done: 0,
+        };
+        let queue_for = |order| {
+            let (cards, states) = fixture(now);
+            let queue = build_queue(cards, &states, &limits, &progress, order, now);
+            ids(&queue).iter().map(ToString::to_string).collect::<Vec<_>>()
+        };
+
+        assert_eq!(queue_for(DueOrder::Overdue), ["l1", "r2", "r1", "n1", "n2", "n3"]);
+        assert_eq!(queue_for(DueOrder::Curriculum), ["l1", "r1", "r2", "n1", "n2", "n3"]);
+        assert_eq!(queue_for(DueOrder::Random(7)), queue_for(DueOrder::Random(7)));
+        let shuffled = queue_for(DueOrder::Random(7));
+        assert_eq!(shuffled[0], "l1");
+        let mut reviews = shuffled[1..3].to_vec();
+        reviews.sort();
+        assert_eq!(reviews, ["r1", "r2"]);
+    }
+
+    #[test]
+    fn random_order_is_seeded_by_the_study_day() {
+        let day = NaiveDate::from_ymd_opt(2026, 10, 19).unwrap();
+        let seed = |day| match DueOrder::parse(Some("random"), None, day).unwrap() {
+            DueOrder::Random(seed) => seed,
+            _ => unreachable!(),
+        };
+        assert_eq!(seed(day), seed(day));
+        assert_ne!(seed(day), seed(day.succ_opt().unwrap()));
+        assert!(DueOrder::parse(Some("alphabetical"), None, day).is_err());
+    }
+}
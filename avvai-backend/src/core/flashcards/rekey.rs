@@ -0,0 +1,239 @@
+use rusqlite::{params, OptionalExtension};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use tokio::task;
+
+use crate::core::db::Db;
+use super::VocabularyCard;
+
+/// A stored card state whose id matches no current vocabulary entry.
+#[derive(Serialize)]
+pub struct OrphanedCard {
+    pub card_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_review: Option<String>,
+    pub review_count: i64,
+}
+
+/// Renames `fsrs_cards` and `fsrs_review_log` rows still keyed on the old
+/// `{lesson}:vocab:{index}` ids to the entry now at that position. Ids whose
+/// target already has a state for the same learner, or that could name more
+/// than one entry, are left alone and show up as orphans.
+/// Idempotent: once re-keyed, no rows match a legacy id any more.
+pub fn rekey_legacy_cards(db: &Db, cards: &[VocabularyCard]) -> Result<usize, String> {
+    // Older ids restarted the index in every vocabulary section, so a legacy
+    // id can match entries in several sections.
+    let mut targets: HashMap<&str, Vec<&str>> = HashMap::new();
+    for card in cards {
+        if let Some(legacy_id) = &card.legacy_id {
+            targets
+                .entry(legacy_id.as_str())
+                .or_default()
+                .push(card.card.id.as_str());
+        }
+    }
+
+    let mut conn = db.write()?;
+    let tx = conn
+        .transaction()
+        .map_err(|err| format!("Failed to start transaction: {err}"))?;
+
+    let stored = {
+        let mut stmt = tx
+            .prepare("SELECT user_id, card_id FROM fsrs_cards WHERE card_id LIKE '%:vocab:%'")
+            .map_err(|err| format!("Failed to prepare statement: {err}"))?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))
+            .map_err(|err| format!("Failed to query card states: {err}"))?;
+        let mut stored = Vec::new();
+        for row in rows {
+            stored.push(row.map_err(|err| format!("Failed to read row: {err}"))?);
+        }
+        stored
+    };
+
+    let mut rekeyed = 0;
+    for (user_id, old_id) in stored {
+        let new_id = match targets.get(old_id.as_str()).map(Vec::as_slice) {
+            Some([new_id]) => *new_id,
+            Some(candidates) => {
+                eprintln!(
+                    "Not re-keying {old_id} for user {user_id}: it could be any of {}",
+                    candidates.join(", ")
+                );
+                continue;
+            }
+            None => continue,
+        };
+        let taken = tx
+            .query_row(
+                "SELECT 1 FROM fsrs_cards WHERE user_id = ?1 AND card_id = ?2",
+                params![user_id, new_id],
+                |_| Ok(()),
+            )
+            .optional()
+            .map_err(|err| format!("Failed to read card state: {err}"))?
+            .is_some();
+        if taken {
+            continue;
+        }
+
+        tx.execute(
+            "UPDATE fsrs_cards SET card_id = ?2 WHERE user_id = ?3 AND card_id = ?1",
+            params![old_id, new_id, user_id],
+        )
+        .map_err(|err| format!("Failed to re-key card state: {err}"))?;
+        tx.execute(
+            "UPDATE fsrs_review_log SET card_id = ?2 WHERE user_id = ?3 AND card_id = ?1",
+            params![old_id, new_id, user_id],
+        )
+        .map_err(|err| format!("Failed to re-key review log: {err}"))?;
+        rekeyed += 1;
+    }
+
+    tx.commit()
+        .map_err(|err| format!("Failed to commit re-keying: {err}"))?;
+    Ok(rekeyed)
+}
+
+pub async fn find_orphans(
+    db: Db,
+    user_id: i64,
+    cards: &[VocabularyCard],
+) -> Result<Vec<OrphanedCard>, String> {
+    let known = cards
+        .iter()
+        .map(|card| card.card.id.clone())
+        .collect::<HashSet<_>>();
+
+    task::spawn_blocking(move || {
+        let conn = db.read()?;
+        let orphans = {
+            let mut stmt = conn
+                .prepare(
+                    "
+                    SELECT cards.card_id, cards.last_review, COUNT(log.id)
+                    FROM fsrs_cards AS cards
+                    LEFT JOIN fsrs_review_log AS log
+                        ON log.user_id = cards.user_id AND log.card_id = cards.card_id
+                    WHERE cards.user_id = ?1
+                    GROUP BY cards.card_id
+                    ORDER BY cards.card_id
+                    ",
+                )
+                .map_err(|err| format!("Failed to prepare statement: {err}"))?;
+            let rows = stmt
+                .query_map([user_id], |row| {
+                    Ok(OrphanedCard {
+                        card_id: row.get(0)?,
+                        last_review: row.get(1)?,
+                        review_count: row.get(2)?,
+                    })
+                })
+                .map_err(|err| format!("Failed to query card states: {err}"))?;
+
+            let mut orphans = Vec::new();
+            for row in rows {
+                let card = row.map_err(|err| format!("Failed to read row: {err}"))?;
+                if !known.contains(&card.card_id) {
+                    orphans.push(card);
+                }
+            }
+            orphans
+        };
+        drop(conn);
+
+        Ok(orphans)
+    })
+    .await
+    .map_err(|_| "Failed to join blocking task".to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::flashcards::{lesson_cards, tests::fixture_lesson};
+    use crate::core::lesson::Lesson;
+
+    fn insert_reviewed(db: &Db, user_id: i64, card_id: &str) {
+        let conn = db.write().unwrap();
+        conn.execute(
+            "
+            INSERT INTO fsrs_cards (user_id, card_id, stability, difficulty, interval_days)
+            VALUES (?1, ?2, 10.0, 5.0, 10)
+            ",
+            params![user_id, card_id],
+        )
+        .unwrap();
+        conn.execute(
+            "
+            INSERT INTO fsrs_review_log (user_id, card_id, rating, reviewed_at, elapsed_days, stability_after, difficulty_after, interval_days, due_date)
+            VALUES (?1, ?2, 3, '2025-01-01T00:00:00+00:00', 0, 10.0, 5.0, 10, '2025-01-11T00:00:00+00:00')
+            ",
+            params![user_id, card_id],
+        )
+        .unwrap();
+    }
+
+    fn card_ids(db: &Db, table: &str) -> Vec<String> {
+        let conn = db.read().unwrap();
+        let mut stmt = conn
+            .prepare(&format!("SELECT card_id FROM {table} ORDER BY card_id"))
+            .unwrap();
+        stmt.query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn moves_state_and_history_to_the_entry_at_that_position() {
+        let db = Db::temporary();
+        let cards = lesson_cards(&fixture_lesson());
+        insert_reviewed(&db, 1, "fixture:vocab:1");
+
+        assert_eq!(rekey_legacy_cards(&db, &cards).unwrap(), 1);
+        assert_eq!(card_ids(&db, "fsrs_cards"), ["fixture:aram"]);
+        assert_eq!(card_ids(&db, "fsrs_review_log"), ["fixture:aram"]);
+        assert_eq!(rekey_legacy_cards(&db, &cards).unwrap(), 0);
+    }
+
+    #[test]
+    fn leaves_ids_whose_target_already_has_a_state() {
+        let db = Db::temporary();
+        let cards = lesson_cards(&fixture_lesson());
+        insert_reviewed(&db, 1, "fixture:vocab:0");
+        insert_reviewed(&db, 1, "fixture:anbu");
+        insert_reviewed(&db, 2, "fixture:vocab:0");
+
+        assert_eq!(rekey_legacy_cards(&db, &cards).unwrap(), 1);
+        assert_eq!(
+            card_ids(&db, "fsrs_cards"),
+            ["fixture:anbu", "fixture:anbu", "fixture:vocab:0"]
+        );
+    }
+
+    #[test]
+    fn skips_ids_that_could_name_entries_in_several_sections() {
+        let lesson: Lesson = serde_json::from_value(serde_json::json!({
+            "id": "fixture",
+            "title": "Fixture",
+            "description": "",
+            "sections": [
+                {"type": "vocabulary", "entries": [{"id": "anbu", "word": "அன்பு", "meaning": "love"}]},
+                {"type": "vocabulary", "entries": [
+                    {"id": "aram", "word": "அறம்", "meaning": "virtue"},
+                    {"id": "ulagam", "word": "உலகம்", "meaning": "world"}
+                ]}
+            ]
+        }))
+        .unwrap();
+        let db = Db::temporary();
+        let cards = lesson_cards(&lesson);
+        insert_reviewed(&db, 1, "fixture:vocab:0");
+        insert_reviewed(&db, 1, "fixture:vocab:1");
+
+        assert_eq!(rekey_legacy_cards(&db, &cards).unwrap(), 1);
+        assert_eq!(card_ids(&db, "fsrs_cards"), ["fixture:ulagam", "fixture:vocab:0"]);
+    }
+}
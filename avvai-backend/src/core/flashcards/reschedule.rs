@@ -0,0 +1,301 @@
+use chrono::{DateTime, Duration, Utc};
+use fsrs::{FSRSItem, FSRSReview, MemoryState, FSRS};
+use rusqlite::params;
+use serde::Serialize;
+use std::collections::HashMap;
+use tokio::task;
+
+use crate::core::db::Db;
+use super::{scheduler::StudyClock, CardState, Deck, StoredState, VocabularyCard};
+
+const LARGEST_MOVES: usize = 10;
+
+/// A review card whose due date changes under the current settings.
+#[derive(Serialize, Clone)]
+pub struct MovedCard {
+    pub id: String,
+    pub front: String,
+    pub due_before: String,
+    pub due_after: String,
+    pub interval_before: i64,
+    pub interval_after: i64,
+    /// Study days the card moves by; positive when it moves later.
+    pub shift_days: i64,
+}
+
+#[derive(Serialize)]
+pub struct RescheduleReport {
+    pub dry_run: bool,
+    pub desired_retention: f32,
+    /// Review cards that were recomputed.
+    pub cards: usize,
+    pub earlier: usize,
+    pub later: usize,
+    pub unchanged: usize,
+    /// Cards in a (re)learning step, which keep their step timing.
+    pub skipped_learning: usize,
+    /// The cards that move furthest, in either direction.
+    pub largest_moves: Vec<MovedCard>,
+    /// Cards actually written; absent on a dry run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated: Option<usize>,
+}
+
+/// A due-date change, keyed on the review it was computed from so a review
+/// landing in between is not overwritten.
+pub struct PlannedMove {
+    card_id: String,
+    last_review: String,
+    due_date: DateTime<Utc>,
+    interval_days: i64,
+}
+
+/// The desired retention each card is rescheduled for.
+pub struct Retention {
+    pub default: f32,
+    /// Decks with a retention override, in deck list order: lesson decks,
+    /// then courses, then filtered decks. A card takes the override of the
+    /// first one that contains it.
+    pub overrides: Vec<Deck>,
+}
+
+impl Retention {
+    fn for_card(&self, card: &VocabularyCard) -> f32 {
+        self.overrides
+            .iter()
+            .filter(|deck| deck.contains(card))
+            .find_map(|deck| deck.desired_retention)
+            .unwrap_or(self.default)
+    }
+}
+
+/// A card's logged reviews, oldest first, ready to replay. Cards whose log
+/// starts after their first review replay from the memory state recorded
+/// before the first logged one.
+pub struct ReviewHistory {
+    start: Option<MemoryState>,
+    reviews: Vec<FSRSReview>,
+}
+
+/// Recomputes each review card's interval the same way a review schedules
+/// the next one, counted from its last review. The memory state is replayed
+/// from the card's review history under the current parameters, since the
+/// stored one came from the parameters in use at the time.
+pub fn plan(
+    cards: &[VocabularyCard],
+    states: &HashMap<String, StoredState>,
+    histories: &HashMap<String, ReviewHistory>,
+    fsrs: &FSRS,
+    retention: &Retention,
+    clock: StudyClock,
+    dry_run: bool,
+) -> (Vec<PlannedMove>, RescheduleReport) {
+    let mut report = RescheduleReport {
+        dry_run,
+        desired_retention: retention.default,
+        cards: 0,
+        earlier: 0,
+        later: 0,
+        unchanged: 0,
+        skipped_learning: 0,
+        largest_moves: Vec::new(),
+        updated: None,
+    };
+    let mut moves = Vec::new();
+    let mut moved = Vec::new();
+
+    for card in cards {
+        let Some(stored) = states.get(&card.card.id) else {
+            continue;
+        };
+        if stored.state.is_learning() {
+            report.skipped_learning += 1;
+            continue;
+        }
+        let (CardState::Review, Some(last_review), Some(due_before)) =
+            (stored.state, stored.last_review, stored.due_date)
+        else {
+            continue;
+        };
+
+        let stability = histories
+            .get(&card.card.id)
+            .and_then(|history| {
+                let item = FSRSItem {
+                    reviews: history.reviews.clone(),
+                };
+                fsrs.memory_state(item, history.start).ok()
+            })
+            .map_or(stored.stability, |memory| memory.stability);
+        #[allow(clippy::cast_possible_truncation)]
+        let interval_days = fsrs
+            .next_interval(Some(stability), retention.for_card(card), 3)
+            .round()
+            .max(1.0) as i64;
+        let due_date = last_review + Duration::days(interval_days);
+        report.cards += 1;
+
+        let shift_days = (clock.study_day(due_date) - clock.study_day(due_before)).num_days();
+        match shift_days.cmp(&0) {
+            std::cmp::Ordering::Less => report.earlier += 1,
+            std::cmp::Ordering::Greater => report.later += 1,
+            std::cmp::Ordering::Equal => {
+                report.unchanged += 1;
+                continue;
+            }
+        }
+
+        moved.push(MovedCard {
+            id: card.card.id.clone(),
+            front: card.card.front.clone(),
+            due_before: due_before.to_rfc3339(),
+            due_after: due_date.to_rfc3339(),
+            interval_before: stored.interval_days,
+            interval_after: interval_days,
+            shift_days,
+        });
+        moves.push(PlannedMove {
+            card_id: card.card.id.clone(),
+            last_review: last_review.to_rfc3339(),
+            due_date,
+            interval_days,
+        });
+    }
+
+    moved.sort_by(|a, b| b.shift_days.abs().cmp(&a.shift_days.abs()).then_with(|| a.id.cmp(&b.id)));
+    moved.truncate(LARGEST_MOVES);
+    report.largest_moves = moved;
+
+    (moves, report)
+}
+
+pub async fn load_histories(db: Db, user_id: i64) -> Result<HashMap<String, ReviewHistory>, String> {
+    task::spawn_blocking(move || {
+        let conn = db.read()?;
+        let mut stmt = conn
+            .prepare(
+                "
+                SELECT card_id, rating, elapsed_days, stability_before, difficulty_before
+                FROM fsrs_review_log
+                WHERE user_id = ?1
+                ORDER BY card_id ASC, id ASC
+                ",
+            )
+            .map_err(|err| format!("Failed to prepare statement: {err}"))?;
+        let rows = stmt
+            .query_map([user_id], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, u32>(1)?,
+                    row.get::<_, u32>(2)?,
+                    row.get::<_, Option<f32>>(3)?,
+                    row.get::<_, Option<f32>>(4)?,
+                ))
+            })
+            .map_err(|err| format!("Failed to query review log: {err}"))?;
+
+        let mut histories: HashMap<String, ReviewHistory> = HashMap::new();
+        for row in rows {
+            let (card_id, rating, elapsed_days, stability, difficulty) =
+                row.map_err(|err| format!("Failed to read row: {err}"))?;
+            let history = histories.entry(card_id).or_insert_with(|| ReviewHistory {
+                start: stability
+                    .zip(difficulty)
+                    .map(|(stability, difficulty)| MemoryState {
+                        stability,
+                        difficulty,
+                    }),
+                reviews: Vec::new(),
+            });
+            let first = history.reviews.is_empty() && history.start.is_none();
+            history.reviews.push(FSRSReview {
+                rating,
+                delta_t: if first { 0 } else { elapsed_days },
+            });
+        }
+        Ok(histories)
+    })
+    .await
+    .map_err(|_| "Failed to join blocking task".to_string())?
+}
+
+/// Writes the new due dates in one transaction, skipping cards reviewed
+/// since the plan was made. Returns how many cards were updated.
+pub async fn apply(db: Db, user_id: i64, moves: Vec<PlannedMove>) -> Result<usize, String> {
+    task::spawn_blocking(move || {
+        let mut conn = db.write()?;
+        let tx = conn
+            .transaction()
+            .map_err(|err| format!("Failed to start transaction: {err}"))?;
+        let mut updated = 0;
+        for planned in &moves {
+            updated += tx
+                .execute(
+                    "
+                    UPDATE fsrs_cards SET due_date = ?3, interval_days = ?4
+                    WHERE user_id = ?5 AND card_id = ?1 AND last_review = ?2 AND state = 'review'
+                    ",
+                    params![
+                        planned.card_id,
+                        planned.last_review,
+                        planned.due_date.to_rfc3339(),
+                        planned.interval_days,
+                        user_id,
+                    ],
+                )
+                .map_err(|err| format!("Failed to reschedule card: {err}"))?;
+        }
+        tx.commit()
+            .map_err(|err| format!("Failed to commit reschedule: {err}"))?;
+        Ok(updated)
+    })
+    .await
+    .map_err(|_| "Failed to join blocking task".to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::flashcards::{
+        lesson_cards, load_states, review_card,
+        tests::{fixture_card_id, fixture_lesson, test_state},
+    };
+    use fsrs::DEFAULT_PARAMETERS;
+
+    fn retention(default: f32) -> Retention {
+        Retention {
+            default,
+            overrides: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn replays_history_instead_of_trusting_the_stored_memory() {
+        let state = test_state();
+        let card_id = fixture_card_id();
+        review_card(&state, 1, &card_id, 4, None, None).await.unwrap();
+
+        let cards = lesson_cards(&fixture_lesson());
+        let mut states = load_states(state.db.clone(), 1).await.unwrap();
+        let histories = load_histories(state.db.clone(), 1).await.unwrap();
+        assert_eq!(histories[&card_id].reviews.len(), 1);
+        let fsrs = FSRS::new(Some(&DEFAULT_PARAMETERS)).unwrap();
+        let clock = StudyClock::new(0);
+
+        let (moves, report) = plan(&cards, &states, &histories, &fsrs, &retention(0.97), clock, true);
+        assert_eq!((report.cards, report.earlier, report.later), (1, 1, 0));
+        let replayed = moves[0].interval_days;
+
+        // A stale stored stability is ignored when the history is there...
+        states.get_mut(&card_id).unwrap().stability = 1000.0;
+        let (moves, _) = plan(&cards, &states, &histories, &fsrs, &retention(0.97), clock, true);
+        assert_eq!(moves[0].interval_days, replayed);
+
+        // ...and used only when it is not.
+        let (moves, _) = plan(&cards, &states, &HashMap::new(), &fsrs, &retention(0.97), clock, true);
+        assert!(moves[0].interval_days > replayed);
+
+        let (_, report) = plan(&cards, &states, &histories, &fsrs, &retention(0.75), clock, true);
+        assert_eq!((report.earlier, report.later), (0, 1));
+    }
+}
@@ -0,0 +1,236 @@
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, Utc};
+use fsrs::{ItemState, NextStates};
+use serde::{Deserialize, Serialize};
+
+/// Local hour at which a new study day begins, so late-night sessions count
+/// towards the day they started in.
+pub const DAY_ROLLOVER_HOUR: i64 = 4;
+/// Learners' offsets from UTC range from UTC-12:00 to UTC+14:00.
+pub const MIN_UTC_OFFSET_MINUTES: i32 = -12 * 60;
+pub const MAX_UTC_OFFSET_MINUTES: i32 = 14 * 60;
+
+/// Learning cards due within this window are shown straight away rather than
+/// making the learner wait out the last few minutes of a step.
+pub const LEARN_AHEAD_MINUTES: i64 = 20;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CardState {
+    New,
+    Learning,
+    Review,
+    Relearning,
+}
+
+impl CardState {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::New => "new",
+            Self::Learning => "learning",
+            Self::Review => "review",
+            Self::Relearning => "relearning",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "new" => Self::New,
+            "learning" => Self::Learning,
+            "relearning" => Self::Relearning,
+            _ => Self::Review,
+        }
+    }
+
+    pub const fn is_learning(self) -> bool {
+        matches!(self, Self::Learning | Self::Relearning)
+    }
+}
+
+/// Where a card sits in the state machine before a review.
+pub struct CardPosition {
+    pub state: CardState,
+    pub step: usize,
+}
+
+/// Outcome of scheduling one review.
+pub struct Scheduled {
+    pub item: ItemState,
+    pub state: CardState,
+    pub step: usize,
+    pub interval_days: i64,
+    pub due: DateTime<Utc>,
+}
+
+/// Where a learner's study days begin: at [`DAY_ROLLOVER_HOUR`] in their
+/// local time, given as an offset from UTC.
+#[derive(Clone, Copy, Default)]
+pub struct StudyClock {
+    utc_offset: Duration,
+}
+
+impl StudyClock {
+    pub fn new(utc_offset_minutes: i32) -> Self {
+        Self {
+            utc_offset: Duration::minutes(i64::from(utc_offset_minutes)),
+        }
+    }
+
+    fn rollover(self) -> Duration {
+        Duration::hours(DAY_ROLLOVER_HOUR) - self.utc_offset
+    }
+
+    /// Day number used for elapsed-day counting and daily limits.
+    pub fn study_day(self, at: DateTime<Utc>) -> NaiveDate {
+        (at - self.rollover()).date_naive()
+    }
+
+    /// The instant the study day `day` begins.
+    pub fn start_of(self, day: NaiveDate) -> DateTime<Utc> {
+        day.and_time(NaiveTime::MIN).and_utc() + self.rollover()
+    }
+
+    /// The instant the current study day began.
+    pub fn day_start(self, now: DateTime<Utc>) -> DateTime<Utc> {
+        self.start_of(self.study_day(now))
+    }
+
+    /// Whole study days between two instants. A review late in the evening
+    /// followed by one the next morning counts as one day, however few hours
+    /// apart they are.
+    pub fn elapsed_days(self, last: DateTime<Utc>, now: DateTime<Utc>) -> u32 {
+        let days = (self.study_day(now) - self.study_day(last)).num_days().max(0);
+        u32::try_from(days).unwrap_or(u32::MAX)
+    }
+}
+
+/// Applies a rating to a card. The FSRS memory state is always updated, but
+/// new, learning and relearning cards step through minute-based delays before
+/// (re)graduating to day-based review intervals.
+pub fn schedule(
+    position: &CardPosition,
+    rating: u32,
+    next_states: NextStates,
+    learning_steps: &[u32],
+    relearning_steps: &[u32],
+    now: DateTime<Utc>,
+) -> Scheduled {
+    let item = match rating {
+        1 => next_states.again,
+        2 => next_states.hard,
+        4 => next_states.easy,
+        _ => next_states.good,
+    };
+
+    let (steps, stepping_state) = match position.state {
+        CardState::New | CardState::Learning => (learning_steps, CardState::Learning),
+        CardState::Relearning => (relearning_steps, CardState::Relearning),
+        CardState::Review => {
+            if rating == 1 && !relearning_steps.is_empty() {
+                return in_step(item, CardState::Relearning, 0, relearning_steps[0], now);
+            }
+            return graduate(item, now);
+        }
+    };
+
+    if steps.is_empty() || rating == 4 {
+        return graduate(item, now);
+    }
+
+    let current = if position.state == CardState::New {
+        0
+    } else {
+        position.step.min(steps.len() - 1)
+    };
+
+    match rating {
+        1 => in_step(item, stepping_state, 0, steps[0], now),
+        2 => {
+            let delay = if current == 0 {
+                hard_delay(steps)
+            } else {
+                steps[current]
+            };
+            in_step(item, stepping_state, current, delay, now)
+        }
+        _ => {
+            let next = if position.state == CardState::New {
+                1
+            } else {
+                current + 1
+            };
+            match steps.get(next) {
+                Some(delay) => in_step(item, stepping_state, next, *delay, now),
+                None => graduate(item, now),
+            }
+        }
+    }
+}
+
+/// "Hard" on the first step waits halfway between the first two steps, or
+/// half as long again as the only step.
+fn hard_delay(steps: &[u32]) -> u32 {
+    match steps {
+        [first, second, ..] => (first + second) / 2,
+        [only] => only + only / 2,
+        [] => 0,
+    }
+}
+
+fn in_step(
+    item: ItemState,
+    state: CardState,
+    step: usize,
+    minutes: u32,
+    now: DateTime<Utc>,
+) -> Scheduled {
+    Scheduled {
+        item,
+        state,
+        step,
+        interval_days: 0,
+        due: now + Duration::minutes(i64::from(minutes)),
+    }
+}
+
+fn graduate(item: ItemState, now: DateTime<Utc>) -> Scheduled {
+    #[allow(clippy::cast_possible_truncation)]
+    let interval_days = item.interval.round().max(1.0) as i64;
+    Scheduled {
+        item,
+        state: CardState::Review,
+        step: 0,
+        interval_days,
+        due: now + Duration::days(interval_days),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(timestamp: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(timestamp).unwrap().to_utc()
+    }
+
+    #[test]
+    fn study_day_rolls_over_at_local_rollover_hour() {
+        // 04:00 in India (UTC+05:30) is 22:30 UTC the previous evening.
+        let india = StudyClock::new(330);
+        let today = NaiveDate::from_ymd_opt(2025, 3, 10).unwrap();
+        assert_eq!(india.study_day(at("2025-03-10T22:29:00Z")), today);
+        assert_eq!(india.study_day(at("2025-03-10T22:30:00Z")), today.succ_opt().unwrap());
+        assert_eq!(india.day_start(at("2025-03-11T08:00:00Z")), at("2025-03-10T22:30:00Z"));
+
+        let utc = StudyClock::default();
+        assert_eq!(utc.study_day(at("2025-03-10T22:30:00Z")), today);
+    }
+
+    #[test]
+    fn elapsed_days_counts_local_study_days() {
+        // 23:00 and 06:00 the next morning in New York (UTC-05:00) are two
+        // study days, though only seven hours apart.
+        let (evening, morning) = (at("2025-03-11T04:00:00Z"), at("2025-03-11T11:00:00Z"));
+        assert_eq!(StudyClock::new(-300).elapsed_days(evening, morning), 1);
+        assert_eq!(StudyClock::default().elapsed_days(evening, morning), 0);
+    }
+}
@@ -0,0 +1,206 @@
+use chrono::{DateTime, Duration, Utc};
+use fsrs::{Card, SimulatorConfig};
+use serde::Serialize;
+use std::collections::HashMap;
+
+use super::{
+    flags::CardFlags, scheduler::StudyClock, CardState, FlashcardSettings, LeechAction, StoredState,
+    VocabularyCard,
+};
+
+pub const DEFAULT_SIMULATION_DAYS: usize = 30;
+pub const MAX_SIMULATION_DAYS: usize = 365;
+/// Fixed so the same inputs always give the same projection.
+pub const SIMULATION_SEED: u64 = 42;
+
+/// What to simulate: a candidate retention, optionally with candidate
+/// parameters, over the next `days` study days.
+pub struct SimulationRequest {
+    pub deck: Option<String>,
+    pub desired_retention: f32,
+    pub parameters: Option<Vec<f32>>,
+    pub days: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct SimulatedDay {
+    /// Study day as `YYYY-MM-DD`.
+    pub date: String,
+    pub reviews: usize,
+    pub new: usize,
+    /// Estimated study time, from Anki's average seconds per rating.
+    pub minutes: f32,
+    /// Share of that day's reviews expected to be passed.
+    pub retention: Option<f32>,
+    /// Expected number of cards the learner would recall that day.
+    pub memorised: f32,
+}
+
+#[derive(Serialize)]
+pub struct Simulation {
+    pub desired_retention: f32,
+    pub days: usize,
+    /// Cards taken into account: the learner's current cards plus the new
+    /// cards still to introduce, leaving out suspended ones.
+    pub cards: usize,
+    pub total_reviews: usize,
+    pub total_new: usize,
+    pub total_minutes: f32,
+    pub average_daily_minutes: f32,
+    pub daily: Vec<SimulatedDay>,
+}
+
+/// Builds the simulator input from the learner's cards: one entry per card
+/// that has a memory state, positioned relative to today, with every other
+/// card introduced at the daily new-card limit.
+pub fn build_input(
+    cards: &[VocabularyCard],
+    states: &HashMap<String, StoredState>,
+    flags: &HashMap<String, CardFlags>,
+    lapses: &HashMap<String, usize>,
+    settings: &FlashcardSettings,
+    days: usize,
+    now: DateTime<Utc>,
+) -> (SimulatorConfig, Vec<Card>) {
+    let clock = settings.clock();
+    let today = clock.study_day(now);
+    let cards = cards
+        .iter()
+        .filter(|card| {
+            !flags
+                .get(&card.card.id)
+                .is_some_and(|flags| flags.suspended)
+        })
+        .collect::<Vec<_>>();
+
+    let existing = cards
+        .iter()
+        .enumerate()
+        .filter_map(|(index, card)| {
+            let stored = states
+                .get(&card.card.id)
+                .filter(|stored| stored.state != CardState::New)?;
+            let day_offset = |at: DateTime<Utc>| (clock.study_day(at) - today).num_days();
+            let last_date = stored.last_review.map_or(0, day_offset);
+            let due = stored.due_date.map_or(0, day_offset).max(0);
+            #[allow(clippy::cast_precision_loss)]
+            Some(Card {
+                // Only hooks read the id; positive ones mark existing cards.
+                id: i64::try_from(index + 1).unwrap_or(i64::MAX),
+                difficulty: stored.difficulty,
+                stability: stored.stability,
+                last_date: last_date as f32,
+                due: due as f32,
+                interval: (due - last_date).max(0) as f32,
+                lapses: u32::try_from(lapses.get(&card.card.id).copied().unwrap_or_default())
+                    .unwrap_or(u32::MAX),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let config = SimulatorConfig {
+        deck_size: cards.len(),
+        learn_span: days,
+        // Time is bounded by the daily limits instead.
+        max_cost_perday: f32::MAX,
+        learn_limit: settings.new_cards_per_day,
+        review_limit: settings.reviews_per_day,
+        suspend_after_lapses: (settings.leech_action == LeechAction::Suspend)
+            .then(|| u32::try_from(settings.leech_threshold).unwrap_or(u32::MAX)),
+        learning_step_count: settings.learning_steps.len(),
+        relearning_step_count: settings.relearning_steps.len(),
+        ..SimulatorConfig::default()
+    };
+
+    (config, existing)
+}
+
+pub fn summarise(
+    result: &fsrs::SimulationResult,
+    config: &SimulatorConfig,
+    desired_retention: f32,
+    clock: StudyClock,
+    now: DateTime<Utc>,
+) -> Simulation {
+    let today = clock.study_day(now);
+    let daily = (0..config.learn_span)
+        .map(|day| {
+            let reviews = result.review_cnt_per_day[day];
+            let correct = result.correct_cnt_per_day[day];
+            #[allow(clippy::cast_precision_loss)]
+            SimulatedDay {
+                date: (today + Duration::days(i64::try_from(day).unwrap_or_default())).to_string(),
+                reviews,
+                new: result.learn_cnt_per_day[day],
+                minutes: result.cost_per_day[day] / 60.0,
+                retention: (reviews > 0).then(|| correct as f32 / reviews as f32),
+                memorised: result.memorized_cnt_per_day[day],
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let total_minutes = daily.iter().map(|day| day.minutes).sum::<f32>();
+    #[allow(clippy::cast_precision_loss)]
+    let average_daily_minutes = total_minutes / config.learn_span.max(1) as f32;
+
+    Simulation {
+        desired_retention,
+        days: config.learn_span,
+        cards: config.deck_size,
+        total_reviews: daily.iter().map(|day| day.reviews).sum(),
+        total_new: daily.iter().map(|day| day.new).sum(),
+        total_minutes,
+        average_daily_minutes,
+        daily,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::flashcards::{
+        get_settings, lesson_cards,
+        tests::{fixture_lesson, test_state},
+    };
+    use fsrs::DEFAULT_PARAMETERS;
+
+    #[tokio::test]
+    async fn same_inputs_give_the_same_projection() {
+        let settings = get_settings(&test_state(), 1, None).await.unwrap();
+        let now = Utc::now();
+        let cards = lesson_cards(&fixture_lesson());
+        let states = HashMap::from([(
+            cards[0].card.id.clone(),
+            StoredState {
+                stability: 3.0,
+                difficulty: 5.0,
+                last_review: Some(now - Duration::days(3)),
+                due_date: Some(now + Duration::days(1)),
+                interval_days: 4,
+                state: CardState::Review,
+                step: 0,
+            },
+        )]);
+        let flags = HashMap::from([(
+            cards[1].card.id.clone(),
+            CardFlags {
+                suspended: true,
+                buried_until: None,
+            },
+        )]);
+
+        let run = || {
+            let (config, existing) =
+                build_input(&cards, &states, &flags, &HashMap::new(), &settings, 60, now);
+            let result =
+                fsrs::simulate(&config, &DEFAULT_PARAMETERS, 0.9, Some(SIMULATION_SEED), Some(existing))
+                    .unwrap();
+            serde_json::to_value(summarise(&result, &config, 0.9, settings.clock(), now)).unwrap()
+        };
+
+        let first = run();
+        assert_eq!(first, run());
+        assert_eq!(first["cards"], cards.len() - 1, "suspended cards are left out");
+        assert_eq!(first["daily"].as_array().unwrap().len(), 60);
+    }
+}
@@ -0,0 +1,309 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use tokio::task;
+
+use crate::core::db::Db;
+use super::{scheduler::StudyClock, CardState, CardTemplate, StoredState, VocabularyCard};
+
+pub const DEFAULT_RETENTION_DAYS: u32 = 30;
+pub const MAX_RETENTION_DAYS: u32 = 365;
+const FORECAST_DAYS: i64 = 30;
+const HARDEST_LIMIT: usize = 10;
+
+#[derive(Serialize, Default)]
+pub struct StateCounts {
+    pub new: usize,
+    pub learning: usize,
+    pub review: usize,
+    pub relearning: usize,
+    pub total: usize,
+}
+
+/// Pass rate of review-state cards on one study day. Learning steps are left
+/// out because they say little about long-term memory.
+#[derive(Serialize)]
+pub struct RetentionDay {
+    /// Study day as `YYYY-MM-DD`.
+    pub date: String,
+    pub reviews: usize,
+    pub passed: usize,
+    pub rate: Option<f32>,
+}
+
+#[derive(Serialize)]
+pub struct ForecastDay {
+    pub date: String,
+    /// Cards falling due that day; the first day includes overdue cards.
+    pub due: usize,
+}
+
+#[derive(Serialize)]
+pub struct HardCard {
+    pub id: String,
+    pub template: CardTemplate,
+    pub front: String,
+    pub back: String,
+    pub difficulty: f32,
+    pub stability: f32,
+    /// Times the card was forgotten after graduating.
+    pub lapses: usize,
+}
+
+#[derive(Serialize)]
+pub struct FlashcardStats {
+    pub cards: StateCounts,
+    pub retention: Vec<RetentionDay>,
+    pub forecast: Vec<ForecastDay>,
+    pub average_stability: Option<f32>,
+    pub average_difficulty: Option<f32>,
+    pub hardest: Vec<HardCard>,
+}
+
+/// A review of a card that was in the review state, reduced to what the
+/// statistics need.
+pub struct LoggedReview {
+    card_id: String,
+    rating: u32,
+    reviewed_at: DateTime<Utc>,
+}
+
+pub fn compute(
+    cards: &[VocabularyCard],
+    states: &HashMap<String, StoredState>,
+    log: &[LoggedReview],
+    retention_days: u32,
+    clock: StudyClock,
+    now: DateTime<Utc>,
+) -> FlashcardStats {
+    let known = cards
+        .iter()
+        .map(|card| card.card.id.as_str())
+        .collect::<HashSet<_>>();
+    let today = clock.study_day(now);
+
+    let mut counts = StateCounts::default();
+    let mut forecast = BTreeMap::new();
+    for offset in 0..FORECAST_DAYS {
+        forecast.insert(today + Duration::days(offset), 0);
+    }
+    let (mut stability_sum, mut difficulty_sum, mut memorised) = (0.0_f64, 0.0_f64, 0_u32);
+
+    for card in cards {
+        let stored = states
+            .get(&card.card.id)
+            .filter(|stored| stored.state != CardState::New);
+        let Some(stored) = stored else {
+            counts.new += 1;
+            continue;
+        };
+
+        match stored.state {
+            CardState::New => counts.new += 1,
+            CardState::Learning => counts.learning += 1,
+            CardState::Review => counts.review += 1,
+            CardState::Relearning => counts.relearning += 1,
+        }
+        stability_sum += f64::from(stored.stability);
+        difficulty_sum += f64::from(stored.difficulty);
+        memorised += 1;
+
+        if let Some(due) = stored.due_date {
+            let day = clock.study_day(due).max(today);
+            if let Some(count) = forecast.get_mut(&day) {
+                *count += 1;
+            }
+        }
+    }
+    counts.total = cards.len();
+
+    let first_day = today - Duration::days(i64::from(retention_days.max(1)) - 1);
+    let mut retention = BTreeMap::new();
+    let mut day = first_day;
+    while day <= today {
+        retention.insert(day, (0, 0));
+        day += Duration::days(1);
+    }
+    let mut lapses: HashMap<&str, usize> = HashMap::new();
+    for review in log.iter().filter(|review| known.contains(review.card_id.as_str())) {
+        if review.rating == 1 {
+            *lapses.entry(review.card_id.as_str()).or_default() += 1;
+        }
+        if let Some((reviews, passed)) = retention.get_mut(&clock.study_day(review.reviewed_at)) {
+            *reviews += 1;
+            if review.rating > 1 {
+                *passed += 1;
+            }
+        }
+    }
+
+    let mut hardest = cards
+        .iter()
+        .filter_map(|card| {
+            let stored = states.get(&card.card.id)?;
+            (stored.state != CardState::New).then(|| HardCard {
+                id: card.card.id.clone(),
+                template: card.card.template,
+                front: card.card.front.clone(),
+                back: card.card.back.clone(),
+                difficulty: stored.difficulty,
+                stability: stored.stability,
+                lapses: lapses.get(card.card.id.as_str()).copied().unwrap_or_default(),
+            })
+        })
+        .collect::<Vec<_>>();
+    hardest.sort_by(|a, b| {
+        b.difficulty
+            .total_cmp(&a.difficulty)
+            .then(b.lapses.cmp(&a.lapses))
+            .then(a.stability.total_cmp(&b.stability))
+    });
+    hardest.truncate(HARDEST_LIMIT);
+
+    #[allow(clippy::cast_possible_truncation)]
+    let average = |sum: f64| (memorised > 0).then(|| (sum / f64::from(memorised)) as f32);
+
+    FlashcardStats {
+        cards: counts,
+        retention: retention
+            .into_iter()
+            .map(|(date, (reviews, passed))| RetentionDay {
+                date: date.to_string(),
+                reviews,
+                passed,
+                #[allow(clippy::cast_precision_loss)]
+                rate: (reviews > 0).then(|| passed as f32 / reviews as f32),
+            })
+            .collect(),
+        forecast: forecast
+            .into_iter()
+            .map(|(date, due)| ForecastDay {
+                date: date.to_string(),
+                due,
+            })
+            .collect(),
+        average_stability: average(stability_sum),
+        average_difficulty: average(difficulty_sum),
+        hardest,
+    }
+}
+
+/// Reviews of review-state cards, for lapse counts and the retention window.
+/// Lapses count over the whole history, so no date filter is applied.
+pub async fn load_log(db: Db, user_id: i64) -> Result<Vec<LoggedReview>, String> {
+    task::spawn_blocking(move || {
+        let conn = db.read()?;
+        let reviews = {
+            let mut stmt = conn
+                .prepare(
+                    "
+                    SELECT card_id, rating, reviewed_at
+                    FROM fsrs_review_log
+                    WHERE user_id = ?1 AND state_before = 'review'
+                    ORDER BY id
+                    ",
+                )
+                .map_err(|err| format!("Failed to prepare statement: {err}"))?;
+            let rows = stmt
+                .query_map([user_id], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, u32>(1)?,
+                        row.get::<_, String>(2)?,
+                    ))
+                })
+                .map_err(|err| format!("Failed to query review log: {err}"))?;
+
+            let mut reviews = Vec::new();
+            for row in rows {
+                let (card_id, rating, reviewed_at) =
+                    row.map_err(|err| format!("Failed to read row: {err}"))?;
+                let Ok(reviewed_at) = DateTime::parse_from_rfc3339(&reviewed_at) else {
+                    continue;
+                };
+                reviews.push(LoggedReview {
+                    card_id,
+                    rating,
+                    reviewed_at: reviewed_at.with_timezone(&Utc),
+                });
+            }
+            reviews
+        };
+        drop(conn);
+
+        Ok(reviews)
+    })
+    .await
+    .map_err(|_| "Failed to join blocking task".to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::flashcards::{lesson_cards, tests::fixture_lesson};
+    use chrono::TimeZone;
+
+    fn stored(state: CardState, due: DateTime<Utc>, difficulty: f32) -> StoredState {
+        StoredState {
+            stability: 2.0,
+            difficulty,
+            last_review: None,
+            due_date: Some(due),
+            interval_days: 1,
+            state,
+            step: 0,
+        }
+    }
+
+    fn logged(card_id: &str, rating: u32, reviewed_at: DateTime<Utc>) -> LoggedReview {
+        LoggedReview {
+            card_id: card_id.to_string(),
+            rating,
+            reviewed_at,
+        }
+    }
+
+    #[test]
+    fn buckets_counts_forecast_and_retention_by_study_day() {
+        let now = Utc.with_ymd_and_hms(2026, 10, 19, 12, 0, 0).unwrap();
+        let day = Duration::days(1);
+        let cards = lesson_cards(&fixture_lesson());
+        let id = |index: usize| cards[index].card.id.clone();
+        let states = HashMap::from([
+            (id(0), stored(CardState::Review, now - day * 3, 4.0)),
+            (id(1), stored(CardState::Learning, now + day, 6.0)),
+            (id(2), stored(CardState::Review, now + day * 40, 5.0)),
+        ]);
+        let log = [
+            logged(&id(0), 1, now - day),
+            logged(&id(0), 3, now),
+            logged("gone:card", 3, now),
+            logged(&id(1), 3, now - day * 60),
+        ];
+
+        let stats = compute(&cards, &states, &log, 30, StudyClock::new(0), now);
+
+        let counts = &stats.cards;
+        assert_eq!((counts.learning, counts.review, counts.relearning), (1, 2, 0));
+        assert_eq!(counts.new, cards.len() - 3);
+
+        // Overdue cards count towards today; cards beyond the window are left
+        // out.
+        assert_eq!(stats.forecast.len(), 30);
+        assert_eq!(stats.forecast[0].date, "2026-10-19");
+        let due = stats.forecast.iter().map(|day| day.due).collect::<Vec<_>>();
+        assert_eq!((due[0], due[1], due.iter().sum::<usize>()), (1, 1, 2));
+
+        // Reviews of unknown cards and those before the window are ignored.
+        assert_eq!(stats.retention.len(), 30);
+        let today = &stats.retention[29];
+        let yesterday = &stats.retention[28];
+        assert_eq!((today.date.as_str(), today.reviews, today.rate), ("2026-10-19", 1, Some(1.0)));
+        assert_eq!((yesterday.reviews, yesterday.passed, yesterday.rate), (1, 0, Some(0.0)));
+        assert_eq!(stats.retention[0].rate, None);
+
+        let hardest = stats.hardest.iter().map(|card| (card.id.clone(), card.lapses)).collect::<Vec<_>>();
+        assert_eq!(hardest, [(id(1), 0), (id(2), 0), (id(0), 1)]);
+        assert_eq!(stats.average_difficulty, Some(5.0));
+    }
+}
@@ -0,0 +1,142 @@
+use serde::{Deserialize, Serialize};
+
+use super::cloze;
+use crate::core::lesson::VocabularyEntry;
+
+/// The ways one vocabulary entry is tested. Each template is a separate card
+/// with its own schedule.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CardTemplate {
+    /// Tamil word on the front, meaning on the back.
+    Recognition,
+    /// Meaning on the front, Tamil word on the back.
+    Production,
+    /// Pronunciation clip on the front, word and meaning on the back. Only
+    /// generated for entries with audio.
+    Audio,
+    /// A lesson sentence with the word blanked out and the meaning as a
+    /// hint. Only generated when the word, or an inflected form of its
+    /// lemma, appears in the lesson text.
+    Cloze,
+}
+
+impl CardTemplate {
+    pub const ALL: [Self; 4] = [Self::Recognition, Self::Production, Self::Audio, Self::Cloze];
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Recognition => "recognition",
+            Self::Production => "production",
+            Self::Audio => "audio",
+            Self::Cloze => "cloze",
+        }
+    }
+
+    /// Suffix appended to the entry's card id. Recognition has none so its
+    /// history carries over from before templates existed.
+    const fn id_suffix(self) -> Option<&'static str> {
+        match self {
+            Self::Recognition => None,
+            Self::Production => Some("production"),
+            Self::Audio => Some("audio"),
+            Self::Cloze => Some("cloze"),
+        }
+    }
+
+    pub fn card_id(self, base: &str) -> String {
+        self.id_suffix()
+            .map_or_else(|| base.to_string(), |suffix| format!("{base}:{suffix}"))
+    }
+}
+
+/// Front and back text of one generated card.
+pub struct CardFaces {
+    pub template: CardTemplate,
+    pub front: String,
+    pub back: String,
+    pub hint: Option<String>,
+}
+
+/// Every card the templates produce for one entry, in template order.
+pub fn expand(entry: &VocabularyEntry, sentences: &[String]) -> Vec<CardFaces> {
+    let mut faces = vec![
+        CardFaces {
+            template: CardTemplate::Recognition,
+            front: entry.word.clone(),
+            back: entry.meaning.clone(),
+            hint: None,
+        },
+        CardFaces {
+            template: CardTemplate::Production,
+            front: entry.meaning.clone(),
+            back: entry.word.clone(),
+            hint: None,
+        },
+    ];
+
+    if entry.audio.is_some() {
+        faces.push(CardFaces {
+            template: CardTemplate::Audio,
+            front: String::new(),
+            back: format!("{} — {}", entry.word, entry.meaning),
+            hint: None,
+        });
+    }
+
+    if let Some(cloze) = cloze::find_cloze(entry, sentences) {
+        faces.push(CardFaces {
+            template: CardTemplate::Cloze,
+            front: cloze.text,
+            back: cloze.sentence,
+            hint: Some(entry.meaning.clone()),
+        });
+    }
+
+    faces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(word: &str, meaning: &str, audio: Option<&str>) -> VocabularyEntry {
+        VocabularyEntry {
+            id: Some("e".to_string()),
+            word: word.to_string(),
+            meaning: meaning.to_string(),
+            romanisation: None,
+            audio: audio.map(str::to_string),
+            lemma: None,
+            tags: Vec::new(),
+        }
+    }
+
+    fn templates(faces: &[CardFaces]) -> Vec<CardTemplate> {
+        faces.iter().map(|faces| faces.template).collect()
+    }
+
+    #[test]
+    fn every_entry_gets_recognition_and_production() {
+        let faces = expand(&entry("அறம்", "virtue", None), &["நல்ல நாள்.".to_string()]);
+        assert_eq!(templates(&faces), [CardTemplate::Recognition, CardTemplate::Production]);
+        assert_eq!((faces[0].front.as_str(), faces[0].back.as_str()), ("அறம்", "virtue"));
+        assert_eq!((faces[1].front.as_str(), faces[1].back.as_str()), ("virtue", "அறம்"));
+    }
+
+    #[test]
+    fn audio_and_cloze_need_a_clip_and_a_sentence() {
+        let sentences = ["அவள் அன்பை மறந்தாள்.".to_string()];
+        let faces = expand(&entry("அன்பு", "love", Some("anbu.mp3")), &sentences);
+        assert_eq!(templates(&faces), CardTemplate::ALL);
+        assert!(faces[2].front.is_empty());
+        assert_eq!(faces[3].front, "அவள் ____ மறந்தாள்.");
+        assert_eq!(faces[3].hint.as_deref(), Some("love"));
+    }
+
+    #[test]
+    fn only_recognition_keeps_the_bare_entry_id() {
+        let ids = CardTemplate::ALL.map(|template| template.card_id("l:e"));
+        assert_eq!(ids, ["l:e", "l:e:production", "l:e:audio", "l:e:cloze"]);
+    }
+}
@@ -0,0 +1,260 @@
+use rusqlite::{params, OptionalExtension, Transaction, TransactionBehavior};
+use serde::Serialize;
+use tokio::task;
+
+use crate::core::db::Db;
+use super::{CardState, FlashcardsError};
+
+pub const DEFAULT_UNDO_COUNT: usize = 1;
+pub const MAX_UNDO_COUNT: usize = 50;
+
+/// A review that was taken back, with the state the card returned to.
+#[derive(Serialize)]
+pub struct UndoneReview {
+    pub card_id: String,
+    pub rating: u32,
+    pub reviewed_at: String,
+    /// The card's state after the undo; `new` if it had never been reviewed.
+    pub state: CardState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub due_date: Option<String>,
+    /// True when the review had suspended the card as a leech and the undo
+    /// unsuspended it.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub unsuspended: bool,
+}
+
+/// The pre-review state stored on a log row.
+struct LoggedReview {
+    id: i64,
+    card_id: String,
+    rating: u32,
+    reviewed_at: String,
+    stability_before: Option<f32>,
+    difficulty_before: Option<f32>,
+    last_review_before: Option<String>,
+    due_before: Option<String>,
+    interval_before: Option<i64>,
+    state_before: Option<String>,
+    step_before: Option<i64>,
+    suspended_card: bool,
+}
+
+/// Takes back the most recent `count` reviews, newest first: from the given
+/// session, or from all reviews when no session is given. Each card is put
+/// back to the state recorded before the review, unsuspended if the review
+/// suspended it as a leech, and the log row is removed, so the undone rating
+/// does not count towards limits, stats, leeches or optimisation. A review can only be undone while it is the card's latest.
+pub async fn undo_reviews(
+    db: Db,
+    user_id: i64,
+    session_id: Option<String>,
+    count: usize,
+) -> Result<Vec<UndoneReview>, FlashcardsError> {
+    task::spawn_blocking(move || {
+        let mut conn = db.write().map_err(FlashcardsError::Internal)?;
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(|err| FlashcardsError::Internal(format!("Failed to start transaction: {err}")))?;
+
+        let reviews = latest_reviews(&tx, user_id, session_id.as_deref(), count)
+            .map_err(|err| FlashcardsError::Internal(format!("Failed to read review log: {err}")))?;
+        if reviews.is_empty() {
+            return Err(FlashcardsError::NotFound("Nothing to undo".to_string()));
+        }
+
+        let mut undone = Vec::new();
+        for review in reviews {
+            undone.push(restore(&tx, user_id, review)?);
+        }
+
+        tx.commit()
+            .map_err(|err| FlashcardsError::Internal(format!("Failed to commit undo: {err}")))?;
+        Ok(undone)
+    })
+    .await
+    .map_err(|_| FlashcardsError::Internal("Failed to join blocking task".to_string()))?
+}
+
+fn latest_reviews(
+    tx: &Transaction<'_>,
+    user_id: i64,
+    session_id: Option<&str>,
+    count: usize,
+) -> rusqlite::Result<Vec<LoggedReview>> {
+    let mut stmt = tx.prepare(
+        "
+        SELECT id, card_id, rating, reviewed_at, stability_before, difficulty_before,
+            last_review_before, due_before, interval_before, state_before, step_before,
+            suspended_card
+        FROM fsrs_review_log
+        WHERE user_id = ?1 AND (?2 IS NULL OR session_id = ?2)
+        ORDER BY id DESC
+        LIMIT ?3
+        ",
+    )?;
+    let rows = stmt.query_map(
+        params![user_id, session_id, i64::try_from(count).unwrap_or(i64::MAX)],
+        |row| {
+            Ok(LoggedReview {
+                id: row.get(0)?,
+                card_id: row.get(1)?,
+                rating: row.get(2)?,
+                reviewed_at: row.get(3)?,
+                stability_before: row.get(4)?,
+                difficulty_before: row.get(5)?,
+                last_review_before: row.get(6)?,
+                due_before: row.get(7)?,
+                interval_before: row.get(8)?,
+                state_before: row.get(9)?,
+                step_before: row.get(10)?,
+                suspended_card: row.get(11)?,
+            })
+        },
+    )?;
+    rows.collect()
+}
+
+fn restore(
+    tx: &Transaction<'_>,
+    user_id: i64,
+    review: LoggedReview,
+) -> Result<UndoneReview, FlashcardsError> {
+    let internal = |err: rusqlite::Error| FlashcardsError::Internal(format!("Failed to undo review: {err}"));
+
+    let latest: Option<i64> = tx
+        .query_row(
+            "SELECT MAX(id) FROM fsrs_review_log WHERE user_id = ?1 AND card_id = ?2",
+            params![user_id, review.card_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(internal)?
+        .flatten();
+    if latest != Some(review.id) {
+        return Err(FlashcardsError::Conflict(format!(
+            "{} has been reviewed again since; undo that review first",
+            review.card_id
+        )));
+    }
+
+    let (state, due_date) = match (review.stability_before, review.difficulty_before) {
+        (Some(stability), Some(difficulty)) => {
+            let state = review
+                .state_before
+                .as_deref()
+                .map_or(CardState::Review, CardState::parse);
+            tx.execute(
+                "
+                UPDATE fsrs_cards SET
+                    stability = ?2,
+                    difficulty = ?3,
+                    last_review = ?4,
+                    due_date = ?5,
+                    interval_days = ?6,
+                    state = ?7,
+                    step = ?8
+                WHERE user_id = ?9 AND card_id = ?1
+                ",
+                params![
+                    review.card_id,
+                    stability,
+                    difficulty,
+                    review.last_review_before,
+                    review.due_before,
+                    review.interval_before.unwrap_or_default(),
+                    state.as_str(),
+                    review.step_before.unwrap_or_default(),
+                    user_id,
+                ],
+            )
+            .map_err(internal)?;
+            (state, review.due_before)
+        }
+        _ => {
+            tx.execute(
+                "DELETE FROM fsrs_cards WHERE user_id = ?1 AND card_id = ?2",
+                params![user_id, review.card_id],
+            )
+            .map_err(internal)?;
+            (CardState::New, None)
+        }
+    };
+
+    if review.suspended_card {
+        tx.execute(
+            "UPDATE flashcard_flags SET suspended = 0 WHERE user_id = ?1 AND card_id = ?2",
+            params![user_id, review.card_id],
+        )
+        .map_err(internal)?;
+    }
+
+    tx.execute("DELETE FROM fsrs_review_log WHERE id = ?1", [review.id])
+        .map_err(internal)?;
+
+    Ok(UndoneReview {
+        card_id: review.card_id,
+        rating: review.rating,
+        reviewed_at: review.reviewed_at,
+        state,
+        due_date,
+        unsuspended: review.suspended_card,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::flashcards::{
+        review_card,
+        tests::{fixture_card_id, test_state},
+        update_settings, FlashcardsState, LeechAction, SettingsUpdate,
+    };
+
+    #[tokio::test]
+    async fn undo_unsuspends_card_suspended_as_leech() {
+        let state = test_state();
+        let card_id = fixture_card_id();
+        let update = SettingsUpdate {
+            deck: None,
+            desired_retention: None,
+            learning_steps: None,
+            relearning_steps: None,
+            new_cards_per_day: None,
+            reviews_per_day: None,
+            templates: None,
+            leech_threshold: Some(1),
+            leech_action: Some(LeechAction::Suspend),
+            utc_offset_minutes: None,
+        };
+        update_settings(&state, 1, update).await.unwrap();
+        // Easy graduates a new card straight to review, so Again is a lapse.
+        review_card(&state, 1, &card_id, 4, None, None).await.unwrap();
+        let suspended = |state: &FlashcardsState| -> bool {
+            state
+                .db
+                .read()
+                .unwrap()
+                .query_row(
+                    "SELECT COALESCE(MAX(suspended), 0) FROM flashcard_flags WHERE user_id = 1 AND card_id = ?1",
+                    [&card_id],
+                    |row| row.get(0),
+                )
+                .unwrap()
+        };
+
+        let result = review_card(&state, 1, &card_id, 1, None, None).await.unwrap();
+        assert!(result.leech && result.suspended);
+        assert!(suspended(&state));
+        let counts = crate::core::flashcards::flags::load_leech_counts(state.db.clone())
+            .await
+            .unwrap();
+        let counts = &counts[&card_id];
+        assert_eq!((counts.learners, counts.suspended, counts.lapses), (1, 1, 1));
+
+        let undone = undo_reviews(state.db.clone(), 1, None, 1).await.unwrap();
+        assert!(undone[0].unsuspended);
+        assert_eq!(undone[0].state, CardState::Review);
+        assert!(!suspended(&state));
+    }
+}
@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use tokio::fs;
 use tokio::sync::{Mutex, OnceCell};
@@ -109,8 +110,25 @@ pub struct VocabularySection {
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct VocabularyEntry {
+    /// Stable identifier that flashcard review history is keyed on. Assigned
+    /// once, when the CMS first saves the entry (or by `lessons assign-ids`
+    /// for older files), and kept as-is afterwards so later edits and
+    /// reordering do not move the history.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
     pub word: String,
     pub meaning: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub romanisation: Option<String>,
+    /// Filename of an uploaded pronunciation clip in the media library.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio: Option<String>,
+    /// Dictionary form of the word, when it appears inflected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lemma: Option<String>,
+    /// Free-form labels used to build filtered flashcard decks.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
 }
 
 // =============================================================================
@@ -259,6 +277,8 @@ pub fn lesson_filename_for_id(id: &str) -> String {
 }
 
 static LESSON_INDEX: OnceCell<Mutex<HashMap<String, PathBuf>>> = OnceCell::const_new();
+/// Vocabulary entries by normalised word; `None` until first needed.
+static VOCABULARY_INDEX: Mutex<Option<HashMap<String, VocabularyEntry>>> = Mutex::const_new(None);
 
 async fn build_lesson_index() -> HashMap<String, PathBuf> {
     let mut index = HashMap::new();
@@ -330,12 +350,163 @@ async fn load_lesson_from_path(path: &Path) -> Option<Lesson> {
     serde_json::from_str::<Lesson>(&content).ok()
 }
 
-async fn save_lesson_to_path(path: &Path, lesson: &Lesson) -> Result<(), LessonStoreError> {
+/// Content-derived id for a vocabulary entry that has none yet.
+fn derived_vocabulary_id(word: &str) -> String {
+    let digest = Sha256::digest(word.trim().to_lowercase().as_bytes());
+    let hex = digest
+        .iter()
+        .take(5)
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    format!("w-{hex}")
+}
+
+/// Picks an id for a new entry: the content-derived one, with a `-2`, `-3`,
+/// ... suffix while it is already taken in the lesson.
+fn allocate_vocabulary_id(word: &str, taken: &mut HashSet<String>) -> String {
+    let base = derived_vocabulary_id(word);
+    let mut candidate = base.clone();
+    let mut suffix = 2;
+    while taken.contains(&candidate) {
+        candidate = format!("{base}-{suffix}");
+        suffix += 1;
+    }
+    taken.insert(candidate.clone());
+    candidate
+}
+
+/// Gives every vocabulary entry without an id a new one. Only called right
+/// before the CMS writes the lesson, so an id is picked once and never
+/// recomputed from a word that may since have been edited.
+fn assign_vocabulary_ids(lesson: &mut Lesson) {
+    let mut taken = lesson
+        .sections
+        .iter()
+        .filter_map(|section| match section {
+            ContentSection::Vocabulary(vocab) => Some(vocab),
+            _ => None,
+        })
+        .flat_map(|vocab| vocab.entries.iter().filter_map(|entry| entry.id.clone()))
+        .collect::<HashSet<_>>();
+
+    for section in &mut lesson.sections {
+        let ContentSection::Vocabulary(vocab) = section else {
+            continue;
+        };
+        for entry in vocab.entries.iter_mut().filter(|entry| entry.id.is_none()) {
+            entry.id = Some(allocate_vocabulary_id(&entry.word, &mut taken));
+        }
+    }
+}
+
+/// One-off migration for lesson files written before vocabulary entries had
+/// ids, run with `avvai-backend lessons assign-ids`. Adds an `id` to every
+/// entry without one and keeps the rest of the JSON, unknown fields and key
+/// order included. Each file is replaced by writing a temporary file next to
+/// it and renaming it over, so a crash never leaves a truncated lesson.
+/// Returns the files that changed.
+pub fn migrate_vocabulary_ids() -> Result<Vec<PathBuf>, String> {
+    let mut paths = std::fs::read_dir(lessons_dir())
+        .map_err(|err| format!("Failed to read lessons directory: {err}"))?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    let mut changed = Vec::new();
+    for path in paths {
+        let content = std::fs::read_to_string(&path)
+            .map_err(|err| format!("Failed to read {}: {err}", path.display()))?;
+        let mut lesson: serde_json::Value = serde_json::from_str(&content)
+            .map_err(|err| format!("Failed to parse {}: {err}", path.display()))?;
+        if !assign_raw_vocabulary_ids(&mut lesson) {
+            continue;
+        }
+
+        let mut contents = serde_json::to_string_pretty(&lesson)
+            .map_err(|err| format!("Failed to serialize {}: {err}", path.display()))?;
+        if content.ends_with('\n') {
+            contents.push('\n');
+        }
+        let temp_path = path.with_extension("json.tmp");
+        std::fs::write(&temp_path, contents)
+            .and_then(|()| std::fs::rename(&temp_path, &path))
+            .map_err(|err| {
+                let _ = std::fs::remove_file(&temp_path);
+                format!("Failed to write {}: {err}", path.display())
+            })?;
+        changed.push(path);
+    }
+    Ok(changed)
+}
+
+/// [`assign_vocabulary_ids`] on untyped lesson JSON; the id goes first in
+/// each entry, where the CMS writes it. Returns whether any entry changed.
+fn assign_raw_vocabulary_ids(lesson: &mut serde_json::Value) -> bool {
+    let Some(sections) = lesson["sections"].as_array_mut() else {
+        return false;
+    };
+    let mut taken = sections
+        .iter()
+        .filter(|section| section["type"] == "vocabulary")
+        .filter_map(|section| section["entries"].as_array())
+        .flatten()
+        .filter_map(|entry| entry["id"].as_str().map(str::to_string))
+        .collect::<HashSet<_>>();
+
+    let mut assigned = false;
+    for section in sections.iter_mut().filter(|section| section["type"] == "vocabulary") {
+        let Some(entries) = section["entries"].as_array_mut() else {
+            continue;
+        };
+        for entry in entries.iter_mut().filter_map(serde_json::Value::as_object_mut) {
+            if entry.contains_key("id") {
+                continue;
+            }
+            let word = entry.get("word").and_then(serde_json::Value::as_str).unwrap_or_default();
+            let id = allocate_vocabulary_id(word, &mut taken);
+            entry.shift_insert(0, "id".to_string(), id.into());
+            assigned = true;
+        }
+    }
+    assigned
+}
+
+/// Explicit ids end up inside flashcard ids (`{lesson}:{entry}`), so they
+/// must be unique within the lesson and free of separators.
+fn validate_vocabulary_ids(lesson: &Lesson) -> Result<(), LessonStoreError> {
+    let mut seen = HashSet::new();
+    for section in &lesson.sections {
+        let ContentSection::Vocabulary(vocab) = section else {
+            continue;
+        };
+        for id in vocab.entries.iter().filter_map(|entry| entry.id.as_deref()) {
+            if id.is_empty() || id.contains(':') || id.chars().any(char::is_whitespace) {
+                return Err(LessonStoreError::InvalidReference(format!(
+                    "Invalid vocabulary id: {id:?}"
+                )));
+            }
+            if !seen.insert(id) {
+                return Err(LessonStoreError::InvalidReference(format!(
+                    "Duplicate vocabulary id: {id}"
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes a lesson, first assigning ids to any new vocabulary entries.
+async fn save_lesson_to_path(path: &Path, lesson: &mut Lesson) -> Result<(), LessonStoreError> {
+    validate_vocabulary_ids(lesson)?;
+    assign_vocabulary_ids(lesson);
     let contents =
         serde_json::to_string_pretty(lesson).map_err(|_| LessonStoreError::Serialize)?;
     fs::write(path, contents)
         .await
         .map_err(|err| LessonStoreError::Io(err.to_string()))?;
+    *VOCABULARY_INDEX.lock().await = None;
     Ok(())
 }
 
@@ -426,9 +597,10 @@ pub async fn create_lesson(
         return Err(LessonStoreError::AlreadyExists);
     }
 
-    save_lesson_to_path(&path, lesson).await?;
+    let mut lesson = lesson.clone();
+    save_lesson_to_path(&path, &mut lesson).await?;
     update_index(&lesson.id, Some(path)).await;
-    Ok(lesson.clone())
+    Ok(lesson)
 }
 
 pub async fn update_lesson(id: &str, lesson: &Lesson) -> Result<Lesson, LessonStoreError> {
@@ -437,9 +609,103 @@ pub async fn update_lesson(id: &str, lesson: &Lesson) -> Result<Lesson, LessonSt
         return Err(LessonStoreError::NotFound);
     }
 
-    save_lesson_to_path(&path, lesson).await?;
+    let mut lesson = lesson.clone();
+    save_lesson_to_path(&path, &mut lesson).await?;
     update_index(id, Some(path)).await;
-    Ok(lesson.clone())
+    Ok(lesson)
+}
+
+/// Attaches (or with `None`, detaches) a pronunciation clip on the
+/// vocabulary entry with id `entry_id`, wherever it sits in the lesson.
+pub async fn set_vocabulary_audio(
+    id: &str,
+    entry_id: &str,
+    audio: Option<String>,
+) -> Result<VocabularyEntry, LessonStoreError> {
+    let path = lessons_dir().join(lesson_filename_for_id(id));
+    let Some(mut lesson) = load_lesson_from_path(&path).await else {
+        return Err(LessonStoreError::NotFound);
+    };
+
+    let entry = vocabulary_entries_mut(&mut lesson)
+        .find(|entry| entry.id.as_deref() == Some(entry_id))
+        .ok_or_else(|| {
+            LessonStoreError::InvalidReference("Vocabulary entry not found".to_string())
+        })?;
+    entry.audio = audio;
+    let updated = entry.clone();
+
+    save_lesson_to_path(&path, &mut lesson).await?;
+    Ok(updated)
+}
+
+fn vocabulary_entries_mut(lesson: &mut Lesson) -> impl Iterator<Item = &mut VocabularyEntry> {
+    lesson.sections.iter_mut().flat_map(|section| match section {
+        ContentSection::Vocabulary(vocab) => vocab.entries.iter_mut(),
+        _ => [].iter_mut(),
+    })
+}
+
+/// Finds the vocabulary entry whose word matches `word`, ignoring case and
+/// surrounding whitespace, from an index built once and dropped whenever a
+/// lesson is saved or deleted.
+pub async fn find_vocabulary_entry(word: &str) -> Option<VocabularyEntry> {
+    let mut index = VOCABULARY_INDEX.lock().await;
+    if index.is_none() {
+        *index = Some(build_vocabulary_index(load_all_lessons().await));
+    }
+    index
+        .as_ref()
+        .and_then(|index| index.get(&vocabulary_key(word)).cloned())
+}
+
+fn vocabulary_key(word: &str) -> String {
+    word.trim().to_lowercase()
+}
+
+/// Every lesson on disk, in filename order.
+async fn load_all_lessons() -> Vec<Lesson> {
+    let Ok(mut entries) = fs::read_dir(lessons_dir()).await else {
+        return Vec::new();
+    };
+    let mut paths = Vec::new();
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let mut lessons = Vec::new();
+    for path in paths {
+        if let Some(lesson) = load_lesson_from_path(&path).await {
+            lessons.push(lesson);
+        }
+    }
+    lessons
+}
+
+/// Maps each word to one of its entries. When a word appears more than once,
+/// the first entry with a pronunciation clip wins, and otherwise the first
+/// entry, in lesson order.
+fn build_vocabulary_index(lessons: Vec<Lesson>) -> HashMap<String, VocabularyEntry> {
+    let mut index: HashMap<String, VocabularyEntry> = HashMap::new();
+    for entry in lessons.into_iter().flat_map(|lesson| {
+        lesson.sections.into_iter().flat_map(|section| match section {
+            ContentSection::Vocabulary(vocab) => vocab.entries,
+            _ => Vec::new(),
+        })
+    }) {
+        let key = vocabulary_key(&entry.word);
+        match index.get(&key) {
+            Some(existing) if existing.audio.is_some() || entry.audio.is_none() => {}
+            _ => {
+                index.insert(key, entry);
+            }
+        }
+    }
+    index
 }
 
 pub async fn delete_lesson(id: &str) -> Result<(), LessonStoreError> {
@@ -451,6 +717,7 @@ pub async fn delete_lesson(id: &str) -> Result<(), LessonStoreError> {
     fs::remove_file(&path)
         .await
         .map_err(|err| LessonStoreError::Io(err.to_string()))?;
+    *VOCABULARY_INDEX.lock().await = None;
     update_index(id, None).await;
     Ok(())
 }
@@ -460,6 +727,7 @@ pub enum LessonStoreError {
     NotFound,
     AlreadyExists,
     Serialize,
+    InvalidReference(String),
     Io(String),
 }
 
@@ -469,7 +737,63 @@ impl LessonStoreError {
             Self::NotFound => "Lesson not found".to_string(),
             Self::AlreadyExists => "Lesson already exists".to_string(),
             Self::Serialize => "Failed to serialize lesson".to_string(),
-            Self::Io(err) => err.clone(),
+            Self::InvalidReference(err) | Self::Io(err) => err.clone(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_id_assignment_keeps_the_rest_of_the_lesson() {
+        let mut lesson: serde_json::Value = serde_json::from_str(
+            r#"{"id": "l", "reviewedBy": "ed", "sections": [{"type": "vocabulary", "entries": [
+                {"word": "அன்பு", "meaning": "love", "note": "keep"},
+                {"id": "w-kept", "word": "அறம்", "meaning": "virtue"},
+                {"word": "அன்பு", "meaning": "affection"}
+            ]}]}"#,
+        )
+        .unwrap();
+
+        assert!(assign_raw_vocabulary_ids(&mut lesson));
+        let base = derived_vocabulary_id("அன்பு");
+        let entries = &lesson["sections"][0]["entries"];
+        assert_eq!(entries[0]["id"], base.as_str());
+        assert_eq!(entries[1]["id"], "w-kept");
+        assert_eq!(entries[2]["id"], format!("{base}-2").as_str());
+        assert_eq!(lesson["reviewedBy"], "ed");
+        let keys = entries[0].as_object().unwrap().keys().collect::<Vec<_>>();
+        assert_eq!(keys, ["id", "word", "meaning", "note"]);
+        assert!(!assign_raw_vocabulary_ids(&mut lesson));
+    }
+
+    fn vocabulary_lesson(id: &str, entries: serde_json::Value) -> Lesson {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "title": id,
+            "description": "",
+            "sections": [{"type": "vocabulary", "entries": entries}]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn vocabulary_index_prefers_the_first_entry_with_audio() {
+        let index = build_vocabulary_index(vec![
+            vocabulary_lesson("a", serde_json::json!([
+                {"word": "அன்பு", "meaning": "love"},
+                {"word": "அறம்", "meaning": "virtue", "audio": "aram.mp3"}
+            ])),
+            vocabulary_lesson("b", serde_json::json!([
+                {"word": " அன்பு ", "meaning": "affection", "audio": "anbu-b.mp3"},
+                {"word": "அன்பு", "meaning": "kindness", "audio": "anbu-c.mp3"},
+                {"word": "அறம்", "meaning": "duty"}
+            ])),
+        ]);
+
+        assert_eq!(index["அன்பு"].audio.as_deref(), Some("anbu-b.mp3"));
+        assert_eq!(index["அறம்"].meaning, "virtue");
+    }
+}
@@ -1,69 +1,818 @@
-use std::path::{Path, PathBuf};
-use tokio::fs;
+use image::{imageops::FilterType, DynamicImage, ImageFormat};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashSet,
+    env,
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::{fs, io::AsyncWriteExt, task};
+
+use crate::core::db::Db;
 
 const MEDIA_DIR: &str = "static/media";
+const VARIANTS_DIR: &str = "variants";
+const DEFAULT_MIME: &str = "application/octet-stream";
+
+/// Resized renditions generated for every uploaded still image, each width
+/// in the original's format and in WebP. Variants wider than the original
+/// are skipped rather than upscaled, except `webp`, which falls back to the
+/// original's size.
+const VARIANT_SPECS: &[VariantSpec] = &[
+    VariantSpec {
+        name: "thumbnail",
+        width: 160,
+        webp: false,
+    },
+    VariantSpec {
+        name: "medium",
+        width: 640,
+        webp: false,
+    },
+    VariantSpec {
+        name: "large",
+        width: 1280,
+        webp: false,
+    },
+    VariantSpec {
+        name: "thumbnail-webp",
+        width: 160,
+        webp: true,
+    },
+    VariantSpec {
+        name: "medium-webp",
+        width: 640,
+        webp: true,
+    },
+    VariantSpec {
+        name: "webp",
+        width: 1280,
+        webp: true,
+    },
+];
+
+struct VariantSpec {
+    name: &'static str,
+    width: u32,
+    webp: bool,
+}
+
+/// Bytes kept from the start of an upload for type sniffing and header
+/// probing.
+const SNIFF_LEN: usize = 512;
+const PROBE_LEN: usize = 4096;
+const TEMP_PREFIX: &str = ".upload-";
+
+const MIB: u64 = 1024 * 1024;
+const DEFAULT_MAX_IMAGE_BYTES: u64 = 10 * MIB;
+const DEFAULT_MAX_AUDIO_BYTES: u64 = 25 * MIB;
+const DEFAULT_MAX_VIDEO_BYTES: u64 = 200 * MIB;
+const DEFAULT_MAX_REQUEST_BYTES: u64 = 512 * MIB;
+
+/// MIME types accepted for upload, as detected from the file contents. SVG is
+/// deliberately absent because it can carry script.
+const DEFAULT_ALLOWED_TYPES: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "audio/mpeg",
+    "audio/wav",
+    "audio/ogg",
+    "audio/flac",
+    "audio/mp4",
+    "video/mp4",
+    "video/webm",
+];
+
+/// Upload limits, read once from the environment:
+/// `MEDIA_MAX_IMAGE_BYTES`, `MEDIA_MAX_AUDIO_BYTES`, `MEDIA_MAX_VIDEO_BYTES`,
+/// `MEDIA_MAX_REQUEST_BYTES` and a comma-separated `MEDIA_ALLOWED_TYPES`.
+pub struct UploadLimits {
+    pub max_image_bytes: u64,
+    pub max_audio_bytes: u64,
+    pub max_video_bytes: u64,
+    pub max_request_bytes: u64,
+    pub allowed_types: HashSet<String>,
+}
+
+impl UploadLimits {
+    fn from_env() -> Self {
+        let bytes = |key: &str, default: u64| {
+            env::var(key)
+                .ok()
+                .and_then(|value| value.trim().parse::<u64>().ok())
+                .unwrap_or(default)
+        };
+
+        let allowed_types = env::var("MEDIA_ALLOWED_TYPES")
+            .ok()
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(|mime| mime.trim().to_lowercase())
+                    .filter(|mime| !mime.is_empty())
+                    .collect::<HashSet<_>>()
+            })
+            .filter(|types| !types.is_empty())
+            .unwrap_or_else(|| {
+                DEFAULT_ALLOWED_TYPES
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect()
+            });
+
+        Self {
+            max_image_bytes: bytes("MEDIA_MAX_IMAGE_BYTES", DEFAULT_MAX_IMAGE_BYTES),
+            max_audio_bytes: bytes("MEDIA_MAX_AUDIO_BYTES", DEFAULT_MAX_AUDIO_BYTES),
+            max_video_bytes: bytes("MEDIA_MAX_VIDEO_BYTES", DEFAULT_MAX_VIDEO_BYTES),
+            max_request_bytes: bytes("MEDIA_MAX_REQUEST_BYTES", DEFAULT_MAX_REQUEST_BYTES),
+            allowed_types,
+        }
+    }
+
+    fn max_bytes_for(&self, mime: Option<&str>) -> u64 {
+        match mime {
+            Some(mime) if mime.starts_with("image/") => self.max_image_bytes,
+            Some(mime) if mime.starts_with("audio/") => self.max_audio_bytes,
+            Some(mime) if mime.starts_with("video/") => self.max_video_bytes,
+            Some(_) => self.max_image_bytes.min(self.max_audio_bytes),
+            None => self
+                .max_image_bytes
+                .max(self.max_audio_bytes)
+                .max(self.max_video_bytes),
+        }
+    }
+
+    fn check_type(&self, mime: &'static str) -> Result<&'static str, MediaError> {
+        if self.allowed_types.contains(mime) {
+            Ok(mime)
+        } else {
+            Err(MediaError::UnsupportedType(mime.to_string()))
+        }
+    }
+
+    fn check_size(&self, mime: Option<&str>, size: u64) -> Result<(), MediaError> {
+        let limit = self.max_bytes_for(mime);
+        if size > limit {
+            Err(MediaError::TooLarge(limit))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+static UPLOAD_LIMITS: OnceLock<UploadLimits> = OnceLock::new();
+
+pub fn upload_limits() -> &'static UploadLimits {
+    UPLOAD_LIMITS.get_or_init(UploadLimits::from_env)
+}
 
 #[derive(Debug)]
 pub enum MediaError {
     NotFound,
+    Invalid(String),
+    UnsupportedType(String),
+    TooLarge(u64),
     Io(String),
+    Database(String),
 }
 
 impl MediaError {
     pub fn message(&self) -> String {
         match self {
             Self::NotFound => "File not found".to_string(),
-            Self::Io(err) => err.clone(),
+            Self::UnsupportedType(mime) => format!("File type {mime} is not allowed"),
+            Self::TooLarge(limit) => format!("File exceeds the {limit} byte limit"),
+            Self::Invalid(err) | Self::Io(err) | Self::Database(err) => err.clone(),
         }
     }
 }
 
+#[derive(Serialize, Clone)]
+pub struct MediaRecord {
+    pub filename: String,
+    pub content_hash: String,
+    pub mime_type: String,
+    pub size_bytes: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uploaded_by: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alt_text: Option<String>,
+    pub created_at: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub variants: Vec<MediaVariant>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct MediaVariant {
+    pub variant: String,
+    pub filename: String,
+    pub mime_type: String,
+    pub width: u32,
+    pub height: u32,
+    pub size_bytes: u64,
+    pub url: String,
+}
+
+impl MediaVariant {
+    fn is_webp(&self) -> bool {
+        self.mime_type == "image/webp"
+    }
+}
+
+/// Result of storing an upload. `deduplicated` is set when an asset with the
+/// same content already existed and no new file was written.
+pub struct StoredMedia {
+    pub record: MediaRecord,
+    pub deduplicated: bool,
+}
+
 pub fn media_root() -> PathBuf {
     Path::new(env!("CARGO_MANIFEST_DIR")).join(MEDIA_DIR)
 }
 
+pub fn variants_root() -> PathBuf {
+    media_root().join(VARIANTS_DIR)
+}
+
 pub fn sanitize_filename(filename: &str) -> String {
     filename.replace(['/', '\\'], "_")
 }
 
-pub async fn list_media() -> Vec<String> {
-    let dir = media_root();
-    let mut files = Vec::new();
+/// Public URL for an asset. Lessons can append `?w=<pixels>` to get the
+/// smallest stored rendition at least that wide, and `&format=webp` to prefer
+/// a WebP rendition of that width.
+pub fn media_url(filename: &str) -> String {
+    format!("/media/{filename}")
+}
 
-    if let Ok(mut entries) = fs::read_dir(dir).await {
-        while let Ok(Some(entry)) = entries.next_entry().await {
-            let path = entry.path();
-            if let Ok(file_type) = entry.file_type().await
-                && file_type.is_file()
-                && let Some(name) = path.file_name()
-            {
-                files.push(name.to_string_lossy().to_string());
-            }
+// =============================================================================
+// CONTENT INSPECTION
+// =============================================================================
+
+pub fn content_hash(bytes: &[u8]) -> String {
+    to_hex(&Sha256::digest(bytes))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Detects the MIME type from the leading bytes rather than trusting the
+/// client-supplied filename or content type.
+pub fn sniff_mime(bytes: &[u8]) -> &'static str {
+    let starts = |magic: &[u8]| bytes.starts_with(magic);
+    let riff_kind = bytes.get(8..12);
+
+    if starts(b"\x89PNG\r\n\x1a\n") {
+        "image/png"
+    } else if starts(&[0xFF, 0xD8, 0xFF]) {
+        "image/jpeg"
+    } else if starts(b"GIF87a") || starts(b"GIF89a") {
+        "image/gif"
+    } else if starts(b"RIFF") && riff_kind == Some(b"WEBP") {
+        "image/webp"
+    } else if starts(b"RIFF") && riff_kind == Some(b"WAVE") {
+        "audio/wav"
+    } else if starts(b"ID3") || (bytes.len() > 1 && bytes[0] == 0xFF && bytes[1] & 0xE0 == 0xE0) {
+        "audio/mpeg"
+    } else if starts(b"OggS") {
+        "audio/ogg"
+    } else if starts(b"fLaC") {
+        "audio/flac"
+    } else if bytes.get(4..8) == Some(b"ftyp") {
+        // Only the major brands of plain MP4; HEIC, QuickTime and other ISO
+        // media files share the `ftyp` box.
+        match bytes.get(8..12) {
+            Some(b"M4A ") => "audio/mp4",
+            Some(b"isom" | b"mp41" | b"mp42" | b"avc1" | b"M4V ") => "video/mp4",
+            _ => DEFAULT_MIME,
+        }
+    } else if starts(&[0x1A, 0x45, 0xDF, 0xA3]) {
+        "video/webm"
+    } else if starts(b"%PDF") {
+        "application/pdf"
+    } else if looks_like_svg(bytes) {
+        "image/svg+xml"
+    } else {
+        DEFAULT_MIME
+    }
+}
+
+fn looks_like_svg(bytes: &[u8]) -> bool {
+    let head = &bytes[..bytes.len().min(512)];
+    std::str::from_utf8(head).is_ok_and(|text| {
+        let text = text.trim_start();
+        (text.starts_with("<?xml") || text.starts_with("<svg")) && text.contains("<svg")
+    })
+}
+
+fn probe_dimensions(bytes: &[u8], mime: &str) -> Option<(u32, u32)> {
+    if !mime.starts_with("image/") || mime == "image/svg+xml" {
+        return None;
+    }
+    image::ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()
+}
+
+/// Reads the duration from a WAV header. Compressed formats would need a
+/// full demuxer, so their duration is left unset.
+fn probe_duration_ms(bytes: &[u8], mime: &str) -> Option<u64> {
+    if mime != "audio/wav" {
+        return None;
+    }
+
+    let mut byte_rate = None;
+    let mut offset = 12;
+    while offset + 8 <= bytes.len() {
+        let id = &bytes[offset..offset + 4];
+        let size = u32::from_le_bytes(bytes[offset + 4..offset + 8].try_into().ok()?);
+        let body = offset + 8;
+        if id == b"fmt " && body + 12 <= bytes.len() {
+            byte_rate = Some(u32::from_le_bytes(bytes[body + 8..body + 12].try_into().ok()?));
+        } else if id == b"data" {
+            let rate = u64::from(byte_rate.filter(|rate| *rate > 0)?);
+            return Some(u64::from(size) * 1000 / rate);
         }
+        offset = body + size as usize + (size as usize % 2);
+    }
+
+    None
+}
+
+// =============================================================================
+// RESPONSIVE VARIANTS
+// =============================================================================
+
+struct GeneratedVariant {
+    variant: &'static str,
+    filename: String,
+    mime_type: &'static str,
+    width: u32,
+    height: u32,
+    bytes: Vec<u8>,
+}
+
+/// GIFs get no variants: resizing would keep only their first frame.
+fn variant_format(mime: &str) -> Option<(ImageFormat, &'static str, &'static str)> {
+    match mime {
+        "image/jpeg" => Some((ImageFormat::Jpeg, "image/jpeg", "jpg")),
+        "image/png" | "image/webp" => Some((ImageFormat::Png, "image/png", "png")),
+        _ => None,
     }
+}
 
-    files.sort();
-    files
+fn encode(image: &DynamicImage, format: ImageFormat) -> Option<Vec<u8>> {
+    let mut buffer = Cursor::new(Vec::new());
+    let result = match format {
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8()).write_to(&mut buffer, format),
+        ImageFormat::WebP => DynamicImage::ImageRgba8(image.to_rgba8()).write_to(&mut buffer, format),
+        _ => image.write_to(&mut buffer, format),
+    };
+    result.ok().map(|()| buffer.into_inner())
 }
 
-pub async fn create_media_file(filename: &str) -> Result<(PathBuf, tokio::fs::File), MediaError> {
-    let root = media_root();
+fn generate_variants(filename: &str, bytes: &[u8], mime: &str) -> Vec<GeneratedVariant> {
+    let Some((format, variant_mime, ext)) = variant_format(mime) else {
+        return Vec::new();
+    };
+    let Ok(image) = image::load_from_memory(bytes) else {
+        return Vec::new();
+    };
+
+    let mut variants = Vec::new();
+    for spec in VARIANT_SPECS {
+        let resized = if image.width() > spec.width {
+            image.resize(spec.width, u32::MAX, FilterType::Lanczos3)
+        } else if spec.name == "webp" {
+            image.clone()
+        } else {
+            continue;
+        };
+
+        let (format, variant_mime, ext) = if spec.webp {
+            (ImageFormat::WebP, "image/webp", "webp")
+        } else {
+            (format, variant_mime, ext)
+        };
+        let Some(encoded) = encode(&resized, format) else {
+            continue;
+        };
+
+        variants.push(GeneratedVariant {
+            variant: spec.name,
+            filename: format!("{filename}.{}.{ext}", spec.name),
+            mime_type: variant_mime,
+            width: resized.width(),
+            height: resized.height(),
+            bytes: encoded,
+        });
+    }
+
+    variants
+}
+
+async fn create_variants(db: &Db, filename: &str, bytes: Vec<u8>, mime: &str) -> Result<(), MediaError> {
+    let source = filename.to_string();
+    let mime = mime.to_string();
+    let generated = task::spawn_blocking(move || generate_variants(&source, &bytes, &mime))
+        .await
+        .map_err(|_| MediaError::Io("Failed to join blocking task".to_string()))?;
+    if generated.is_empty() {
+        return Ok(());
+    }
+
+    let root = variants_root();
     fs::create_dir_all(&root)
         .await
         .map_err(|err| MediaError::Io(err.to_string()))?;
 
-    let sanitized = sanitize_filename(filename);
-    let path = root.join(sanitized);
-    let file = tokio::fs::File::create(&path)
-        .await
-        .map_err(|_| MediaError::Io("Failed to create file".to_string()))?;
+    let mut rows: Vec<MediaVariant> = Vec::new();
+    for variant in generated {
+        let temp_path = root.join(temp_filename());
+        let written = match fs::write(&temp_path, &variant.bytes).await {
+            Ok(()) => fs::rename(&temp_path, root.join(&variant.filename)).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = written {
+            // Nothing is recorded yet, so remove what this call wrote.
+            let _ = fs::remove_file(&temp_path).await;
+            for row in &rows {
+                let _ = fs::remove_file(root.join(&row.filename)).await;
+            }
+            return Err(MediaError::Io(err.to_string()));
+        }
+        rows.push(MediaVariant {
+            variant: variant.variant.to_string(),
+            url: String::new(),
+            filename: variant.filename,
+            mime_type: variant.mime_type.to_string(),
+            width: variant.width,
+            height: variant.height,
+            size_bytes: variant.bytes.len() as u64,
+        });
+    }
+
+    let written = rows
+        .iter()
+        .map(|row| root.join(&row.filename))
+        .collect::<Vec<_>>();
+    let source = filename.to_string();
+    let db = db.clone();
+    let saved = task::spawn_blocking(move || {
+        let mut conn = db.write()?;
+        let tx = conn
+            .transaction()
+            .map_err(|err| format!("Failed to start transaction: {err}"))?;
+        for row in rows {
+            tx.execute(
+                "
+                INSERT INTO media_variants (source_filename, variant, filename, mime_type, width, height, size_bytes)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                ON CONFLICT(source_filename, variant) DO UPDATE SET
+                    filename = excluded.filename,
+                    mime_type = excluded.mime_type,
+                    width = excluded.width,
+                    height = excluded.height,
+                    size_bytes = excluded.size_bytes
+                ",
+                params![
+                    source,
+                    row.variant,
+                    row.filename,
+                    row.mime_type,
+                    row.width,
+                    row.height,
+                    row.size_bytes,
+                ],
+            )
+            .map_err(|err| format!("Failed to save media variant: {err}"))?;
+        }
+        tx.commit()
+            .map_err(|err| format!("Failed to save media variants: {err}"))
+    })
+    .await
+    .map_err(|_| MediaError::Io("Failed to join blocking task".to_string()))?
+    .map_err(MediaError::Database);
+    if saved.is_err() {
+        for path in written {
+            let _ = fs::remove_file(path).await;
+        }
+    }
+    saved
+}
+
+/// The concrete file chosen to answer a media request.
+pub struct ResolvedMedia {
+    pub path: PathBuf,
+    pub mime_type: String,
+    pub etag: String,
+}
+
+/// Resolves the file to serve for a requested width: the narrowest variant
+/// that is at least `width` wide, or the original when nothing smaller fits.
+/// With `prefer_webp`, the narrowest WebP variant that is wide enough is
+/// served instead; the format is ignored when none is.
+pub fn resolve_variant(record: &MediaRecord, width: Option<u32>, prefer_webp: bool) -> ResolvedMedia {
+    let original = ResolvedMedia {
+        path: media_root().join(&record.filename),
+        mime_type: record.mime_type.clone(),
+        etag: format!("\"{}\"", record.content_hash),
+    };
+    let from_variant = |variant: &MediaVariant| ResolvedMedia {
+        path: variants_root().join(&variant.filename),
+        mime_type: variant.mime_type.clone(),
+        etag: format!("\"{}-{}\"", record.content_hash, variant.variant),
+    };
+
+    if prefer_webp {
+        let webp = record
+            .variants
+            .iter()
+            .filter(|variant| variant.is_webp())
+            .filter(|variant| width.is_none_or(|width| width <= variant.width));
+        let chosen = match width {
+            Some(_) => webp.min_by_key(|variant| variant.width),
+            None => webp.max_by_key(|variant| variant.width),
+        };
+        if let Some(variant) = chosen {
+            return from_variant(variant);
+        }
+    }
+
+    let Some(width) = width else {
+        return original;
+    };
+    if record.width.is_some_and(|original_width| original_width <= width) {
+        return original;
+    }
+
+    record
+        .variants
+        .iter()
+        .filter(|variant| !variant.is_webp() && variant.width >= width)
+        .min_by_key(|variant| variant.width)
+        .map_or(original, from_variant)
+}
+
+// =============================================================================
+// CORE API
+// =============================================================================
+
+pub async fn list_media(db: &Db) -> Result<Vec<MediaRecord>, MediaError> {
+    let db = db.clone();
+    task::spawn_blocking(move || {
+        let conn = db.read()?;
+        let records = {
+            let mut stmt = conn
+                .prepare(&format!("{SELECT_MEDIA} ORDER BY filename ASC"))
+                .map_err(|err| format!("Failed to prepare query: {err}"))?;
+            let rows = stmt
+                .query_map([], read_record)
+                .map_err(|err| format!("Failed to query media: {err}"))?;
+
+            let mut records = Vec::new();
+            for row in rows {
+                let mut record = row.map_err(|err| format!("Failed to read row: {err}"))?;
+                record.variants = load_variants(&conn, &record.filename)?;
+                records.push(record);
+            }
+            records
+        };
+        drop(conn);
+        Ok::<Vec<MediaRecord>, String>(records)
+    })
+    .await
+    .map_err(|_| MediaError::Io("Failed to join blocking task".to_string()))?
+    .map_err(MediaError::Database)
+}
+
+pub async fn get_media(db: &Db, filename: &str) -> Result<Option<MediaRecord>, MediaError> {
+    let filename = sanitize_filename(filename);
+    let db = db.clone();
+    task::spawn_blocking(move || {
+        let conn = db.read()?;
+        find_by_filename(&conn, &filename)
+    })
+    .await
+    .map_err(|_| MediaError::Io("Failed to join blocking task".to_string()))?
+    .map_err(MediaError::Database)
+}
+
+/// An upload being streamed into a temporary file next to the media
+/// directory. Nothing becomes visible under its final name until
+/// [`MediaUpload::finish`] renames it into place, and the temporary file is
+/// removed if the upload is dropped part-way through.
+pub struct MediaUpload {
+    filename: String,
+    temp_path: PathBuf,
+    file: Option<fs::File>,
+    hasher: Sha256,
+    head: Vec<u8>,
+    size: u64,
+    mime: Option<&'static str>,
+}
+
+impl MediaUpload {
+    pub async fn begin(filename: &str) -> Result<Self, MediaError> {
+        let root = media_root();
+        fs::create_dir_all(&root)
+            .await
+            .map_err(|err| MediaError::Io(err.to_string()))?;
 
-    Ok((path, file))
+        let temp_path = root.join(temp_filename());
+        let file = fs::File::create(&temp_path)
+            .await
+            .map_err(|_| MediaError::Io("Failed to create file".to_string()))?;
+
+        Ok(Self {
+            filename: sanitize_filename(filename),
+            temp_path,
+            file: Some(file),
+            hasher: Sha256::new(),
+            head: Vec::new(),
+            size: 0,
+            mime: None,
+        })
+    }
+
+    pub async fn write(&mut self, chunk: &[u8]) -> Result<(), MediaError> {
+        let limits = upload_limits();
+        self.size += chunk.len() as u64;
+
+        if self.head.len() < PROBE_LEN {
+            let take = chunk.len().min(PROBE_LEN - self.head.len());
+            self.head.extend_from_slice(&chunk[..take]);
+        }
+        if self.mime.is_none() && self.head.len() >= SNIFF_LEN {
+            self.mime = Some(limits.check_type(sniff_mime(&self.head))?);
+        }
+        limits.check_size(self.mime, self.size)?;
+
+        self.hasher.update(chunk);
+        let Some(file) = self.file.as_mut() else {
+            return Err(MediaError::Io("Upload already finished".to_string()));
+        };
+        file.write_all(chunk)
+            .await
+            .map_err(|err| MediaError::Io(err.to_string()))
+    }
+
+    pub async fn finish(mut self, db: &Db, uploaded_by: &str) -> Result<StoredMedia, MediaError> {
+        if self.size == 0 {
+            return Err(MediaError::Invalid("File is empty".to_string()));
+        }
+        let limits = upload_limits();
+        let mime = match self.mime {
+            Some(mime) => mime,
+            None => limits.check_type(sniff_mime(&self.head))?,
+        };
+        limits.check_size(Some(mime), self.size)?;
+
+        if let Some(mut file) = self.file.take() {
+            file.flush()
+                .await
+                .map_err(|err| MediaError::Io(err.to_string()))?;
+            file.sync_all()
+                .await
+                .map_err(|err| MediaError::Io(err.to_string()))?;
+        }
+
+        let hash = to_hex(&std::mem::take(&mut self.hasher).finalize());
+        if let Some(record) = find_by_hash(db, &hash).await? {
+            return Ok(StoredMedia {
+                record,
+                deduplicated: true,
+            });
+        }
+
+        let unique = reserve_filename(&media_root(), &self.filename).await?;
+        let stored = self.store(db, &unique, mime, &hash, uploaded_by).await;
+        if !matches!(stored, Ok(Some(_))) {
+            discard_files(db, &unique).await;
+        }
+        match stored {
+            Ok(Some(record)) => Ok(StoredMedia {
+                record,
+                deduplicated: false,
+            }),
+            // The same content was recorded by a concurrent upload since the
+            // hash lookup above.
+            Ok(None) => find_by_hash(db, &hash)
+                .await?
+                .map(|record| StoredMedia {
+                    record,
+                    deduplicated: true,
+                })
+                .ok_or_else(|| MediaError::Database("Media record missing after conflict".to_string())),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Moves the upload onto its reserved name, generates its variants and
+    /// records it. The caller discards the files unless a record results.
+    async fn store(
+        &self,
+        db: &Db,
+        filename: &str,
+        mime: &'static str,
+        hash: &str,
+        uploaded_by: &str,
+    ) -> Result<Option<MediaRecord>, MediaError> {
+        let final_path = media_root().join(filename);
+        fs::rename(&self.temp_path, &final_path)
+            .await
+            .map_err(|err| MediaError::Io(err.to_string()))?;
+
+        let (dimensions, duration_ms) = if mime.starts_with("image/") {
+            let bytes = fs::read(&final_path)
+                .await
+                .map_err(|err| MediaError::Io(err.to_string()))?;
+            let dimensions = probe_dimensions(&bytes, mime);
+            create_variants(db, filename, bytes, mime).await?;
+            (dimensions, None)
+        } else {
+            (None, probe_duration_ms(&self.head, mime))
+        };
+
+        let record = MediaRecord {
+            filename: filename.to_string(),
+            content_hash: hash.to_string(),
+            mime_type: mime.to_string(),
+            size_bytes: self.size,
+            width: dimensions.map(|(width, _)| width),
+            height: dimensions.map(|(_, height)| height),
+            duration_ms,
+            uploaded_by: Some(uploaded_by.to_string()),
+            alt_text: None,
+            created_at: String::new(),
+            variants: Vec::new(),
+        };
+
+        insert_record(db, record).await
+    }
+}
+
+impl Drop for MediaUpload {
+    fn drop(&mut self) {
+        // After a successful rename the temp path no longer exists, so this
+        // only cleans up uploads that failed or were abandoned.
+        let _ = std::fs::remove_file(&self.temp_path);
+    }
 }
 
-pub async fn delete_media(filename: &str) -> Result<(), MediaError> {
+fn temp_filename() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos());
+    let sequence = COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("{TEMP_PREFIX}{}-{nanos}-{sequence}.part", std::process::id())
+}
+
+pub async fn update_alt_text(
+    db: &Db,
+    filename: &str,
+    alt_text: Option<String>,
+) -> Result<MediaRecord, MediaError> {
+    let filename = sanitize_filename(filename);
+    let alt_text = alt_text.filter(|text| !text.trim().is_empty());
+    let db = db.clone();
+    task::spawn_blocking(move || {
+        let conn = db.write()?;
+        conn.execute(
+            "UPDATE media SET alt_text = ?1 WHERE filename = ?2",
+            params![alt_text, filename],
+        )
+        .map_err(|err| format!("Failed to update media: {err}"))?;
+        find_by_filename(&conn, &filename)
+    })
+    .await
+    .map_err(|_| MediaError::Io("Failed to join blocking task".to_string()))?
+    .map_err(MediaError::Database)?
+    .ok_or(MediaError::NotFound)
+}
+
+pub async fn delete_media(db: &Db, filename: &str) -> Result<(), MediaError> {
     let sanitized = sanitize_filename(filename);
-    let path = media_root().join(sanitized);
+    let path = media_root().join(&sanitized);
     if !fs::try_exists(&path).await.unwrap_or(false) {
         return Err(MediaError::NotFound);
     }
@@ -71,5 +820,393 @@ pub async fn delete_media(filename: &str) -> Result<(), MediaError> {
     fs::remove_file(&path)
         .await
         .map_err(|err| MediaError::Io(err.to_string()))?;
+
+    let db = db.clone();
+    let variants = task::spawn_blocking(move || {
+        let conn = db.write()?;
+        let variants = load_variants(&conn, &sanitized)?;
+        conn.execute(
+            "DELETE FROM media_variants WHERE source_filename = ?1",
+            [&sanitized],
+        )
+        .map_err(|err| format!("Failed to delete media variants: {err}"))?;
+        conn.execute("DELETE FROM media WHERE filename = ?1", [&sanitized])
+            .map_err(|err| format!("Failed to delete media record: {err}"))?;
+        Ok::<Vec<MediaVariant>, String>(variants)
+    })
+    .await
+    .map_err(|_| MediaError::Io("Failed to join blocking task".to_string()))?
+    .map_err(MediaError::Database)?;
+
+    for variant in variants {
+        let _ = fs::remove_file(variants_root().join(variant.filename)).await;
+    }
+    Ok(())
+}
+
+// =============================================================================
+// STORAGE HELPERS
+// =============================================================================
+
+/// Removes an unrecorded original and whatever variants were generated for
+/// it, on a best-effort basis.
+async fn discard_files(db: &Db, filename: &str) {
+    let _ = fs::remove_file(media_root().join(filename)).await;
+    discard_variants(db, filename).await;
+}
+
+async fn discard_variants(db: &Db, filename: &str) {
+    let source = filename.to_string();
+    let db = db.clone();
+    let variants = task::spawn_blocking(move || {
+        let conn = db.write()?;
+        let variants = load_variants(&conn, &source)?;
+        conn.execute("DELETE FROM media_variants WHERE source_filename = ?1", [&source])
+            .map_err(|err| format!("Failed to delete media variants: {err}"))?;
+        Ok::<Vec<MediaVariant>, String>(variants)
+    })
+    .await;
+    if let Ok(Ok(variants)) = variants {
+        for variant in variants {
+            let _ = fs::remove_file(variants_root().join(variant.filename)).await;
+        }
+    }
+}
+
+const SELECT_MEDIA: &str = "SELECT filename, content_hash, mime_type, size_bytes, width, height, duration_ms, uploaded_by, alt_text, created_at FROM media";
+
+fn read_record(row: &Row<'_>) -> rusqlite::Result<MediaRecord> {
+    Ok(MediaRecord {
+        filename: row.get(0)?,
+        content_hash: row.get(1)?,
+        mime_type: row.get(2)?,
+        size_bytes: row.get(3)?,
+        width: row.get(4)?,
+        height: row.get(5)?,
+        duration_ms: row.get(6)?,
+        uploaded_by: row.get(7)?,
+        alt_text: row.get(8)?,
+        created_at: row.get(9)?,
+        variants: Vec::new(),
+    })
+}
+
+fn load_variants(conn: &Connection, filename: &str) -> Result<Vec<MediaVariant>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT variant, filename, mime_type, width, height, size_bytes FROM media_variants WHERE source_filename = ?1 ORDER BY width ASC, variant ASC",
+        )
+        .map_err(|err| format!("Failed to prepare query: {err}"))?;
+    let rows = stmt
+        .query_map([filename], |row| {
+            let variant: String = row.get(0)?;
+            let width: u32 = row.get(3)?;
+            let mime_type: String = row.get(2)?;
+            let url = if mime_type == "image/webp" {
+                format!("{}?w={width}&format=webp", media_url(filename))
+            } else {
+                format!("{}?w={width}", media_url(filename))
+            };
+            Ok(MediaVariant {
+                variant,
+                filename: row.get(1)?,
+                mime_type,
+                width,
+                height: row.get(4)?,
+                size_bytes: row.get(5)?,
+                url,
+            })
+        })
+        .map_err(|err| format!("Failed to query media variants: {err}"))?;
+
+    let mut variants = Vec::new();
+    for row in rows {
+        variants.push(row.map_err(|err| format!("Failed to read row: {err}"))?);
+    }
+    Ok(variants)
+}
+
+fn find_by_filename(conn: &Connection, filename: &str) -> Result<Option<MediaRecord>, String> {
+    let record = conn
+        .query_row(
+            &format!("{SELECT_MEDIA} WHERE filename = ?1"),
+            [filename],
+            read_record,
+        )
+        .optional()
+        .map_err(|err| format!("Failed to read media record: {err}"))?;
+
+    match record {
+        Some(mut record) => {
+            record.variants = load_variants(conn, &record.filename)?;
+            Ok(Some(record))
+        }
+        None => Ok(None),
+    }
+}
+
+async fn find_by_hash(db: &Db, hash: &str) -> Result<Option<MediaRecord>, MediaError> {
+    let hash = hash.to_string();
+    let db = db.clone();
+    task::spawn_blocking(move || {
+        let conn = db.read()?;
+        let filename: Option<String> = conn
+            .query_row(
+                "SELECT filename FROM media WHERE content_hash = ?1",
+                [hash],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|err| format!("Failed to read media record: {err}"))?;
+        match filename {
+            Some(filename) => find_by_filename(&conn, &filename),
+            None => Ok(None),
+        }
+    })
+    .await
+    .map_err(|_| MediaError::Io("Failed to join blocking task".to_string()))?
+    .map_err(MediaError::Database)
+}
+
+/// Records an asset. Returns `None` when an asset with the same content is
+/// already recorded.
+async fn insert_record(db: &Db, record: MediaRecord) -> Result<Option<MediaRecord>, MediaError> {
+    let db = db.clone();
+    task::spawn_blocking(move || {
+        let conn = db.write()?;
+        let inserted = conn.execute(
+            "
+            INSERT INTO media (filename, content_hash, mime_type, size_bytes, width, height, duration_ms, uploaded_by, alt_text, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, datetime('now'))
+            ",
+            params![
+                record.filename,
+                record.content_hash,
+                record.mime_type,
+                record.size_bytes,
+                record.width,
+                record.height,
+                record.duration_ms,
+                record.uploaded_by,
+                record.alt_text,
+            ],
+        );
+        match inserted {
+            Ok(_) => find_by_filename(&conn, &record.filename)?
+                .map(Some)
+                .ok_or_else(|| "Media record missing after insert".to_string()),
+            Err(rusqlite::Error::SqliteFailure(err, _))
+                if err.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_UNIQUE =>
+            {
+                Ok(None)
+            }
+            Err(err) => Err(format!("Failed to save media record: {err}")),
+        }
+    })
+    .await
+    .map_err(|_| MediaError::Io("Failed to join blocking task".to_string()))?
+    .map_err(MediaError::Database)
+}
+
+/// Claims `name`, or `stem-1.ext`, `stem-2.ext`, ... if that is already
+/// taken, by creating an empty file under it. Creation fails if the file
+/// exists, so two uploads can never be handed the same name; the caller
+/// renames its upload over the placeholder.
+async fn reserve_filename(root: &Path, name: &str) -> Result<String, MediaError> {
+    let (stem, ext) = match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem, Some(ext)),
+        _ => (name, None),
+    };
+
+    let mut candidate = name.to_string();
+    let mut counter = 1;
+    loop {
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(root.join(&candidate))
+            .await
+        {
+            Ok(_) => return Ok(candidate),
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(err) => return Err(MediaError::Io(err.to_string())),
+        }
+        candidate = match ext {
+            Some(ext) => format!("{stem}-{counter}.{ext}"),
+            None => format!("{stem}-{counter}"),
+        };
+        counter += 1;
+    }
+}
+
+/// Adds metadata rows for files that were placed in the media directory
+/// before the table existed or by hand. Run once at startup; files added
+/// by hand later are not served until the next start.
+pub async fn index_untracked_files(db: &Db) -> Result<(), MediaError> {
+    let Ok(mut entries) = fs::read_dir(media_root()).await else {
+        return Ok(());
+    };
+
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let Ok(file_type) = entry.file_type().await else {
+            continue;
+        };
+        if !file_type.is_file() {
+            continue;
+        }
+        let filename = entry.file_name().to_string_lossy().to_string();
+        if get_media(db, &filename).await?.is_some() {
+            continue;
+        }
+        index_file(db, filename).await?;
+    }
+
     Ok(())
 }
+
+/// Records an untracked file. A copy of an asset that is already recorded
+/// is described but not recorded, so it is served as it is. Uploads in
+/// progress and their empty name reservations are skipped.
+async fn index_file(db: &Db, filename: String) -> Result<Option<MediaRecord>, MediaError> {
+    if filename.starts_with(TEMP_PREFIX) {
+        return Ok(None);
+    }
+    let bytes = fs::read(media_root().join(&filename))
+        .await
+        .map_err(|err| MediaError::Io(err.to_string()))?;
+    if bytes.is_empty() {
+        return Ok(None);
+    }
+
+    let mime = sniff_mime(&bytes);
+    let dimensions = probe_dimensions(&bytes, mime);
+    let record = MediaRecord {
+        filename,
+        content_hash: content_hash(&bytes),
+        mime_type: mime.to_string(),
+        size_bytes: bytes.len() as u64,
+        width: dimensions.map(|(width, _)| width),
+        height: dimensions.map(|(_, height)| height),
+        duration_ms: probe_duration_ms(&bytes, mime),
+        uploaded_by: None,
+        alt_text: None,
+        created_at: String::new(),
+        variants: Vec::new(),
+    };
+    if find_by_hash(db, &record.content_hash).await?.is_some() {
+        return Ok(Some(record));
+    }
+    create_variants(db, &record.filename, bytes, mime).await?;
+    match insert_record(db, record.clone()).await? {
+        Some(record) => Ok(Some(record)),
+        None => {
+            discard_variants(db, &record.filename).await;
+            Ok(Some(record))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ftyp(brand: &[u8; 4]) -> Vec<u8> {
+        [&[0, 0, 0, 0x20][..], b"ftyp", brand, &[0; 20]].concat()
+    }
+
+    #[test]
+    fn sniffs_mp4_only_for_mp4_brands() {
+        for brand in [b"isom", b"mp41", b"mp42", b"avc1", b"M4V "] {
+            assert_eq!(sniff_mime(&ftyp(brand)), "video/mp4");
+        }
+        assert_eq!(sniff_mime(&ftyp(b"M4A ")), "audio/mp4");
+        for brand in [b"heic", b"qt  ", b"3gp4"] {
+            assert_eq!(sniff_mime(&ftyp(brand)), DEFAULT_MIME);
+        }
+    }
+
+    fn variant(name: &str, mime_type: &str, width: u32) -> MediaVariant {
+        MediaVariant {
+            variant: name.to_string(),
+            filename: format!("a.png.{name}"),
+            mime_type: mime_type.to_string(),
+            width,
+            height: width,
+            size_bytes: 1,
+            url: String::new(),
+        }
+    }
+
+    #[test]
+    fn resolves_webp_variant_of_requested_width() {
+        let record = MediaRecord {
+            filename: "a.png".to_string(),
+            content_hash: "h".to_string(),
+            mime_type: "image/png".to_string(),
+            size_bytes: 1,
+            width: Some(2000),
+            height: Some(2000),
+            duration_ms: None,
+            uploaded_by: None,
+            alt_text: None,
+            created_at: String::new(),
+            variants: vec![
+                variant("thumbnail", "image/png", 160),
+                variant("large", "image/png", 1280),
+                variant("thumbnail-webp", "image/webp", 160),
+                variant("webp", "image/webp", 1280),
+            ],
+        };
+        let served = |width, webp| {
+            let path = resolve_variant(&record, width, webp).path;
+            path.file_name().unwrap().to_string_lossy().to_string()
+        };
+
+        assert_eq!(served(Some(100), true), "a.png.thumbnail-webp");
+        assert_eq!(served(Some(1000), true), "a.png.webp");
+        assert_eq!(served(None, true), "a.png.webp");
+        // No WebP is wide enough, so the format is ignored.
+        assert_eq!(served(Some(1600), true), "a.png");
+        assert_eq!(served(Some(100), false), "a.png.thumbnail");
+    }
+
+    fn image_bytes(width: u32, height: u32, format: ImageFormat) -> Vec<u8> {
+        let image = DynamicImage::ImageRgb8(image::RgbImage::new(width, height));
+        encode(&image, format).unwrap()
+    }
+
+    #[test]
+    fn generates_narrower_variants_and_a_webp_copy() {
+        let bytes = image_bytes(800, 400, ImageFormat::Png);
+        let variants = generate_variants("a.png", &bytes, "image/png");
+        let summary = variants
+            .iter()
+            .map(|variant| (variant.variant, variant.width, variant.height, variant.mime_type))
+            .collect::<Vec<_>>();
+
+        // Nothing is scaled up: "large" is wider than the original, and the
+        // full-size WebP keeps the original width.
+        assert_eq!(summary, [
+            ("thumbnail", 160, 80, "image/png"),
+            ("medium", 640, 320, "image/png"),
+            ("thumbnail-webp", 160, 80, "image/webp"),
+            ("medium-webp", 640, 320, "image/webp"),
+            ("webp", 800, 400, "image/webp"),
+        ]);
+        for variant in &variants {
+            assert_eq!(sniff_mime(&variant.bytes), variant.mime_type);
+            assert!(variant.filename.starts_with(&format!("a.png.{}.", variant.variant)));
+        }
+    }
+
+    #[test]
+    fn small_images_get_only_a_webp_copy_and_gifs_none() {
+        let jpeg = image_bytes(100, 50, ImageFormat::Jpeg);
+        let variants = generate_variants("a.jpg", &jpeg, "image/jpeg");
+        assert_eq!(variants.len(), 1);
+        assert_eq!((variants[0].variant, variants[0].width), ("webp", 100));
+
+        let gif = image_bytes(800, 400, ImageFormat::Gif);
+        assert!(generate_variants("a.gif", &gif, "image/gif").is_empty());
+    }
+}
@@ -0,0 +1,578 @@
+use rusqlite::{Connection, TransactionBehavior};
+use std::path::Path;
+
+use crate::core::media;
+
+use crate::core::users::DEFAULT_USER_ID;
+
+/// A numbered schema change. The database records the highest version
+/// applied in `PRAGMA user_version`.
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    apply: fn(&Connection) -> rusqlite::Result<()>,
+}
+
+/// Every migration, oldest first. Append new ones with the next version and
+/// never edit one that has shipped.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "baseline",
+        apply: baseline,
+    },
+    Migration {
+        version: 2,
+        name: "leech suspensions",
+        apply: leech_suspensions,
+    },
+    Migration {
+        version: 3,
+        name: "unique media content",
+        apply: unique_media_content,
+    },
+    Migration {
+        version: 4,
+        name: "exercise attempts",
+        apply: exercise_attempts,
+    },
+];
+
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+pub fn current_version(conn: &Connection) -> rusqlite::Result<i64> {
+    conn.pragma_query_value(None, "user_version", |row| row.get(0))
+}
+
+/// Migrations newer than the database's version.
+pub fn pending(conn: &Connection) -> Result<Vec<&'static Migration>, String> {
+    let current = current_version(conn).map_err(|err| format!("Failed to read schema version: {err}"))?;
+    if current > latest_version() {
+        return Err(format!(
+            "Database schema version {current} is newer than this build knows ({})",
+            latest_version()
+        ));
+    }
+    Ok(MIGRATIONS
+        .iter()
+        .filter(|migration| migration.version > current)
+        .collect())
+}
+
+/// Applies pending migrations in order, each in its own transaction together
+/// with the version bump, so a failed migration leaves the database at the
+/// previous version. Returns the migrations applied.
+pub fn migrate(conn: &mut Connection) -> Result<Vec<&'static Migration>, String> {
+    let mut applied = Vec::new();
+    for migration in pending(conn)? {
+        let failed = |err| format!("Migration {} ({}) failed: {err}", migration.version, migration.name);
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(failed)?;
+        // Another process may have migrated while this one waited for the lock.
+        if current_version(&tx).map_err(failed)? >= migration.version {
+            continue;
+        }
+        (migration.apply)(&tx).map_err(failed)?;
+        tx.pragma_update(None, "user_version", migration.version)
+            .map_err(failed)?;
+        tx.commit().map_err(failed)?;
+        applied.push(migration);
+    }
+    Ok(applied)
+}
+
+/// Everything the schema held before migrations were versioned. Databases
+/// from that time may be at any point of it, so every step checks what is
+/// already there.
+fn baseline(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(&format!(
+        "
+        CREATE TABLE IF NOT EXISTS users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            auth_id TEXT UNIQUE,
+            email TEXT,
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            last_seen_at TEXT
+        );
+        INSERT OR IGNORE INTO users (id) VALUES ({DEFAULT_USER_ID});
+        CREATE TABLE IF NOT EXISTS admin_roles (
+            email TEXT PRIMARY KEY,
+            role TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS admin_audit_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            actor TEXT NOT NULL,
+            action TEXT NOT NULL,
+            target_type TEXT NOT NULL,
+            target TEXT NOT NULL,
+            before TEXT,
+            after TEXT,
+            created_at TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS admin_audit_log_target ON admin_audit_log (target_type, target, id);
+        CREATE TRIGGER IF NOT EXISTS admin_audit_log_no_update
+            BEFORE UPDATE ON admin_audit_log
+            BEGIN SELECT RAISE(ABORT, 'admin_audit_log is append-only'); END;
+        CREATE TRIGGER IF NOT EXISTS admin_audit_log_no_delete
+            BEFORE DELETE ON admin_audit_log
+            BEGIN SELECT RAISE(ABORT, 'admin_audit_log is append-only'); END;
+        CREATE TABLE IF NOT EXISTS fsrs_review_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            card_id TEXT NOT NULL,
+            rating INTEGER NOT NULL,
+            reviewed_at TEXT NOT NULL,
+            elapsed_days INTEGER NOT NULL,
+            stability_before REAL,
+            difficulty_before REAL,
+            stability_after REAL NOT NULL,
+            difficulty_after REAL NOT NULL,
+            last_review_before TEXT,
+            due_before TEXT,
+            interval_before INTEGER,
+            interval_days INTEGER NOT NULL,
+            due_date TEXT NOT NULL,
+            state_before TEXT,
+            step_before INTEGER,
+            state_after TEXT,
+            session_id TEXT
+        );
+        CREATE INDEX IF NOT EXISTS fsrs_review_log_card ON fsrs_review_log (card_id, id);
+        CREATE TABLE IF NOT EXISTS flashcard_decks (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            tag TEXT,
+            lemma TEXT,
+            created_at TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS dictionary_cache (
+            cache_key TEXT PRIMARY KEY,
+            word TEXT NOT NULL,
+            definition TEXT NOT NULL,
+            examples TEXT NOT NULL,
+            updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );
+        CREATE TABLE IF NOT EXISTS media (
+            filename TEXT PRIMARY KEY,
+            content_hash TEXT NOT NULL,
+            mime_type TEXT NOT NULL,
+            size_bytes INTEGER NOT NULL,
+            width INTEGER,
+            height INTEGER,
+            duration_ms INTEGER,
+            uploaded_by TEXT,
+            alt_text TEXT,
+            created_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );
+        CREATE INDEX IF NOT EXISTS media_content_hash ON media (content_hash);
+        CREATE TABLE IF NOT EXISTS media_variants (
+            source_filename TEXT NOT NULL,
+            variant TEXT NOT NULL,
+            filename TEXT NOT NULL,
+            mime_type TEXT NOT NULL,
+            width INTEGER NOT NULL,
+            height INTEGER NOT NULL,
+            size_bytes INTEGER NOT NULL,
+            PRIMARY KEY (source_filename, variant)
+        );
+        "
+    ))?;
+    for (_, definition, _) in USER_TABLES {
+        conn.execute_batch(&definition.replace("CREATE TABLE", "CREATE TABLE IF NOT EXISTS"))?;
+    }
+
+    // Columns added after the tables first shipped.
+    ensure_column(conn, "fsrs_cards", "state", "TEXT NOT NULL DEFAULT 'review'")?;
+    ensure_column(conn, "fsrs_cards", "step", "INTEGER NOT NULL DEFAULT 0")?;
+    ensure_column(conn, "fsrs_review_log", "state_before", "TEXT")?;
+    ensure_column(conn, "fsrs_review_log", "step_before", "INTEGER")?;
+    ensure_column(conn, "fsrs_review_log", "state_after", "TEXT")?;
+    ensure_column(conn, "fsrs_review_log", "session_id", "TEXT")?;
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS fsrs_review_log_session ON fsrs_review_log (session_id, id);",
+    )?;
+
+    scope_to_users(conn)?;
+    ensure_column(
+        conn,
+        "fsrs_review_log",
+        "user_id",
+        &format!("INTEGER NOT NULL DEFAULT {DEFAULT_USER_ID}"),
+    )?;
+    ensure_column(
+        conn,
+        "flashcard_decks",
+        "user_id",
+        &format!("INTEGER NOT NULL DEFAULT {DEFAULT_USER_ID}"),
+    )?;
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS fsrs_review_log_user ON fsrs_review_log (user_id, card_id, id);",
+    )?;
+
+    Ok(())
+}
+
+/// Learner tables keyed on `user_id`: name, definition and the columns they
+/// had before learner accounts existed.
+const USER_TABLES: [(&str, &str, &str); 5] = [
+    (
+        "fsrs_cards",
+        "
+        CREATE TABLE fsrs_cards (
+            user_id INTEGER NOT NULL,
+            card_id TEXT NOT NULL,
+            stability REAL NOT NULL,
+            difficulty REAL NOT NULL,
+            last_review TEXT,
+            due_date TEXT,
+            interval_days INTEGER NOT NULL,
+            state TEXT NOT NULL DEFAULT 'review',
+            step INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (user_id, card_id)
+        );
+        ",
+        "card_id, stability, difficulty, last_review, due_date, interval_days, state, step",
+    ),
+    (
+        "fsrs_settings",
+        "
+        CREATE TABLE fsrs_settings (
+            user_id INTEGER NOT NULL,
+            key TEXT NOT NULL,
+            value TEXT NOT NULL,
+            PRIMARY KEY (user_id, key)
+        );
+        ",
+        "key, value",
+    ),
+    (
+        "flashcard_deck_options",
+        "
+        CREATE TABLE flashcard_deck_options (
+            user_id INTEGER NOT NULL,
+            deck_id TEXT NOT NULL,
+            desired_retention REAL NOT NULL,
+            PRIMARY KEY (user_id, deck_id)
+        );
+        ",
+        "deck_id, desired_retention",
+    ),
+    (
+        "flashcard_flags",
+        "
+        CREATE TABLE flashcard_flags (
+            user_id INTEGER NOT NULL,
+            card_id TEXT NOT NULL,
+            suspended INTEGER NOT NULL DEFAULT 0,
+            buried_until TEXT,
+            PRIMARY KEY (user_id, card_id)
+        );
+        ",
+        "card_id, suspended, buried_until",
+    ),
+    (
+        "progress",
+        "
+        CREATE TABLE progress (
+            user_id INTEGER NOT NULL,
+            lesson_id TEXT NOT NULL,
+            completed INTEGER NOT NULL,
+            updated_at TEXT NOT NULL DEFAULT (datetime('now')),
+            PRIMARY KEY (user_id, lesson_id)
+        );
+        ",
+        "lesson_id, completed, updated_at",
+    ),
+];
+
+/// Databases from before learner accounts hold a single learner's data with
+/// the learner tables keyed on card, setting or lesson alone. Each such
+/// table is rebuilt with a `user_id` key and its rows handed to the default
+/// user.
+fn scope_to_users(conn: &Connection) -> rusqlite::Result<()> {
+    for (table, definition, columns) in USER_TABLES {
+        if has_column(conn, table, "user_id")? {
+            continue;
+        }
+        conn.execute_batch(&format!(
+            "
+            ALTER TABLE {table} RENAME TO {table}_unscoped;
+            {definition}
+            INSERT INTO {table} (user_id, {columns})
+                SELECT {DEFAULT_USER_ID}, {columns} FROM {table}_unscoped;
+            DROP TABLE {table}_unscoped;
+            "
+        ))?;
+    }
+    Ok(())
+}
+
+/// Marks reviews that suspended their card as a leech, so undoing the
+/// review can unsuspend it.
+fn leech_suspensions(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "ALTER TABLE fsrs_review_log ADD COLUMN suspended_card INTEGER NOT NULL DEFAULT 0;",
+    )
+}
+
+/// One row per uploaded content hash, so concurrent uploads of the same file
+/// cannot both be recorded.
+fn unique_media_content(conn: &Connection) -> rusqlite::Result<()> {
+    remove_duplicate_media(conn, &media::variants_root())
+}
+
+/// Where earlier races left several rows for one content hash, keeps the
+/// oldest. The newer originals stay on disk and are still served as
+/// untracked files; their variants are deleted with their rows, since
+/// nothing refers to them any more.
+fn remove_duplicate_media(conn: &Connection, variants_dir: &Path) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "
+        CREATE TEMP TABLE duplicate_media AS
+            SELECT filename FROM media m
+            WHERE EXISTS (
+                SELECT 1 FROM media older
+                WHERE older.content_hash = m.content_hash
+                  AND (older.created_at, older.rowid) < (m.created_at, m.rowid)
+            );
+        ",
+    )?;
+    let orphaned = conn
+        .prepare(
+            "SELECT filename FROM media_variants WHERE source_filename IN (SELECT filename FROM duplicate_media)",
+        )?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    conn.execute_batch(
+        "
+        DELETE FROM media_variants WHERE source_filename IN (SELECT filename FROM duplicate_media);
+        DELETE FROM media WHERE filename IN (SELECT filename FROM duplicate_media);
+        DROP TABLE duplicate_media;
+        DROP INDEX media_content_hash;
+        CREATE UNIQUE INDEX media_content_hash ON media (content_hash);
+        ",
+    )?;
+    for filename in orphaned {
+        let _ = std::fs::remove_file(variants_dir.join(filename));
+    }
+    Ok(())
+}
+
+/// Every answer a learner submits to a lesson exercise.
+fn exercise_attempts(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "
+        CREATE TABLE exercise_attempts (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL,
+            lesson_id TEXT NOT NULL,
+            exercise_id TEXT NOT NULL,
+            answer TEXT NOT NULL,
+            correct INTEGER,
+            attempted_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );
+        CREATE INDEX exercise_attempts_user ON exercise_attempts (user_id, lesson_id, id);
+        ",
+    )
+}
+
+fn ensure_column(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> rusqlite::Result<()> {
+    if !has_column(conn, table, column)? {
+        conn.execute_batch(&format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"))?;
+    }
+    Ok(())
+}
+
+fn has_column(conn: &Connection, table: &str, column: &str) -> rusqlite::Result<bool> {
+    Ok(conn
+        .prepare(&format!("PRAGMA table_info({table})"))?
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<rusqlite::Result<Vec<_>>>()?
+        .iter()
+        .any(|name| name == column))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The schema each build created on an empty database before migrations
+    /// were versioned, named after the change it brought in.
+    const SNAPSHOTS: [(&str, &str); 11] = [
+        ("baseline", include_str!("../../tests/fixtures/schema/01-baseline.sql")),
+        ("media", include_str!("../../tests/fixtures/schema/02-media.sql")),
+        ("media variants", include_str!("../../tests/fixtures/schema/03-media-variants.sql")),
+        ("review log", include_str!("../../tests/fixtures/schema/04-review-log.sql")),
+        ("learning states", include_str!("../../tests/fixtures/schema/05-learning-states.sql")),
+        ("decks", include_str!("../../tests/fixtures/schema/06-decks.sql")),
+        ("review sessions", include_str!("../../tests/fixtures/schema/07-review-sessions.sql")),
+        ("card flags", include_str!("../../tests/fixtures/schema/08-card-flags.sql")),
+        ("learner accounts", include_str!("../../tests/fixtures/schema/09-learner-accounts.sql")),
+        ("cms roles", include_str!("../../tests/fixtures/schema/10-cms-roles.sql")),
+        ("audit log", include_str!("../../tests/fixtures/schema/11-audit-log.sql")),
+    ];
+
+    /// Tables with their columns (name, type, not null, default, key
+    /// position), indexes with their columns, and trigger names.
+    fn shape(conn: &Connection) -> Vec<String> {
+        let names = |kind: &str| {
+            conn.prepare(
+                "SELECT name FROM sqlite_master WHERE type = ?1 AND name NOT LIKE 'sqlite_%' ORDER BY name",
+            )
+            .unwrap()
+            .query_map([kind], |row| row.get::<_, String>(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap()
+        };
+        let mut shape = Vec::new();
+        for table in names("table") {
+            let mut stmt = conn.prepare(&format!("PRAGMA table_info({table})")).unwrap();
+            let columns = stmt
+                .query_map([], |row| {
+                    Ok(format!(
+                        "{} {} {} {:?} {}",
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, bool>(3)?,
+                        row.get::<_, Option<String>>(4)?,
+                        row.get::<_, i64>(5)?,
+                    ))
+                })
+                .unwrap()
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .unwrap();
+            shape.push(format!("table {table}: {}", columns.join(", ")));
+        }
+        for index in names("index") {
+            let mut stmt = conn.prepare(&format!("PRAGMA index_info({index})")).unwrap();
+            let columns = stmt
+                .query_map([], |row| row.get::<_, String>(2))
+                .unwrap()
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .unwrap();
+            shape.push(format!("index {index}: {}", columns.join(", ")));
+        }
+        for trigger in names("trigger") {
+            shape.push(format!("trigger {trigger}"));
+        }
+        shape
+    }
+
+    fn fresh() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn migrates_empty_database_to_latest() {
+        let conn = fresh();
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+        let learners: i64 = conn
+            .query_row("SELECT count(*) FROM users WHERE id = ?1", [DEFAULT_USER_ID], |row| row.get(0))
+            .unwrap();
+        assert_eq!(learners, 1);
+    }
+
+    #[test]
+    fn upgrades_every_historic_schema() {
+        let expected = shape(&fresh());
+        for (name, snapshot) in SNAPSHOTS {
+            let mut conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(snapshot).unwrap();
+            let scoped = has_column(&conn, "fsrs_cards", "user_id").unwrap();
+            let user = if scoped { "user_id, " } else { "" };
+            let id = if scoped { "1, " } else { "" };
+            conn.execute_batch(&format!(
+                "
+                INSERT INTO fsrs_cards ({user}card_id, stability, difficulty, interval_days)
+                    VALUES ({id}'w1', 2.5, 5.0, 3);
+                INSERT INTO fsrs_settings ({user}key, value) VALUES ({id}'desired_retention', '0.85');
+                INSERT INTO progress ({user}lesson_id, completed) VALUES ({id}'l1', 1);
+                "
+            ))
+            .unwrap();
+
+            let applied = migrate(&mut conn).unwrap();
+            assert_eq!(applied.len(), MIGRATIONS.len(), "migrations applied to the {name} schema");
+            assert_eq!(current_version(&conn).unwrap(), latest_version());
+            assert_eq!(shape(&conn), expected, "schema upgraded from the {name} schema");
+
+            let kept: (i64, f64, String, i64) = conn
+                .query_row(
+                    "
+                    SELECT c.user_id, c.stability, s.value, p.completed
+                    FROM fsrs_cards c
+                    JOIN fsrs_settings s ON s.user_id = c.user_id
+                    JOIN progress p ON p.user_id = c.user_id
+                    WHERE c.card_id = 'w1' AND s.key = 'desired_retention' AND p.lesson_id = 'l1'
+                    ",
+                    [],
+                    |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+                )
+                .unwrap();
+            assert_eq!(kept, (DEFAULT_USER_ID, 2.5, "0.85".to_string(), 1));
+        }
+    }
+
+    #[test]
+    fn keeps_oldest_of_duplicate_media_and_removes_its_variants() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(SNAPSHOTS[10].1).unwrap();
+        conn.execute_batch(
+            "
+            INSERT INTO media (filename, content_hash, mime_type, size_bytes, created_at)
+                VALUES ('a-1.png', 'h', 'image/png', 3, '2025-01-02 00:00:00'),
+                       ('a.png', 'h', 'image/png', 3, '2025-01-01 00:00:00'),
+                       ('b.png', 'other', 'image/png', 3, '2025-01-03 00:00:00');
+            INSERT INTO media_variants (source_filename, variant, filename, mime_type, width, height, size_bytes)
+                VALUES ('a-1.png', 'thumbnail', 'a-1.png.thumbnail.png', 'image/png', 1, 1, 1),
+                       ('a.png', 'thumbnail', 'a.png.thumbnail.png', 'image/png', 1, 1, 1);
+            ",
+        )
+        .unwrap();
+        let variants_dir = std::env::temp_dir().join(format!("avvai-variants-{}", std::process::id()));
+        std::fs::create_dir_all(&variants_dir).unwrap();
+        for filename in ["a-1.png.thumbnail.png", "a.png.thumbnail.png"] {
+            std::fs::write(variants_dir.join(filename), b"png").unwrap();
+        }
+
+        remove_duplicate_media(&conn, &variants_dir).unwrap();
+
+        let mut stmt = conn.prepare("SELECT filename FROM media ORDER BY filename").unwrap();
+        let kept = stmt
+            .query_map([], |row| row.get::<_, String>(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(kept, ["a.png", "b.png"]);
+        let variants: Vec<String> = conn
+            .prepare("SELECT filename FROM media_variants")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(variants, ["a.png.thumbnail.png"]);
+        assert!(!variants_dir.join("a-1.png.thumbnail.png").exists());
+        assert!(variants_dir.join("a.png.thumbnail.png").exists());
+        let _ = std::fs::remove_dir_all(&variants_dir);
+    }
+
+    #[test]
+    fn refuses_newer_database() {
+        let conn = fresh();
+        conn.pragma_update(None, "user_version", latest_version() + 1).unwrap();
+        assert!(pending(&conn).is_err());
+    }
+}
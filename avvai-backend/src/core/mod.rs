@@ -1,9 +1,14 @@
+pub mod admin_roles;
+pub mod audit;
 pub mod db;
 pub mod dictionary;
 pub mod dictionary_cache;
+pub mod exercises;
 pub mod flashcards;
 pub mod lemmatise;
 pub mod lesson;
+pub mod migrations;
 pub mod media;
 pub mod openrouter;
 pub mod progress;
+pub mod users;
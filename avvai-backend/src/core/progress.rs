@@ -1,20 +1,19 @@
 use rusqlite::params;
 use std::collections::HashMap;
-use std::sync::Arc;
 use tokio::task;
 
-pub async fn load_progress(
-    db: Arc<std::sync::Mutex<rusqlite::Connection>>,
-) -> Result<HashMap<String, bool>, String> {
+use crate::core::db::Db;
+
+pub async fn load_progress(db: Db, user_id: i64) -> Result<HashMap<String, bool>, String> {
     task::spawn_blocking(move || {
-        let conn = db.lock().map_err(|_| "DB lock poisoned".to_string())?;
+        let conn = db.read()?;
         let map = {
             let mut stmt = conn
-                .prepare("SELECT lesson_id, completed FROM progress")
+                .prepare("SELECT lesson_id, completed FROM progress WHERE user_id = ?1")
                 .map_err(|err| format!("Failed to prepare query: {err}"))?;
 
             let rows = stmt
-                .query_map([], |row| {
+                .query_map([user_id], |row| {
                     let lesson_id: String = row.get(0)?;
                     let completed: i64 = row.get(1)?;
                     Ok((lesson_id, completed != 0))
@@ -38,23 +37,23 @@ pub async fn load_progress(
 }
 
 pub async fn upsert_progress(
-    db: Arc<std::sync::Mutex<rusqlite::Connection>>,
+    db: Db,
+    user_id: i64,
     lesson_id: &str,
     completed: bool,
 ) -> Result<(), String> {
     let lesson_id = lesson_id.to_string();
     task::spawn_blocking(move || {
-        db.lock()
-            .map_err(|_| "DB lock poisoned".to_string())?
+        db.write()?
             .execute(
             "
-            INSERT INTO progress (lesson_id, completed, updated_at)
-            VALUES (?1, ?2, datetime('now'))
-            ON CONFLICT(lesson_id) DO UPDATE SET
+            INSERT INTO progress (user_id, lesson_id, completed, updated_at)
+            VALUES (?1, ?2, ?3, datetime('now'))
+            ON CONFLICT(user_id, lesson_id) DO UPDATE SET
                 completed = excluded.completed,
                 updated_at = excluded.updated_at
             ",
-            params![lesson_id, i64::from(completed)],
+            params![user_id, lesson_id, i64::from(completed)],
             )
             .map_err(|err| format!("Failed to save progress: {err}"))?;
         Ok::<(), String>(())
@@ -0,0 +1,48 @@
+use rusqlite::{params, OptionalExtension};
+use tokio::task;
+
+use crate::core::db::Db;
+
+/// Owner of the data recorded before learner accounts existed, and of every
+/// request made without a sign-in when anonymous learners are allowed.
+pub const DEFAULT_USER_ID: i64 = 1;
+
+/// Finds or creates the learner for a Supabase account, keeping the stored
+/// email current. Returns the local user id. A known account whose email
+/// has not changed is only read, so signing in does not write every time.
+pub async fn upsert_user(db: Db, auth_id: &str, email: Option<String>) -> Result<i64, String> {
+    let auth_id = auth_id.to_string();
+    task::spawn_blocking(move || {
+        let known = db
+            .read()?
+            .query_row(
+                "SELECT id, email FROM users WHERE auth_id = ?1",
+                [&auth_id],
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, Option<String>>(1)?)),
+            )
+            .optional()
+            .map_err(|err| format!("Failed to read user: {err}"))?;
+        if let Some((user_id, stored)) = known
+            && stored == email
+        {
+            return Ok(user_id);
+        }
+
+        db.write()?
+            .query_row(
+                "
+                INSERT INTO users (auth_id, email, last_seen_at)
+                VALUES (?1, ?2, datetime('now'))
+                ON CONFLICT(auth_id) DO UPDATE SET
+                    email = excluded.email,
+                    last_seen_at = excluded.last_seen_at
+                RETURNING id
+                ",
+                params![auth_id, email],
+                |row| row.get(0),
+            )
+            .map_err(|err| format!("Failed to save user: {err}"))
+    })
+    .await
+    .map_err(|_| "Failed to join blocking task".to_string())?
+}
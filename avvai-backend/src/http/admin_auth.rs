@@ -1,74 +1,206 @@
 use axum::{
-    extract::FromRequestParts,
-    http::{request::Parts, HeaderMap, StatusCode},
+    extract::{FromRef, FromRequestParts},
+    http::{request::Parts, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
 };
-use serde::Deserialize;
 use std::{collections::HashSet, env, sync::Arc};
 
-#[allow(dead_code)]
-#[derive(Clone)]
+use crate::core::admin_roles::{self, Role};
+use crate::core::db::Db;
+use crate::http::supabase_auth::{extract_bearer, AuthError, TokenVerifier};
+
 pub struct AdminAuthState {
-    pub supabase_url: String,
-    pub anon_key: String,
-    pub allowed_emails: HashSet<String>,
+    verifier: Arc<TokenVerifier>,
+    db: Db,
 }
 
 impl AdminAuthState {
-    pub fn from_env() -> Result<Self, String> {
-        let supabase_url = env::var("PUBLIC_SUPABASE_URL")
-            .map_err(|_| "Missing PUBLIC_SUPABASE_URL".to_string())?;
-        let anon_key = env::var("PUBLIC_SUPABASE_PUBLISHABLE_DEFAULT_KEY")
-            .map_err(|_| "Missing PUBLIC_SUPABASE_PUBLISHABLE_DEFAULT_KEY".to_string())?;
-
+    pub fn from_env(db: Db, verifier: Arc<TokenVerifier>) -> Result<Self, String> {
+        // Roles live in the database; the env var only bootstraps admins.
         let allowed = env::var("ADMIN_ALLOWED_EMAILS")
             .unwrap_or_else(|_| String::new())
             .split(',')
-            .map(|email| email.trim().to_lowercase())
+            .map(admin_roles::normalise_email)
             .filter(|email| !email.is_empty())
             .collect::<HashSet<_>>();
+        match admin_roles::seed_admins(&db, &allowed) {
+            Ok(0) => {}
+            Ok(count) => println!("Granted the admin role to {count} email(s) from ADMIN_ALLOWED_EMAILS"),
+            Err(err) => eprintln!("Failed to seed admin roles: {err}"),
+        }
 
-        Ok(Self {
-            supabase_url,
-            anon_key,
-            allowed_emails: allowed,
-        })
+        Ok(Self { verifier, db })
     }
 }
 
-#[allow(dead_code)]
-#[derive(Deserialize)]
-struct SupabaseUser {
-    email: Option<String>,
-}
-
-#[allow(dead_code)]
+/// A signed-in CMS user with a role. Any role may read content; handlers
+/// that change it check the role they need with [`AdminUser::denied_unless`].
 pub struct AdminUser {
     pub email: String,
+    pub role: Role,
+}
+
+impl AdminUser {
+    /// A 403 response when the user's role is below `role`.
+    pub fn denied_unless(&self, role: Role) -> Option<Response> {
+        (self.role < role).then(|| {
+            (
+                StatusCode::FORBIDDEN,
+                Json(serde_json::json!({
+                    "error": format!("This action needs the {} role", role.as_str()),
+                })),
+            )
+                .into_response()
+        })
+    }
 }
 
 impl FromRequestParts<Arc<AdminAuthState>> for AdminUser {
-    type Rejection = (StatusCode, &'static str);
+    type Rejection = Response;
 
     async fn from_request_parts(
-        _parts: &mut Parts,
-        _state: &Arc<AdminAuthState>,
+        parts: &mut Parts,
+        state: &Arc<AdminAuthState>,
     ) -> Result<Self, Self::Rejection> {
-        // AUTH DISABLED - allowing all access
-        Ok(Self {
-            email: "admin@avvai.edu".to_string(),
-        })
+        let token = extract_bearer(&parts.headers)
+            .ok_or_else(|| reject((StatusCode::UNAUTHORIZED, "Sign-in required")))?;
+        let not_allowed = || reject((StatusCode::FORBIDDEN, "Not allowed to use the CMS"));
+        let email = state
+            .verifier
+            .verify(&token)
+            .await
+            .map_err(reject)?
+            .email
+            .map(|email| admin_roles::normalise_email(&email))
+            .ok_or_else(not_allowed)?;
+        let role = admin_roles::role_for(&state.db, &email)
+            .await
+            .map_err(|_| reject((StatusCode::INTERNAL_SERVER_ERROR, "Could not read CMS roles")))?
+            .ok_or_else(not_allowed)?;
+        Ok(Self { email, role })
     }
 }
 
-#[allow(dead_code)]
-fn extract_bearer(headers: &HeaderMap) -> Option<String> {
-    let header = headers.get("Authorization")?.to_str().ok()?;
-    let mut parts = header.split_whitespace();
-    let scheme = parts.next()?;
-    let token = parts.next()?;
-    if scheme.eq_ignore_ascii_case("Bearer") {
-        Some(token.to_string())
-    } else {
-        None
+/// Lets admin handlers take `State<Db>` alongside [`AdminUser`].
+impl FromRef<Arc<AdminAuthState>> for Db {
+    fn from_ref(state: &Arc<AdminAuthState>) -> Self {
+        state.db.clone()
+    }
+}
+
+fn reject((status, message): AuthError) -> Response {
+    (status, Json(serde_json::json!({"error": message}))).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body, http::Request};
+    use crate::http::supabase_auth::SUPABASE_AUDIENCE;
+    use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+    use serde_json::{json, Value};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    const SECRET: &str = "test-secret";
+
+    fn state() -> Arc<AdminAuthState> {
+        let db = Db::temporary();
+        let emails = HashSet::from(["admin@example.com".to_string()]);
+        admin_roles::seed_admins(&db, &emails).unwrap();
+        let verifier = TokenVerifier::new(
+            "http://supabase.invalid".to_string(),
+            "anon".to_string(),
+            Some(SECRET.to_string()),
+        );
+        Arc::new(AdminAuthState {
+            verifier: Arc::new(verifier),
+            db,
+        })
+    }
+
+    fn now() -> i64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64
+    }
+
+    fn mint(claims: Value, secret: &str) -> String {
+        encode(
+            &Header::new(Algorithm::HS256),
+            &claims,
+            &EncodingKey::from_secret(secret.as_bytes()),
+        )
+        .unwrap()
+    }
+
+    fn claims(email: Option<&str>) -> Value {
+        let mut claims = json!({"aud": SUPABASE_AUDIENCE, "exp": now() + 3600, "sub": "user"});
+        if let Some(email) = email {
+            claims["email"] = json!(email);
+        }
+        claims
+    }
+
+    async fn extract(token: Option<&str>) -> Result<AdminUser, Response> {
+        let mut request = Request::builder();
+        if let Some(token) = token {
+            request = request.header("Authorization", format!("Bearer {token}"));
+        }
+        let (mut parts, ()) = request.body(()).unwrap().into_parts();
+        AdminUser::from_request_parts(&mut parts, &state()).await
+    }
+
+    async fn assert_rejected(result: Result<AdminUser, Response>, status: StatusCode, error: &str) {
+        let response = result.err().expect("request should be rejected");
+        assert_eq!(response.status(), status);
+        let body = body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body, json!({"error": error}));
+    }
+
+    #[tokio::test]
+    async fn accepts_allowed_email() {
+        let token = mint(claims(Some("Admin@Example.com")), SECRET);
+        let user = extract(Some(&token)).await.expect("token should be accepted");
+        assert_eq!(user.email, "admin@example.com");
+        assert_eq!(user.role, Role::Admin);
+    }
+
+    #[tokio::test]
+    async fn rejects_expired_token() {
+        let mut claims = claims(Some("admin@example.com"));
+        claims["exp"] = json!(now() - 3600);
+        let token = mint(claims, SECRET);
+        assert_rejected(extract(Some(&token)).await, StatusCode::UNAUTHORIZED, "Invalid or expired token").await;
+    }
+
+    #[tokio::test]
+    async fn rejects_wrong_secret() {
+        let token = mint(claims(Some("admin@example.com")), "another-secret");
+        assert_rejected(extract(Some(&token)).await, StatusCode::UNAUTHORIZED, "Invalid or expired token").await;
+    }
+
+    #[tokio::test]
+    async fn rejects_wrong_audience() {
+        let mut claims = claims(Some("admin@example.com"));
+        claims["aud"] = json!("anon");
+        let token = mint(claims, SECRET);
+        assert_rejected(extract(Some(&token)).await, StatusCode::UNAUTHORIZED, "Invalid or expired token").await;
+    }
+
+    #[tokio::test]
+    async fn rejects_missing_email() {
+        let token = mint(claims(None), SECRET);
+        assert_rejected(extract(Some(&token)).await, StatusCode::FORBIDDEN, "Not allowed to use the CMS").await;
+    }
+
+    #[tokio::test]
+    async fn rejects_email_without_role() {
+        let token = mint(claims(Some("learner@example.com")), SECRET);
+        assert_rejected(extract(Some(&token)).await, StatusCode::FORBIDDEN, "Not allowed to use the CMS").await;
+    }
+
+    #[tokio::test]
+    async fn rejects_missing_bearer() {
+        assert_rejected(extract(None).await, StatusCode::UNAUTHORIZED, "Sign-in required").await;
     }
 }
@@ -0,0 +1,188 @@
+use axum::{
+    extract::FromRequestParts,
+    http::{request::Parts, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    env,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::core::{db::Db, users};
+use crate::http::supabase_auth::{extract_bearer, TokenVerifier};
+
+/// How long a verified token maps to its user before it is verified again.
+const TOKEN_CACHE_TTL: Duration = Duration::from_secs(5 * 60);
+const TOKEN_CACHE_MAX: usize = 1024;
+
+/// Configuration for identifying learners, added to learner-facing routers
+/// as an extension.
+pub struct LearnerAuthState {
+    verifier: Arc<TokenVerifier>,
+    /// When set, requests without a bearer token act as the default user,
+    /// which keeps single-learner deployments working without sign-in.
+    /// Off unless `LEARNER_ALLOW_ANONYMOUS` is set, since every anonymous
+    /// visitor would share that user's progress and schedule.
+    allow_anonymous: bool,
+    cache: Mutex<HashMap<String, (i64, Instant)>>,
+    db: Db,
+}
+
+impl LearnerAuthState {
+    pub fn from_env(db: Db, verifier: Arc<TokenVerifier>) -> Result<Self, String> {
+        let allow_anonymous = env::var("LEARNER_ALLOW_ANONYMOUS")
+            .is_ok_and(|value| matches!(value.trim(), "1" | "true" | "yes"));
+        if allow_anonymous {
+            println!("Requests without a sign-in act as the default learner (LEARNER_ALLOW_ANONYMOUS)");
+        }
+
+        Ok(Self {
+            verifier,
+            allow_anonymous,
+            cache: Mutex::new(HashMap::new()),
+            db,
+        })
+    }
+
+    /// Resolves a Supabase access token to a local user id, creating the user
+    /// on first sight.
+    async fn resolve(&self, token: &str) -> Result<i64, Response> {
+        let key = format!("{:x}", Sha256::digest(token.as_bytes()));
+        if let Some(user_id) = self.cached(&key) {
+            return Ok(user_id);
+        }
+
+        let user = self
+            .verifier
+            .verify(token)
+            .await
+            .map_err(|(status, message)| error(status, message))?;
+        let user_id = users::upsert_user(self.db.clone(), &user.id, user.email)
+            .await
+            .map_err(|err| error(StatusCode::INTERNAL_SERVER_ERROR, &err))?;
+        self.remember(key, user_id);
+        Ok(user_id)
+    }
+
+    fn cached(&self, key: &str) -> Option<i64> {
+        let cache = self.cache.lock().ok()?;
+        cache
+            .get(key)
+            .filter(|(_, verified_at)| verified_at.elapsed() < TOKEN_CACHE_TTL)
+            .map(|(user_id, _)| *user_id)
+    }
+
+    fn remember(&self, key: String, user_id: i64) {
+        let Ok(mut cache) = self.cache.lock() else {
+            return;
+        };
+        if cache.len() >= TOKEN_CACHE_MAX {
+            cache.retain(|_, (_, verified_at)| verified_at.elapsed() < TOKEN_CACHE_TTL);
+        }
+        if cache.len() < TOKEN_CACHE_MAX {
+            cache.insert(key, (user_id, Instant::now()));
+        }
+    }
+}
+
+/// The learner a request acts for. Learner data (progress, exercise
+/// attempts, flashcard schedules, settings, decks) is read and written under
+/// this id.
+pub struct Learner {
+    pub id: i64,
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Learner {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let auth = parts
+            .extensions
+            .get::<Arc<LearnerAuthState>>()
+            .cloned()
+            .ok_or_else(|| error(StatusCode::INTERNAL_SERVER_ERROR, "Learner auth is not configured"))?;
+
+        match extract_bearer(&parts.headers) {
+            Some(token) => Ok(Self {
+                id: auth.resolve(&token).await?,
+            }),
+            None if auth.allow_anonymous => Ok(Self {
+                id: users::DEFAULT_USER_ID,
+            }),
+            None => Err(error(StatusCode::UNAUTHORIZED, "Sign-in required")),
+        }
+    }
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    (status, Json(serde_json::json!({"error": message}))).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::supabase_auth::SUPABASE_AUDIENCE;
+    use axum::http::Request;
+    use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+    use serde_json::json;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    const SECRET: &str = "test-secret";
+
+    fn state(db: Db) -> Arc<LearnerAuthState> {
+        let verifier = TokenVerifier::new(
+            "http://supabase.invalid".to_string(),
+            "anon".to_string(),
+            Some(SECRET.to_string()),
+        );
+        Arc::new(LearnerAuthState {
+            verifier: Arc::new(verifier),
+            allow_anonymous: false,
+            cache: Mutex::new(HashMap::new()),
+            db,
+        })
+    }
+
+    fn token(sub: &str, exp_offset: i64) -> String {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        let claims = json!({"aud": SUPABASE_AUDIENCE, "exp": now + exp_offset, "sub": sub, "email": "l@example.com"});
+        encode(
+            &Header::new(Algorithm::HS256),
+            &claims,
+            &EncodingKey::from_secret(SECRET.as_bytes()),
+        )
+        .unwrap()
+    }
+
+    async fn extract(auth: &Arc<LearnerAuthState>, token: &str) -> Result<Learner, Response> {
+        let (mut parts, ()) = Request::builder()
+            .header("Authorization", format!("Bearer {token}"))
+            .extension(auth.clone())
+            .body(())
+            .unwrap()
+            .into_parts();
+        Learner::from_request_parts(&mut parts, &()).await
+    }
+
+    #[tokio::test]
+    async fn verifies_tokens_offline_and_reuses_the_learner() {
+        let auth = state(Db::temporary());
+        let first = extract(&auth, &token("alice", 3600)).await.ok().unwrap();
+        let again = extract(&auth, &token("alice", 3599)).await.ok().unwrap();
+        let other = extract(&auth, &token("bob", 3600)).await.ok().unwrap();
+        assert_eq!(first.id, again.id);
+        assert_ne!(first.id, other.id);
+        assert_ne!(first.id, users::DEFAULT_USER_ID);
+    }
+
+    #[tokio::test]
+    async fn rejects_expired_token() {
+        let auth = state(Db::temporary());
+        let response = extract(&auth, &token("alice", -3600)).await.err().unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
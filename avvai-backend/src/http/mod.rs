@@ -1 +1,3 @@
 pub mod admin_auth;
+pub mod learner_auth;
+pub mod supabase_auth;
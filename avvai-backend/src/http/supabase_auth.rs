@@ -0,0 +1,202 @@
+use axum::http::{HeaderMap, StatusCode};
+use jsonwebtoken::{decode, decode_header, jwk::{Jwk, JwkSet}, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use std::{
+    env,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// How long fetched signing keys are trusted before they are fetched again.
+const JWKS_TTL: Duration = Duration::from_secs(10 * 60);
+/// A token signed with an unknown key triggers a refetch at most this often,
+/// so garbage tokens cannot hammer Supabase.
+const JWKS_MIN_REFRESH: Duration = Duration::from_secs(30);
+/// Audience Supabase puts on tokens of signed-in users.
+pub const SUPABASE_AUDIENCE: &str = "authenticated";
+
+pub type AuthError = (StatusCode, &'static str);
+
+/// The account a verified token belongs to.
+#[derive(Deserialize)]
+pub struct SupabaseUser {
+    /// Supabase's user id: `sub` in a token, `id` from `/auth/v1/user`.
+    #[serde(alias = "sub")]
+    pub id: String,
+    pub email: Option<String>,
+}
+
+/// Checks Supabase access tokens. Shared by the CMS and learner extractors.
+pub struct TokenVerifier {
+    supabase_url: String,
+    anon_key: String,
+    /// Legacy HS256 signing secret (`SUPABASE_JWT_SECRET`). Without it,
+    /// HS256 tokens are checked by asking Supabase.
+    jwt_secret: Option<String>,
+    client: reqwest::Client,
+    jwks: Mutex<Option<(JwkSet, Instant)>>,
+}
+
+impl TokenVerifier {
+    pub fn from_env() -> Result<Self, String> {
+        let supabase_url = env::var("PUBLIC_SUPABASE_URL")
+            .map_err(|_| "Missing PUBLIC_SUPABASE_URL".to_string())?;
+        let anon_key = env::var("PUBLIC_SUPABASE_PUBLISHABLE_DEFAULT_KEY")
+            .map_err(|_| "Missing PUBLIC_SUPABASE_PUBLISHABLE_DEFAULT_KEY".to_string())?;
+        let jwt_secret = env::var("SUPABASE_JWT_SECRET")
+            .ok()
+            .filter(|secret| !secret.trim().is_empty());
+        Ok(Self::new(supabase_url, anon_key, jwt_secret))
+    }
+
+    pub fn new(supabase_url: String, anon_key: String, jwt_secret: Option<String>) -> Self {
+        Self {
+            supabase_url: supabase_url.trim_end_matches('/').to_string(),
+            anon_key,
+            jwt_secret,
+            client: reqwest::Client::new(),
+            jwks: Mutex::new(None),
+        }
+    }
+
+    /// Verifies a Supabase access token and returns its account. Tokens
+    /// signed with the project's asymmetric keys are checked offline against
+    /// its JWKS; HS256 tokens against `SUPABASE_JWT_SECRET` when set.
+    /// Anything else is handed to Supabase's `/auth/v1/user`.
+    pub async fn verify(&self, token: &str) -> Result<SupabaseUser, AuthError> {
+        let header =
+            decode_header(token).map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid token"))?;
+
+        match (header.alg, &self.jwt_secret, header.kid) {
+            (Algorithm::HS256, Some(secret), _) => {
+                let key = DecodingKey::from_secret(secret.as_bytes());
+                decode_claims(token, &key, Algorithm::HS256)
+            }
+            (Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512, _, _) | (_, _, None) => {
+                self.fetch_user(token).await
+            }
+            (alg, _, Some(kid)) => {
+                let key = self.signing_key(&kid, alg).await?;
+                decode_claims(token, &key, alg)
+            }
+        }
+    }
+
+    /// The JWKS key with the given id, from the cache while it is fresh. A key
+    /// that names its algorithm only verifies tokens that claim the same one,
+    /// so a token cannot pick a weaker algorithm for the key.
+    async fn signing_key(&self, kid: &str, alg: Algorithm) -> Result<DecodingKey, AuthError> {
+        const UNKNOWN_KEY: AuthError = (StatusCode::UNAUTHORIZED, "Unknown signing key");
+        let from_jwk = |jwk: &Jwk| {
+            let unsupported = (StatusCode::UNAUTHORIZED, "Unsupported signing key");
+            if let Some(key_algorithm) = jwk.common.key_algorithm
+                && key_algorithm.to_string().parse::<Algorithm>().map_err(|_| unsupported)? != alg
+            {
+                return Err((StatusCode::UNAUTHORIZED, "Token algorithm does not match its key"));
+            }
+            DecodingKey::from_jwk(jwk).map_err(|_| unsupported)
+        };
+
+        {
+            let jwks = self
+                .jwks
+                .lock()
+                .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Key cache lock poisoned"))?;
+            if let Some((keys, fetched_at)) = jwks.as_ref()
+                && fetched_at.elapsed() < JWKS_TTL
+            {
+                match keys.find(kid) {
+                    Some(jwk) => return from_jwk(jwk),
+                    None if fetched_at.elapsed() < JWKS_MIN_REFRESH => return Err(UNKNOWN_KEY),
+                    None => {}
+                }
+            }
+        }
+
+        let keys = self.fetch_jwks().await?;
+        let key = keys.find(kid).ok_or(UNKNOWN_KEY).and_then(from_jwk);
+        if let Ok(mut jwks) = self.jwks.lock() {
+            *jwks = Some((keys, Instant::now()));
+        }
+        key
+    }
+
+    async fn fetch_jwks(&self) -> Result<JwkSet, AuthError> {
+        let unavailable = |_| (StatusCode::SERVICE_UNAVAILABLE, "Could not fetch signing keys");
+        self.client
+            .get(format!("{}/auth/v1/.well-known/jwks.json", self.supabase_url))
+            .header("apikey", &self.anon_key)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(unavailable)?
+            .json::<JwkSet>()
+            .await
+            .map_err(unavailable)
+    }
+
+    async fn fetch_user(&self, token: &str) -> Result<SupabaseUser, AuthError> {
+        let response = self
+            .client
+            .get(format!("{}/auth/v1/user", self.supabase_url))
+            .header("apikey", &self.anon_key)
+            .bearer_auth(token)
+            .send()
+            .await
+            .map_err(|_| (StatusCode::SERVICE_UNAVAILABLE, "Could not verify sign-in"))?;
+        if response.status() == StatusCode::UNAUTHORIZED || response.status() == StatusCode::FORBIDDEN {
+            return Err((StatusCode::UNAUTHORIZED, "Invalid or expired token"));
+        }
+        if !response.status().is_success() {
+            return Err((StatusCode::SERVICE_UNAVAILABLE, "Could not verify sign-in"));
+        }
+        response
+            .json::<SupabaseUser>()
+            .await
+            .map_err(|_| (StatusCode::SERVICE_UNAVAILABLE, "Could not verify sign-in"))
+    }
+}
+
+/// The account in a token's claims; `exp` and `aud` are checked by the
+/// decoder.
+fn decode_claims(token: &str, key: &DecodingKey, alg: Algorithm) -> Result<SupabaseUser, AuthError> {
+    let mut validation = Validation::new(alg);
+    validation.set_audience(&[SUPABASE_AUDIENCE]);
+    decode::<SupabaseUser>(token, key, &validation)
+        .map(|data| data.claims)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid or expired token"))
+}
+
+pub fn extract_bearer(headers: &HeaderMap) -> Option<String> {
+    let header = headers.get("Authorization")?.to_str().ok()?;
+    let mut parts = header.split_whitespace();
+    let scheme = parts.next()?;
+    let token = parts.next()?;
+    if scheme.eq_ignore_ascii_case("Bearer") {
+        Some(token.to_string())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Header `{"alg":"RS384","kid":"k1"}` with an unexpired `authenticated`
+    /// payload and a dummy signature.
+    const RS384_TOKEN: &str = "eyJhbGciOiJSUzM4NCIsImtpZCI6ImsxIiwidHlwIjoiSldUIn0.eyJzdWIiOiJ1MSIsImF1ZCI6ImF1dGhlbnRpY2F0ZWQiLCJleHAiOjQxMDI0NDQ4MDB9.c2ln";
+
+    #[tokio::test]
+    async fn rejects_token_whose_algorithm_differs_from_its_key() {
+        let verifier = TokenVerifier::new("http://localhost".to_string(), String::new(), None);
+        let keys: JwkSet = serde_json::from_value(serde_json::json!({"keys": [
+            {"kty": "RSA", "kid": "k1", "alg": "RS256", "use": "sig", "n": "sXchDaQebHnPiGvyDOAT4saGEUetSyo9MKLOoWFsueri23bOdgWp4Dy1WlUzewbgBHod5pcM9H95GQRV3JDXboIRROSBigeC5yjU1hGzHHyXss8UDprecbAYxknTcQkhslANGRUZmdTOQ5qTRsLAt6BTYuyvVRdhS8exSZEy_c4gs_7svlJJQ4H9_NxsiIoLwAEk7-Q3UXERGYw_75IDrGA84-lA_-Ct4eTlXHBIY2EaV7t7LjJaynVJCpkv4LKjTTAumiGUIuQhrNhZLuF_RJLqHpM2kgWFLU7-VTdL1VbC2tejvcI2BlMkEpk1BzBZI0KQB0GaDWFLN-aEAw3vRw", "e": "AQAB"}
+        ]}))
+        .unwrap();
+        *verifier.jwks.lock().unwrap() = Some((keys, Instant::now()));
+
+        let error = verifier.verify(RS384_TOKEN).await.err().unwrap();
+        assert_eq!(error, (StatusCode::UNAUTHORIZED, "Token algorithm does not match its key"));
+    }
+}
@@ -1,30 +1,68 @@
+mod cli;
 mod core;
 mod http;
 mod routes;
 
-use axum::{routing::get, Router};
+use axum::{routing::get, Extension, Router};
 use dotenv::from_filename;
-use std::sync::Arc;
+use std::{env, process::ExitCode, sync::Arc};
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
     from_filename(".env.local").ok();
 
-    let flashcards_state = routes::flashcards::init_state();
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    if args.first().is_some_and(|command| command == "migrate") {
+        return cli::migrate(&args[1..]);
+    }
+    if args.first().is_some_and(|command| command == "lessons") {
+        return cli::lessons(&args[1..]);
+    }
+
+    let db = core::db::Db::connect().expect("Failed to open database");
+
+    let media_db = db.clone();
+    tokio::spawn(async move {
+        if let Err(error) = core::media::index_untracked_files(&media_db).await {
+            eprintln!("Failed to index media files: {}", error.message());
+        }
+    });
+
+    let flashcards_state = routes::flashcards::init_state(db.clone());
+
+    let verifier = Arc::new(
+        http::supabase_auth::TokenVerifier::from_env().expect("Missing Supabase configuration"),
+    );
 
     let admin_state = Arc::new(
-        http::admin_auth::AdminAuthState::from_env()
+        http::admin_auth::AdminAuthState::from_env(db.clone(), verifier.clone())
             .expect("Missing admin auth configuration"),
     );
 
     let admin_router = routes::admin::router().with_state(admin_state);
 
+    let learner_auth = Arc::new(
+        http::learner_auth::LearnerAuthState::from_env(db.clone(), verifier)
+            .expect("Missing learner auth configuration"),
+    );
+
     let app = Router::new()
-        .nest("/dictionary", routes::dictionary::router())
+        .nest("/dictionary", routes::dictionary::router(db.clone()))
         .nest("/lesson", routes::lesson::router())
-        .nest("/progress", routes::progress::router())
-        .nest("/flashcards", routes::flashcards::router(flashcards_state))
+        .nest(
+            "/progress",
+            routes::progress::router(db.clone()).layer(Extension(learner_auth.clone())),
+        )
+        .nest(
+            "/exercises",
+            routes::exercises::router(db.clone()).layer(Extension(learner_auth.clone())),
+        )
+        .nest(
+            "/flashcards",
+            routes::flashcards::router(flashcards_state).layer(Extension(learner_auth)),
+        )
         .nest("/dictionary/lemmatise", routes::lemmatise::router())
+        .nest("/media", routes::media::router(db))
         .nest("/admin", admin_router)
         .route("/health", get(|| async { "ok" }));
 
@@ -35,4 +73,5 @@ async fn main() {
     println!("avvai backend listening on http://localhost:3001");
 
     axum::serve(listener, app).await.expect("Server error");
+    ExitCode::SUCCESS
 }
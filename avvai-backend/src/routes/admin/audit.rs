@@ -0,0 +1,60 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::core::admin_roles::{self, Role};
+use crate::core::audit::{self, AuditFilter};
+use crate::core::db::Db;
+use crate::http::admin_auth::{AdminAuthState, AdminUser};
+
+#[derive(Deserialize)]
+struct AuditParams {
+    actor: Option<String>,
+    action: Option<String>,
+    #[serde(rename = "targetType")]
+    target_type: Option<String>,
+    target: Option<String>,
+    since: Option<String>,
+    until: Option<String>,
+    before: Option<i64>,
+    limit: Option<usize>,
+}
+
+/// Who changed what in the CMS, newest first.
+async fn list_audit(
+    State(db): State<Db>,
+    admin: AdminUser,
+    Query(params): Query<AuditParams>,
+) -> impl IntoResponse {
+    if let Some(denied) = admin.denied_unless(Role::Admin) {
+        return denied;
+    }
+    let filter = AuditFilter {
+        actor: params.actor.as_deref().map(admin_roles::normalise_email),
+        action: params.action,
+        target_type: params.target_type,
+        target: params.target,
+        since: params.since,
+        until: params.until,
+        before: params.before,
+        limit: params.limit,
+    };
+    match audit::list(&db, filter).await {
+        Ok(page) => Json(page).into_response(),
+        Err(error) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({"error": error})),
+        )
+            .into_response(),
+    }
+}
+
+pub fn router() -> Router<Arc<AdminAuthState>> {
+    Router::new().route("/audit", get(list_audit))
+}
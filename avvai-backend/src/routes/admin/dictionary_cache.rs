@@ -1,5 +1,5 @@
 use axum::{
-    extract::Json as AxumJson,
+    extract::{Json as AxumJson, State},
     http::StatusCode,
     response::IntoResponse,
     routing::delete,
@@ -10,6 +10,9 @@ use axum::{
 use serde::Deserialize;
 use std::sync::Arc;
 
+use crate::core::admin_roles::Role;
+use crate::core::audit::{self, AuditEvent};
+use crate::core::db::Db;
 use crate::core::dictionary_cache::{self, DictionaryEntry};
 use crate::http::admin_auth::{AdminAuthState, AdminUser};
 
@@ -24,12 +27,16 @@ struct CacheUpsertRequest {
     entry: Option<DictionaryEntry>,
 }
 
-async fn list_entries(_admin: AdminUser) -> impl IntoResponse {
-    let entries = dictionary_cache::list().await;
+async fn list_entries(State(db): State<Db>, _admin: AdminUser) -> impl IntoResponse {
+    let entries = dictionary_cache::list(&db).await;
     Json(entries).into_response()
 }
 
-async fn get_entry(_admin: AdminUser, AxumJson(params): AxumJson<CacheKeyRequest>) -> impl IntoResponse {
+async fn get_entry(
+    State(db): State<Db>,
+    _admin: AdminUser,
+    AxumJson(params): AxumJson<CacheKeyRequest>,
+) -> impl IntoResponse {
     let Some(key) = params.key else {
         return (
             StatusCode::BAD_REQUEST,
@@ -38,7 +45,7 @@ async fn get_entry(_admin: AdminUser, AxumJson(params): AxumJson<CacheKeyRequest
             .into_response();
     };
 
-    dictionary_cache::get(&key).await.map_or_else(
+    dictionary_cache::get(&db, &key).await.map_or_else(
         || {
             (
                 StatusCode::NOT_FOUND,
@@ -50,7 +57,14 @@ async fn get_entry(_admin: AdminUser, AxumJson(params): AxumJson<CacheKeyRequest
     )
 }
 
-async fn upsert_entry(_admin: AdminUser, AxumJson(params): AxumJson<CacheUpsertRequest>) -> impl IntoResponse {
+async fn upsert_entry(
+    State(db): State<Db>,
+    admin: AdminUser,
+    AxumJson(params): AxumJson<CacheUpsertRequest>,
+) -> impl IntoResponse {
+    if let Some(denied) = admin.denied_unless(Role::Editor) {
+        return denied;
+    }
     let Some(key) = params.key else {
         return (
             StatusCode::BAD_REQUEST,
@@ -67,11 +81,28 @@ async fn upsert_entry(_admin: AdminUser, AxumJson(params): AxumJson<CacheUpsertR
             .into_response();
     };
 
-    dictionary_cache::set(&key, entry.clone()).await;
+    let before = dictionary_cache::get(&db, &key).await;
+    dictionary_cache::set(&db, &key, entry.clone()).await;
+    audit::record(&db, AuditEvent {
+        actor: &admin.email,
+        action: "dictionary_cache.upsert",
+        target_type: "dictionary_cache",
+        target: &dictionary_cache::normalise(&key),
+        before: before.map(|entry| serde_json::json!(entry)),
+        after: Some(serde_json::json!(entry)),
+    })
+    .await;
     Json(entry).into_response()
 }
 
-async fn delete_entry(_admin: AdminUser, AxumJson(params): AxumJson<CacheKeyRequest>) -> impl IntoResponse {
+async fn delete_entry(
+    State(db): State<Db>,
+    admin: AdminUser,
+    AxumJson(params): AxumJson<CacheKeyRequest>,
+) -> impl IntoResponse {
+    if let Some(denied) = admin.denied_unless(Role::Reviewer) {
+        return denied;
+    }
     let Some(key) = params.key else {
         return (
             StatusCode::BAD_REQUEST,
@@ -80,8 +111,18 @@ async fn delete_entry(_admin: AdminUser, AxumJson(params): AxumJson<CacheKeyRequ
             .into_response();
     };
 
-    let removed = dictionary_cache::remove(&key).await;
+    let before = dictionary_cache::get(&db, &key).await;
+    let removed = dictionary_cache::remove(&db, &key).await;
     if removed {
+        audit::record(&db, AuditEvent {
+            actor: &admin.email,
+            action: "dictionary_cache.delete",
+            target_type: "dictionary_cache",
+            target: &dictionary_cache::normalise(&key),
+            before: before.map(|entry| serde_json::json!(entry)),
+            after: None,
+        })
+        .await;
         Json(serde_json::json!({"status": "deleted"})).into_response()
     } else {
         (
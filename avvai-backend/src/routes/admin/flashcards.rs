@@ -0,0 +1,30 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use std::sync::Arc;
+
+use crate::core::{admin_roles::Role, db::Db, flashcards};
+use crate::http::admin_auth::{AdminAuthState, AdminUser};
+
+/// Cards that are leeches for any learner, the most widespread first.
+async fn list_leeches(State(db): State<Db>, admin: AdminUser) -> impl IntoResponse {
+    if let Some(denied) = admin.denied_unless(Role::Viewer) {
+        return denied;
+    }
+    match flashcards::get_leech_summaries(&db).await {
+        Ok(leeches) => Json(leeches).into_response(),
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({"error": err.message()})),
+        )
+            .into_response(),
+    }
+}
+
+pub fn router() -> Router<Arc<AdminAuthState>> {
+    Router::new().route("/flashcards/leeches", get(list_leeches))
+}
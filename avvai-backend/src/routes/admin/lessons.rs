@@ -1,5 +1,5 @@
 use axum::{
-    extract::{Json as AxumJson, Path as AxumPath},
+    extract::{Json as AxumJson, Path as AxumPath, State},
     http::StatusCode,
     response::IntoResponse,
     routing::{delete, get, post, put},
@@ -8,7 +8,12 @@ use axum::{
 use serde::Deserialize;
 use std::sync::Arc;
 
-use crate::core::lesson;
+use crate::core::{
+    admin_roles::Role,
+    audit::{self, AuditEvent},
+    db::Db,
+    lesson, media,
+};
 use crate::http::admin_auth::{AdminAuthState, AdminUser};
 
 #[derive(Deserialize)]
@@ -22,6 +27,20 @@ struct LessonUpdateRequest {
     lesson: lesson::Lesson,
 }
 
+#[derive(Deserialize)]
+struct VocabularyAudioRequest {
+    filename: String,
+}
+
+/// What the audit log keeps of a lesson.
+fn lesson_summary(lesson: &lesson::Lesson) -> serde_json::Value {
+    serde_json::json!({
+        "title": lesson.title,
+        "description": lesson.description,
+        "sections": lesson.sections.len(),
+    })
+}
+
 async fn list_lessons(_admin: AdminUser) -> impl IntoResponse {
     let items = lesson::list_admin_items().await;
     Json(items).into_response()
@@ -41,58 +60,197 @@ async fn get_lesson(_admin: AdminUser, AxumPath(id): AxumPath<String>) -> impl I
 }
 
 async fn create_lesson(
-    _admin: AdminUser,
+    State(db): State<Db>,
+    admin: AdminUser,
     AxumJson(payload): AxumJson<LessonCreateRequest>,
 ) -> impl IntoResponse {
+    if let Some(denied) = admin.denied_unless(Role::Editor) {
+        return denied;
+    }
     match lesson::create_lesson(&payload.lesson, payload.filename).await {
-        Ok(lesson) => Json(lesson).into_response(),
-        Err(lesson::LessonStoreError::AlreadyExists) => (
-            StatusCode::CONFLICT,
-            Json(serde_json::json!({"error": "Lesson already exists"})),
-        )
-            .into_response(),
-        Err(error) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(serde_json::json!({"error": error.message()})),
-        )
-            .into_response(),
+        Ok(lesson) => {
+            audit::record(&db, AuditEvent {
+                actor: &admin.email,
+                action: "lesson.create",
+                target_type: "lesson",
+                target: &lesson.id,
+                before: None,
+                after: Some(lesson_summary(&lesson)),
+            })
+            .await;
+            Json(lesson).into_response()
+        }
+        Err(error) => lesson_error_response(error),
     }
 }
 
 async fn update_lesson(
-    _admin: AdminUser,
+    State(db): State<Db>,
+    admin: AdminUser,
     AxumPath(id): AxumPath<String>,
     AxumJson(payload): AxumJson<LessonUpdateRequest>,
 ) -> impl IntoResponse {
+    if let Some(denied) = admin.denied_unless(Role::Editor) {
+        return denied;
+    }
+    let before = lesson::get_lesson(&id).await;
     match lesson::update_lesson(&id, &payload.lesson).await {
-        Ok(lesson) => Json(lesson).into_response(),
-        Err(lesson::LessonStoreError::NotFound) => (
-            StatusCode::NOT_FOUND,
-            Json(serde_json::json!({"error": "Lesson not found"})),
-        )
-            .into_response(),
-        Err(error) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(serde_json::json!({"error": error.message()})),
-        )
-            .into_response(),
+        Ok(lesson) => {
+            audit::record(&db, AuditEvent {
+                actor: &admin.email,
+                action: "lesson.update",
+                target_type: "lesson",
+                target: &id,
+                before: before.as_ref().map(lesson_summary),
+                after: Some(lesson_summary(&lesson)),
+            })
+            .await;
+            Json(lesson).into_response()
+        }
+        Err(error) => lesson_error_response(error),
     }
 }
 
-async fn delete_lesson(_admin: AdminUser, AxumPath(id): AxumPath<String>) -> impl IntoResponse {
+async fn delete_lesson(
+    State(db): State<Db>,
+    admin: AdminUser,
+    AxumPath(id): AxumPath<String>,
+) -> impl IntoResponse {
+    if let Some(denied) = admin.denied_unless(Role::Reviewer) {
+        return denied;
+    }
+    let before = lesson::get_lesson(&id).await;
     match lesson::delete_lesson(&id).await {
-        Ok(()) => Json(serde_json::json!({"status": "deleted"})).into_response(),
-        Err(lesson::LessonStoreError::NotFound) => (
-            StatusCode::NOT_FOUND,
-            Json(serde_json::json!({"error": "Lesson not found"})),
-        )
-            .into_response(),
-        Err(error) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(serde_json::json!({"error": error.message()})),
+        Ok(()) => {
+            audit::record(&db, AuditEvent {
+                actor: &admin.email,
+                action: "lesson.delete",
+                target_type: "lesson",
+                target: &id,
+                before: before.as_ref().map(lesson_summary),
+                after: None,
+            })
+            .await;
+            Json(serde_json::json!({"status": "deleted"})).into_response()
+        }
+        Err(error) => lesson_error_response(error),
+    }
+}
+
+async fn attach_vocabulary_audio(
+    State(db): State<Db>,
+    admin: AdminUser,
+    AxumPath((id, entry_id)): AxumPath<(String, String)>,
+    AxumJson(payload): AxumJson<VocabularyAudioRequest>,
+) -> impl IntoResponse {
+    if let Some(denied) = admin.denied_unless(Role::Editor) {
+        return denied;
+    }
+    let record = match media::get_media(&db, &payload.filename).await {
+        Ok(Some(record)) => record,
+        Ok(None) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({"error": "Media file not found"})),
+            )
+                .into_response();
+        }
+        Err(error) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": error.message()})),
+            )
+                .into_response();
+        }
+    };
+    if !record.mime_type.starts_with("audio/") {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": "Media file is not audio"})),
         )
-            .into_response(),
+            .into_response();
+    }
+
+    let before = vocabulary_audio(&id, &entry_id).await;
+    let result = lesson::set_vocabulary_audio(&id, &entry_id, Some(record.filename)).await;
+    record_vocabulary_audio(&db, &admin, "lesson.audio.attach", &id, before, &result).await;
+    vocabulary_audio_response(result)
+}
+
+async fn detach_vocabulary_audio(
+    State(db): State<Db>,
+    admin: AdminUser,
+    AxumPath((id, entry_id)): AxumPath<(String, String)>,
+) -> impl IntoResponse {
+    if let Some(denied) = admin.denied_unless(Role::Editor) {
+        return denied;
     }
+    let before = vocabulary_audio(&id, &entry_id).await;
+    let result = lesson::set_vocabulary_audio(&id, &entry_id, None).await;
+    record_vocabulary_audio(&db, &admin, "lesson.audio.detach", &id, before, &result).await;
+    vocabulary_audio_response(result)
+}
+
+/// The audio clip currently on a vocabulary entry, if the entry exists.
+async fn vocabulary_audio(id: &str, entry_id: &str) -> Option<Option<String>> {
+    lesson::get_lesson(id)
+        .await?
+        .sections
+        .into_iter()
+        .filter_map(|section| match section {
+            lesson::ContentSection::Vocabulary(vocabulary) => Some(vocabulary.entries),
+            _ => None,
+        })
+        .flatten()
+        .find(|entry| entry.id.as_deref() == Some(entry_id))
+        .map(|entry| entry.audio)
+}
+
+async fn record_vocabulary_audio(
+    db: &Db,
+    admin: &AdminUser,
+    action: &str,
+    id: &str,
+    before: Option<Option<String>>,
+    result: &Result<lesson::VocabularyEntry, lesson::LessonStoreError>,
+) {
+    let Ok(entry) = result else {
+        return;
+    };
+    let summary = |audio: Option<String>| {
+        serde_json::json!({"entry": entry.id, "word": entry.word, "audio": audio})
+    };
+    audit::record(db, AuditEvent {
+        actor: &admin.email,
+        action,
+        target_type: "lesson",
+        target: id,
+        before: before.map(summary),
+        after: Some(summary(entry.audio.clone())),
+    })
+    .await;
+}
+
+fn vocabulary_audio_response(
+    result: Result<lesson::VocabularyEntry, lesson::LessonStoreError>,
+) -> axum::response::Response {
+    match result {
+        Ok(entry) => Json(entry).into_response(),
+        Err(error) => lesson_error_response(error),
+    }
+}
+
+/// One status per store error, shared by every lesson route.
+fn lesson_error_response(error: lesson::LessonStoreError) -> axum::response::Response {
+    let status = match &error {
+        lesson::LessonStoreError::NotFound => StatusCode::NOT_FOUND,
+        lesson::LessonStoreError::AlreadyExists => StatusCode::CONFLICT,
+        lesson::LessonStoreError::InvalidReference(_) => StatusCode::BAD_REQUEST,
+        lesson::LessonStoreError::Serialize | lesson::LessonStoreError::Io(_) => {
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    };
+    (status, Json(serde_json::json!({"error": error.message()}))).into_response()
 }
 
 pub fn router() -> Router<Arc<AdminAuthState>> {
@@ -102,4 +260,8 @@ pub fn router() -> Router<Arc<AdminAuthState>> {
         .route("/lessons/{id}", get(get_lesson))
         .route("/lessons/{id}", put(update_lesson))
         .route("/lessons/{id}", delete(delete_lesson))
+        .route(
+            "/lessons/{id}/vocabulary/{entry}/audio",
+            put(attach_vocabulary_audio).delete(detach_vocabulary_audio),
+        )
 }
@@ -1,59 +1,218 @@
 use axum::{
-    extract::{multipart::Multipart, Path as AxumPath},
+    extract::{
+        multipart::{Field, Multipart},
+        DefaultBodyLimit, Json as AxumJson, Path as AxumPath, State,
+    },
     http::StatusCode,
     response::IntoResponse,
-    routing::{delete, get, post},
+    routing::{delete, get, patch, post},
     Json, Router,
 };
+use serde::Deserialize;
 use std::sync::Arc;
-use tokio::io::AsyncWriteExt;
 
-use crate::core::media;
+use crate::core::{
+    admin_roles::Role,
+    audit::{self, AuditEvent},
+    db::Db,
+    media,
+};
 use crate::http::admin_auth::{AdminAuthState, AdminUser};
 
-async fn list_media(_admin: AdminUser) -> impl IntoResponse {
-    let files = media::list_media().await;
-    Json(files).into_response()
+#[derive(Deserialize)]
+struct MediaUpdateRequest {
+    alt_text: Option<String>,
+}
+
+/// What the audit log keeps of a media file.
+fn media_summary(record: &media::MediaRecord) -> serde_json::Value {
+    serde_json::json!({
+        "mime_type": record.mime_type,
+        "size_bytes": record.size_bytes,
+        "content_hash": record.content_hash,
+        "alt_text": record.alt_text,
+    })
 }
 
-async fn upload_media(_admin: AdminUser, mut multipart: Multipart) -> impl IntoResponse {
-    while let Ok(Some(field)) = multipart.next_field().await {
+async fn list_media(State(db): State<Db>, _admin: AdminUser) -> impl IntoResponse {
+    match media::list_media(&db).await {
+        Ok(records) => Json(records).into_response(),
+        Err(error) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({"error": error.message()})),
+        )
+            .into_response(),
+    }
+}
+
+fn error_status(error: &media::MediaError) -> StatusCode {
+    match error {
+        media::MediaError::NotFound => StatusCode::NOT_FOUND,
+        media::MediaError::Invalid(_) => StatusCode::BAD_REQUEST,
+        media::MediaError::UnsupportedType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        media::MediaError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+        media::MediaError::Io(_) | media::MediaError::Database(_) => {
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+async fn receive_file(
+    db: &Db,
+    filename: &str,
+    field: &mut Field<'_>,
+    uploaded_by: &str,
+) -> Result<media::StoredMedia, media::MediaError> {
+    let mut upload = media::MediaUpload::begin(filename).await?;
+    loop {
+        match field.chunk().await {
+            Ok(Some(chunk)) => upload.write(&chunk).await?,
+            Ok(None) => break,
+            Err(error) => return Err(media::MediaError::Invalid(error.body_text())),
+        }
+    }
+    upload.finish(db, uploaded_by).await
+}
+
+/// Accepts any number of file fields. Each file is validated and stored
+/// independently, so one rejected file does not discard the others.
+async fn upload_media(
+    State(db): State<Db>,
+    admin: AdminUser,
+    mut multipart: Multipart,
+) -> impl IntoResponse {
+    if let Some(denied) = admin.denied_unless(Role::Editor) {
+        return denied;
+    }
+    let mut files = Vec::new();
+    let mut errors = Vec::new();
+    let mut first_error_status = None;
+
+    loop {
+        let mut field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(error) => {
+                first_error_status.get_or_insert(error.status());
+                errors.push(serde_json::json!({"error": error.body_text()}));
+                break;
+            }
+        };
         let Some(filename) = field.file_name().map(ToString::to_string) else {
             continue;
         };
 
-        let Ok((path, mut file)) = media::create_media_file(&filename).await else {
+        match receive_file(&db, &filename, &mut field, &admin.email).await {
+            Ok(stored) => {
+                // A duplicate is logged against the file it matched, so every
+                // upload leaves a trace even when nothing new was stored.
+                let mut summary = media_summary(&stored.record);
+                if stored.deduplicated {
+                    summary["deduplicated_from"] = serde_json::json!(filename);
+                }
+                audit::record(&db, AuditEvent {
+                    actor: &admin.email,
+                    action: "media.upload",
+                    target_type: "media",
+                    target: &stored.record.filename,
+                    before: None,
+                    after: Some(summary),
+                })
+                .await;
+                files.push(serde_json::json!({
+                    "status": if stored.deduplicated { "duplicate" } else { "uploaded" },
+                    "filename": stored.record.filename,
+                    "media": stored.record,
+                }));
+            }
+            Err(error) => {
+                first_error_status.get_or_insert(error_status(&error));
+                errors.push(serde_json::json!({"filename": filename, "error": error.message()}));
+            }
+        }
+    }
+
+    if files.is_empty() {
+        let Some(status) = first_error_status else {
             return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(serde_json::json!({"error": "Failed to create file"})),
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({"error": "No file provided"})),
             )
                 .into_response();
         };
+        let message = errors
+            .first()
+            .and_then(|error| error["error"].as_str())
+            .unwrap_or("Upload failed")
+            .to_string();
+        return (
+            status,
+            Json(serde_json::json!({"error": message, "errors": errors})),
+        )
+            .into_response();
+    }
 
-        let mut stream = field;
-        while let Ok(Some(chunk)) = stream.chunk().await {
-            if let Err(error) = file.write_all(&chunk).await {
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(serde_json::json!({"error": error.to_string()})),
-                )
-                    .into_response();
-            }
-        }
+    Json(serde_json::json!({"files": files, "errors": errors})).into_response()
+}
 
-        return Json(serde_json::json!({"status": "uploaded", "filename": path.file_name().unwrap().to_string_lossy()})).into_response();
+async fn update_media(
+    State(db): State<Db>,
+    admin: AdminUser,
+    AxumPath(filename): AxumPath<String>,
+    AxumJson(payload): AxumJson<MediaUpdateRequest>,
+) -> impl IntoResponse {
+    if let Some(denied) = admin.denied_unless(Role::Editor) {
+        return denied;
+    }
+    let before = media::get_media(&db, &filename).await.ok().flatten();
+    match media::update_alt_text(&db, &filename, payload.alt_text).await {
+        Ok(record) => {
+            audit::record(&db, AuditEvent {
+                actor: &admin.email,
+                action: "media.update",
+                target_type: "media",
+                target: &record.filename,
+                before: before.as_ref().map(media_summary),
+                after: Some(media_summary(&record)),
+            })
+            .await;
+            Json(record).into_response()
+        }
+        Err(media::MediaError::NotFound) => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({"error": "File not found"})),
+        )
+            .into_response(),
+        Err(error) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({"error": error.message()})),
+        )
+            .into_response(),
     }
-
-    (
-        StatusCode::BAD_REQUEST,
-        Json(serde_json::json!({"error": "No file provided"})),
-    )
-        .into_response()
 }
 
-async fn delete_media(_admin: AdminUser, AxumPath(filename): AxumPath<String>) -> impl IntoResponse {
-    match media::delete_media(&filename).await {
-        Ok(()) => Json(serde_json::json!({"status": "deleted"})).into_response(),
+async fn delete_media(
+    State(db): State<Db>,
+    admin: AdminUser,
+    AxumPath(filename): AxumPath<String>,
+) -> impl IntoResponse {
+    if let Some(denied) = admin.denied_unless(Role::Reviewer) {
+        return denied;
+    }
+    let before = media::get_media(&db, &filename).await.ok().flatten();
+    match media::delete_media(&db, &filename).await {
+        Ok(()) => {
+            audit::record(&db, AuditEvent {
+                actor: &admin.email,
+                action: "media.delete",
+                target_type: "media",
+                target: before.as_ref().map_or(filename.as_str(), |record| &record.filename),
+                before: before.as_ref().map(media_summary),
+                after: None,
+            })
+            .await;
+            Json(serde_json::json!({"status": "deleted"})).into_response()
+        }
         Err(media::MediaError::NotFound) => (
             StatusCode::NOT_FOUND,
             Json(serde_json::json!({"error": "File not found"})),
@@ -70,6 +229,12 @@ async fn delete_media(_admin: AdminUser, AxumPath(filename): AxumPath<String>) -
 pub fn router() -> Router<Arc<AdminAuthState>> {
     Router::new()
         .route("/media", get(list_media))
-        .route("/media", post(upload_media))
+        .route(
+            "/media",
+            post(upload_media).layer(DefaultBodyLimit::max(
+                usize::try_from(media::upload_limits().max_request_bytes).unwrap_or(usize::MAX),
+            )),
+        )
+        .route("/media/{filename}", patch(update_media))
         .route("/media/{filename}", delete(delete_media))
 }
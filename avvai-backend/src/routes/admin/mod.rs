@@ -1,6 +1,9 @@
+pub mod audit;
 pub mod dictionary_cache;
+pub mod flashcards;
 pub mod lessons;
 pub mod media;
+pub mod roles;
 
 use axum::Router;
 use std::sync::Arc;
@@ -12,4 +15,94 @@ pub fn router() -> Router<Arc<AdminAuthState>> {
         .nest("/dictionary-cache", dictionary_cache::router())
         .nest("/content", lessons::router())
         .nest("/assets", media::router())
+        .merge(roles::router())
+        .merge(audit::router())
+        .merge(flashcards::router())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::{self, Body},
+        http::{Method, Request, StatusCode},
+    };
+    use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+    use serde_json::{json, Value};
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use tower::ServiceExt;
+
+    use crate::core::{
+        admin_roles::{self, Role},
+        db::Db,
+    };
+    use crate::http::supabase_auth::{TokenVerifier, SUPABASE_AUDIENCE};
+
+    const SECRET: &str = "test-secret";
+    const LESSON: &str = r#"{"lesson": {"id": "l", "title": "L", "description": "", "sections": []}}"#;
+
+    fn token(email: &str) -> String {
+        let exp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() + 3600;
+        let claims = json!({"aud": SUPABASE_AUDIENCE, "exp": exp, "sub": email, "email": email});
+        encode(&Header::new(Algorithm::HS256), &claims, &EncodingKey::from_secret(SECRET.as_bytes()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn each_gated_route_denies_the_role_below_it() {
+        let db = Db::temporary();
+        for role in [Role::Viewer, Role::Editor, Role::Reviewer] {
+            admin_roles::set_role(&db, &format!("{}@example.com", role.as_str()), role)
+                .await
+                .unwrap();
+        }
+        let verifier = TokenVerifier::new(String::new(), String::new(), Some(SECRET.to_string()));
+        let state = Arc::new(AdminAuthState::from_env(db.clone(), Arc::new(verifier)).unwrap());
+        let app = router().with_state(state);
+
+        let json = "application/json";
+        let multipart = "multipart/form-data; boundary=x";
+        let audio = "/content/lessons/l/vocabulary/e/audio";
+        let cases = [
+            (Method::POST, "/content/lessons", LESSON, json, Role::Viewer, Role::Editor),
+            (Method::PUT, "/content/lessons/l", LESSON, json, Role::Viewer, Role::Editor),
+            (Method::DELETE, "/content/lessons/l", "", json, Role::Editor, Role::Reviewer),
+            (Method::PUT, audio, r#"{"filename": "a.mp3"}"#, json, Role::Viewer, Role::Editor),
+            (Method::DELETE, audio, "", json, Role::Viewer, Role::Editor),
+            (Method::POST, "/assets/media", "--x--\r\n", multipart, Role::Viewer, Role::Editor),
+            (Method::PATCH, "/assets/media/a.png", r#"{"alt_text": "a"}"#, json, Role::Viewer, Role::Editor),
+            (Method::DELETE, "/assets/media/a.png", "", json, Role::Editor, Role::Reviewer),
+            (Method::POST, "/dictionary-cache/upsert", "{}", json, Role::Viewer, Role::Editor),
+            (Method::DELETE, "/dictionary-cache/delete", "{}", json, Role::Editor, Role::Reviewer),
+            (Method::GET, "/roles", "", json, Role::Reviewer, Role::Admin),
+            (Method::PUT, "/roles/x@example.com", r#"{"role": "admin"}"#, json, Role::Reviewer, Role::Admin),
+            (Method::DELETE, "/roles/x@example.com", "", json, Role::Reviewer, Role::Admin),
+            (Method::GET, "/audit", "", json, Role::Reviewer, Role::Admin),
+        ];
+
+        for (method, uri, payload, content_type, role, needed) in cases {
+            let email = format!("{}@example.com", role.as_str());
+            let request = Request::builder()
+                .method(method.clone())
+                .uri(uri)
+                .header("Authorization", format!("Bearer {}", token(&email)))
+                .header("Content-Type", content_type)
+                .body(Body::from(payload))
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::FORBIDDEN, "{method} {uri}");
+            let body = body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let body: Value = serde_json::from_slice(&body).unwrap();
+            let error = format!("This action needs the {} role", needed.as_str());
+            assert_eq!(body, json!({"error": error}), "{method} {uri}");
+        }
+
+        // Nothing a denied request tried was carried out.
+        let audited: i64 = db
+            .read()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM admin_audit_log", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(audited, 0);
+    }
 }
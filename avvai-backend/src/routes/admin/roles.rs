@@ -0,0 +1,104 @@
+use axum::{
+    extract::{Json as AxumJson, Path as AxumPath, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{get, put},
+    Json, Router,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::core::admin_roles::{self, Role, RoleError};
+use crate::core::audit::{self, AuditEvent};
+use crate::core::db::Db;
+use crate::http::admin_auth::{AdminAuthState, AdminUser};
+
+#[derive(Deserialize)]
+struct RoleUpdateRequest {
+    role: Role,
+}
+
+fn error_response(error: &RoleError) -> axum::response::Response {
+    let status = match error {
+        RoleError::NotFound => StatusCode::NOT_FOUND,
+        RoleError::Invalid(_) => StatusCode::BAD_REQUEST,
+        RoleError::LastAdmin => StatusCode::CONFLICT,
+        RoleError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    (status, Json(serde_json::json!({"error": error.message()}))).into_response()
+}
+
+/// The caller's own email and role, so the CMS can hide what it cannot do.
+async fn current_role(admin: AdminUser) -> impl IntoResponse {
+    Json(serde_json::json!({"email": admin.email, "role": admin.role}))
+}
+
+async fn list_roles(State(db): State<Db>, admin: AdminUser) -> impl IntoResponse {
+    if let Some(denied) = admin.denied_unless(Role::Admin) {
+        return denied;
+    }
+    match admin_roles::list_roles(&db).await {
+        Ok(roles) => Json(roles).into_response(),
+        Err(error) => error_response(&error),
+    }
+}
+
+async fn set_role(
+    State(db): State<Db>,
+    admin: AdminUser,
+    AxumPath(email): AxumPath<String>,
+    AxumJson(payload): AxumJson<RoleUpdateRequest>,
+) -> impl IntoResponse {
+    if let Some(denied) = admin.denied_unless(Role::Admin) {
+        return denied;
+    }
+    let before = admin_roles::role_for(&db, &email).await.ok().flatten();
+    match admin_roles::set_role(&db, &email, payload.role).await {
+        Ok(assignment) => {
+            audit::record(&db, AuditEvent {
+                actor: &admin.email,
+                action: "role.set",
+                target_type: "role",
+                target: &assignment.email,
+                before: before.map(|role| serde_json::json!({"role": role})),
+                after: Some(serde_json::json!({"role": assignment.role})),
+            })
+            .await;
+            Json(assignment).into_response()
+        }
+        Err(error) => error_response(&error),
+    }
+}
+
+async fn remove_role(
+    State(db): State<Db>,
+    admin: AdminUser,
+    AxumPath(email): AxumPath<String>,
+) -> impl IntoResponse {
+    if let Some(denied) = admin.denied_unless(Role::Admin) {
+        return denied;
+    }
+    let before = admin_roles::role_for(&db, &email).await.ok().flatten();
+    match admin_roles::remove_role(&db, &email).await {
+        Ok(()) => {
+            audit::record(&db, AuditEvent {
+                actor: &admin.email,
+                action: "role.remove",
+                target_type: "role",
+                target: &admin_roles::normalise_email(&email),
+                before: before.map(|role| serde_json::json!({"role": role})),
+                after: None,
+            })
+            .await;
+            Json(serde_json::json!({"status": "deleted"})).into_response()
+        }
+        Err(error) => error_response(&error),
+    }
+}
+
+pub fn router() -> Router<Arc<AdminAuthState>> {
+    Router::new()
+        .route("/roles", get(list_roles))
+        .route("/roles/me", get(current_role))
+        .route("/roles/{email}", put(set_role).delete(remove_role))
+}
@@ -1,18 +1,20 @@
 use axum::{
-    extract::Json as AxumJson, http::StatusCode, response::IntoResponse, routing::post, Json,
-    Router,
+    extract::{Json as AxumJson, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::post,
+    Json, Router,
 };
 use serde::Deserialize;
 
-use crate::core::dictionary;
+use crate::core::{db::Db, dictionary};
 
 #[derive(Deserialize)]
 struct LookupRequest {
     word: Option<String>,
 }
 
-
-async fn lookup(AxumJson(params): AxumJson<LookupRequest>) -> impl IntoResponse {
+async fn lookup(State(db): State<Db>, AxumJson(params): AxumJson<LookupRequest>) -> impl IntoResponse {
     let Some(word) = params.word else {
         return (
             StatusCode::BAD_REQUEST,
@@ -21,7 +23,7 @@ async fn lookup(AxumJson(params): AxumJson<LookupRequest>) -> impl IntoResponse
             .into_response();
     };
 
-    match dictionary::lookup(&word).await {
+    match dictionary::lookup(&db, &word).await {
         Ok(entry) => Json(entry).into_response(),
         Err(dictionary::DictionaryError::EmptyWord) => (
             StatusCode::BAD_REQUEST,
@@ -46,6 +48,6 @@ async fn lookup(AxumJson(params): AxumJson<LookupRequest>) -> impl IntoResponse
     }
 }
 
-pub fn router() -> Router {
-    Router::new().route("/lookup", post(lookup))
+pub fn router(db: Db) -> Router {
+    Router::new().route("/lookup", post(lookup)).with_state(db)
 }
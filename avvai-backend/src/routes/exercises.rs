@@ -0,0 +1,88 @@
+use axum::{
+    extract::Query,
+    http::StatusCode,
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use serde::Deserialize;
+
+use crate::core::{db::Db, exercises, lesson};
+use crate::http::learner_auth::Learner;
+
+#[derive(Clone)]
+struct ExercisesState {
+    db: Db,
+}
+
+#[derive(Deserialize)]
+struct AttemptsQuery {
+    #[serde(rename = "lessonId")]
+    lesson_id: String,
+}
+
+#[derive(Deserialize)]
+struct AttemptRequest {
+    #[serde(rename = "lessonId")]
+    lesson_id: String,
+    #[serde(rename = "exerciseId")]
+    exercise_id: String,
+    answer: String,
+    correct: Option<bool>,
+}
+
+pub fn router(db: Db) -> Router {
+    let state = ExercisesState { db };
+    let state_for_get = state.clone();
+    let state_for_post = state;
+
+    Router::new().route(
+        "/attempts",
+        get({
+            let state = state_for_get;
+            move |learner: Learner, Query(query): Query<AttemptsQuery>| async move {
+                match exercises::load_attempts(state.db.clone(), learner.id, &query.lesson_id).await {
+                    Ok(attempts) => Json(attempts).into_response(),
+                    Err(error) => (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(serde_json::json!({"error": error})),
+                    )
+                        .into_response(),
+                }
+            }
+        })
+        .post({
+            let state = state_for_post;
+            move |learner: Learner, Json(body): Json<AttemptRequest>| async move {
+                let known = lesson::get_lesson(&body.lesson_id)
+                    .await
+                    .is_some_and(|lesson| exercises::has_exercise(&lesson, &body.exercise_id));
+                if !known {
+                    return (
+                        StatusCode::NOT_FOUND,
+                        Json(serde_json::json!({"error": "Exercise not found"})),
+                    )
+                        .into_response();
+                }
+
+                let result = exercises::record_attempt(
+                    state.db.clone(),
+                    learner.id,
+                    &body.lesson_id,
+                    &body.exercise_id,
+                    body.answer,
+                    body.correct,
+                )
+                .await;
+                match result {
+                    Ok(attempt) => Json(attempt).into_response(),
+                    Err(error) => (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(serde_json::json!({"error": error})),
+                    )
+                        .into_response(),
+                }
+            }
+        }),
+    )
+}
@@ -1,17 +1,41 @@
 use axum::{
-    extract::{Query, State},
-    http::StatusCode,
-    response::IntoResponse,
-    routing::{get, post},
+    extract::{DefaultBodyLimit, Multipart, Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
     Json, Router,
 };
 use serde::Deserialize;
 
+use crate::core::db::Db;
 use crate::core::flashcards::{self, FlashcardsState};
+use crate::http::learner_auth::Learner;
 
 #[derive(Deserialize)]
 struct DueParams {
+    deck: Option<String>,
     limit: Option<usize>,
+    order: Option<String>,
+    seed: Option<u64>,
+}
+
+impl DueParams {
+    fn into_options(self) -> flashcards::DueOptions {
+        flashcards::DueOptions {
+            deck: self.deck,
+            limit: self.limit,
+            order: self.order,
+            seed: self.seed,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct HistoryParams {
+    #[serde(rename = "cardId")]
+    card_id: String,
+    limit: Option<usize>,
+    before: Option<i64>,
 }
 
 #[derive(Deserialize)]
@@ -19,87 +43,462 @@ struct ReviewRequest {
     #[serde(rename = "cardId")]
     card_id: String,
     rating: u32,
+    deck: Option<String>,
+    #[serde(rename = "sessionId")]
+    session_id: Option<String>,
 }
 
 #[derive(Deserialize)]
-struct SettingsRequest {
+struct UndoRequest {
+    #[serde(rename = "sessionId")]
+    session_id: Option<String>,
+    count: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct SimulateRequest {
+    deck: Option<String>,
     #[serde(rename = "desiredRetention")]
     desired_retention: f32,
+    parameters: Option<Vec<f32>>,
+    days: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct RescheduleRequest {
+    deck: Option<String>,
+    /// Defaults to a dry run; pass `false` to write the new due dates.
+    #[serde(rename = "dryRun")]
+    dry_run: Option<bool>,
 }
 
-pub fn init_state() -> FlashcardsState {
-    flashcards::init_state()
+#[derive(Deserialize)]
+struct CardsRequest {
+    #[serde(rename = "cardIds")]
+    card_ids: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct StatsParams {
+    deck: Option<String>,
+    days: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct DeckParams {
+    deck: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ExportParams {
+    deck: Option<String>,
+    format: Option<String>,
+}
+
+/// Anki collections with years of history and audio can be large.
+const IMPORT_MAX_BYTES: usize = 100 * 1024 * 1024;
+
+#[derive(Deserialize)]
+struct CreateDeckRequest {
+    name: String,
+    tag: Option<String>,
+    lemma: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SettingsRequest {
+    deck: Option<String>,
+    #[serde(rename = "desiredRetention")]
+    desired_retention: Option<f32>,
+    #[serde(rename = "learningSteps")]
+    learning_steps: Option<Vec<u32>>,
+    #[serde(rename = "relearningSteps")]
+    relearning_steps: Option<Vec<u32>>,
+    #[serde(rename = "newCardsPerDay")]
+    new_cards_per_day: Option<usize>,
+    #[serde(rename = "reviewsPerDay")]
+    reviews_per_day: Option<usize>,
+    templates: Option<Vec<flashcards::CardTemplate>>,
+    #[serde(rename = "leechThreshold")]
+    leech_threshold: Option<usize>,
+    #[serde(rename = "leechAction")]
+    leech_action: Option<flashcards::LeechAction>,
+    #[serde(rename = "utcOffsetMinutes")]
+    utc_offset_minutes: Option<i32>,
+}
+
+pub fn init_state(db: Db) -> FlashcardsState {
+    flashcards::init_state(db)
 }
 
 pub fn router(state: FlashcardsState) -> Router {
     Router::new()
         .route("/due", get(get_due))
+        .route("/summary", get(get_summary))
         .route("/review", post(review_card))
+        .route("/undo", post(undo_reviews))
+        .route("/suspend", post(suspend_cards))
+        .route("/unsuspend", post(unsuspend_cards))
+        .route("/bury", post(bury_cards))
+        .route("/unbury", post(unbury_cards))
+        .route("/leeches", get(list_leeches))
+        .route("/history", get(get_history))
         .route("/settings", get(get_settings).post(update_settings))
+        .route("/simulate", post(simulate))
+        .route("/reschedule", post(reschedule))
+        .route("/optimise", get(get_optimisation).post(optimise_parameters))
+        .route("/decks", get(list_decks).post(create_deck))
+        .route("/decks/{id}", delete(delete_deck))
+        .route("/orphans", get(list_orphans))
+        .route("/stats", get(get_stats))
+        .route("/export", get(export_collection))
+        .route(
+            "/import",
+            post(import_anki).layer(DefaultBodyLimit::max(IMPORT_MAX_BYTES)),
+        )
         .with_state(state)
 }
 
+fn error_response(err: &flashcards::FlashcardsError) -> Response {
+    let status = match err {
+        flashcards::FlashcardsError::BadRequest(_) => StatusCode::BAD_REQUEST,
+        flashcards::FlashcardsError::NotFound(_) => StatusCode::NOT_FOUND,
+        flashcards::FlashcardsError::Conflict(_) => StatusCode::CONFLICT,
+        flashcards::FlashcardsError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    (status, Json(serde_json::json!({"error": err.message()}))).into_response()
+}
+
 async fn get_due(
     State(state): State<FlashcardsState>,
+    learner: Learner,
     Query(params): Query<DueParams>,
 ) -> impl IntoResponse {
-    match flashcards::get_due(&state, params.limit).await {
+    match flashcards::get_due(&state, learner.id, &params.into_options()).await {
         Ok(cards) => Json(cards).into_response(),
-        Err(err) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(serde_json::json!({"error": err.message()})),
-        )
-            .into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+async fn get_summary(
+    State(state): State<FlashcardsState>,
+    learner: Learner,
+    Query(params): Query<DueParams>,
+) -> impl IntoResponse {
+    match flashcards::get_summary(&state, learner.id, &params.into_options()).await {
+        Ok(summary) => Json(summary).into_response(),
+        Err(err) => error_response(&err),
     }
 }
 
 async fn review_card(
     State(state): State<FlashcardsState>,
+    learner: Learner,
     Json(body): Json<ReviewRequest>,
 ) -> impl IntoResponse {
-    match flashcards::review_card(&state, &body.card_id, body.rating).await {
+    let result = flashcards::review_card(
+        &state,
+        learner.id,
+        &body.card_id,
+        body.rating,
+        body.deck.as_deref(),
+        body.session_id,
+    )
+    .await;
+    match result {
         Ok(result) => Json(result).into_response(),
-        Err(flashcards::FlashcardsError::BadRequest(message)) => (
-            StatusCode::BAD_REQUEST,
-            Json(serde_json::json!({"error": message})),
-        )
-            .into_response(),
-        Err(flashcards::FlashcardsError::Internal(message)) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(serde_json::json!({"error": message})),
-        )
-            .into_response(),
+        Err(err) => error_response(&err),
     }
 }
 
-async fn get_settings(State(state): State<FlashcardsState>) -> impl IntoResponse {
-    match flashcards::get_settings(&state).await {
-        Ok(desired_retention) => {
-            Json(serde_json::json!({ "desired_retention": desired_retention })).into_response()
-        }
-        Err(err) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(serde_json::json!({"error": err.message()})),
-        )
-            .into_response(),
+async fn undo_reviews(
+    State(state): State<FlashcardsState>,
+    learner: Learner,
+    body: Option<Json<UndoRequest>>,
+) -> impl IntoResponse {
+    let (session_id, count) = body.map_or((None, None), |Json(body)| (body.session_id, body.count));
+    match flashcards::undo_reviews(&state, learner.id, session_id, count).await {
+        Ok(undone) => Json(serde_json::json!({ "undone": undone })).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+async fn suspend_cards(
+    State(state): State<FlashcardsState>,
+    learner: Learner,
+    Json(body): Json<CardsRequest>,
+) -> impl IntoResponse {
+    match flashcards::suspend_cards(&state, learner.id, body.card_ids, true).await {
+        Ok(()) => Json(serde_json::json!({ "success": true })).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+async fn unsuspend_cards(
+    State(state): State<FlashcardsState>,
+    learner: Learner,
+    Json(body): Json<CardsRequest>,
+) -> impl IntoResponse {
+    match flashcards::suspend_cards(&state, learner.id, body.card_ids, false).await {
+        Ok(()) => Json(serde_json::json!({ "success": true })).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+async fn bury_cards(
+    State(state): State<FlashcardsState>,
+    learner: Learner,
+    Json(body): Json<CardsRequest>,
+) -> impl IntoResponse {
+    match flashcards::bury_cards(&state, learner.id, body.card_ids, true).await {
+        Ok(()) => Json(serde_json::json!({ "success": true })).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+async fn unbury_cards(
+    State(state): State<FlashcardsState>,
+    learner: Learner,
+    Json(body): Json<CardsRequest>,
+) -> impl IntoResponse {
+    match flashcards::bury_cards(&state, learner.id, body.card_ids, false).await {
+        Ok(()) => Json(serde_json::json!({ "success": true })).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+async fn list_leeches(
+    State(state): State<FlashcardsState>,
+    learner: Learner,
+    Query(params): Query<DeckParams>,
+) -> impl IntoResponse {
+    match flashcards::get_leeches(&state, learner.id, params.deck.as_deref()).await {
+        Ok(leeches) => Json(leeches).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+async fn get_history(
+    State(state): State<FlashcardsState>,
+    learner: Learner,
+    Query(params): Query<HistoryParams>,
+) -> impl IntoResponse {
+    let result = flashcards::get_history(
+        &state,
+        learner.id,
+        &params.card_id,
+        params.limit,
+        params.before,
+    )
+    .await;
+    match result {
+        Ok(page) => Json(page).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+async fn get_settings(
+    State(state): State<FlashcardsState>,
+    learner: Learner,
+    Query(params): Query<DeckParams>,
+) -> impl IntoResponse {
+    match flashcards::get_settings(&state, learner.id, params.deck.as_deref()).await {
+        Ok(settings) => Json(settings).into_response(),
+        Err(err) => error_response(&err),
     }
 }
 
 async fn update_settings(
     State(state): State<FlashcardsState>,
+    learner: Learner,
     Json(body): Json<SettingsRequest>,
 ) -> impl IntoResponse {
-    match flashcards::update_settings(&state, body.desired_retention).await {
+    let update = flashcards::SettingsUpdate {
+        deck: body.deck,
+        desired_retention: body.desired_retention,
+        learning_steps: body.learning_steps,
+        relearning_steps: body.relearning_steps,
+        new_cards_per_day: body.new_cards_per_day,
+        reviews_per_day: body.reviews_per_day,
+        templates: body.templates,
+        leech_threshold: body.leech_threshold,
+        leech_action: body.leech_action,
+        utc_offset_minutes: body.utc_offset_minutes,
+    };
+    match flashcards::update_settings(&state, learner.id, update).await {
+        Ok(()) => Json(serde_json::json!({ "success": true })).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+async fn simulate(
+    State(state): State<FlashcardsState>,
+    learner: Learner,
+    Json(body): Json<SimulateRequest>,
+) -> impl IntoResponse {
+    let request = flashcards::SimulationRequest {
+        deck: body.deck,
+        desired_retention: body.desired_retention,
+        parameters: body.parameters,
+        days: body.days,
+    };
+    match flashcards::simulate(&state, learner.id, request).await {
+        Ok(simulation) => Json(simulation).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+async fn reschedule(
+    State(state): State<FlashcardsState>,
+    learner: Learner,
+    body: Option<Json<RescheduleRequest>>,
+) -> impl IntoResponse {
+    let (deck, dry_run) = body.map_or((None, None), |Json(body)| (body.deck, body.dry_run));
+    let dry_run = dry_run.unwrap_or(true);
+    match flashcards::reschedule(&state, learner.id, deck.as_deref(), dry_run).await {
+        Ok(report) => Json(report).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+async fn get_optimisation(
+    State(state): State<FlashcardsState>,
+    learner: Learner,
+) -> impl IntoResponse {
+    match flashcards::last_optimisation(&state, learner.id).await {
+        Ok(report) => Json(serde_json::json!({ "last_run": report })).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+async fn optimise_parameters(
+    State(state): State<FlashcardsState>,
+    learner: Learner,
+) -> impl IntoResponse {
+    match flashcards::optimise_parameters(&state, learner.id).await {
+        Ok(report) => Json(report).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+async fn list_decks(State(state): State<FlashcardsState>, learner: Learner) -> impl IntoResponse {
+    match flashcards::list_decks(&state, learner.id).await {
+        Ok(decks) => Json(decks).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+async fn create_deck(
+    State(state): State<FlashcardsState>,
+    learner: Learner,
+    Json(body): Json<CreateDeckRequest>,
+) -> impl IntoResponse {
+    let deck = flashcards::NewDeck {
+        name: body.name,
+        tag: body.tag,
+        lemma: body.lemma,
+    };
+    match flashcards::create_deck(&state, learner.id, deck).await {
+        Ok(deck) => (StatusCode::CREATED, Json(deck)).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+async fn delete_deck(
+    State(state): State<FlashcardsState>,
+    learner: Learner,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    match flashcards::delete_deck(&state, learner.id, &id).await {
         Ok(()) => Json(serde_json::json!({ "success": true })).into_response(),
-        Err(flashcards::FlashcardsError::BadRequest(message)) => (
+        Err(err) => error_response(&err),
+    }
+}
+
+async fn list_orphans(State(state): State<FlashcardsState>, learner: Learner) -> impl IntoResponse {
+    match flashcards::find_orphans(&state, learner.id).await {
+        Ok(orphans) => Json(orphans).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+async fn get_stats(
+    State(state): State<FlashcardsState>,
+    learner: Learner,
+    Query(params): Query<StatsParams>,
+) -> impl IntoResponse {
+    match flashcards::get_stats(&state, learner.id, params.deck.as_deref(), params.days).await {
+        Ok(stats) => Json(stats).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+async fn export_collection(
+    State(state): State<FlashcardsState>,
+    learner: Learner,
+    Query(params): Query<ExportParams>,
+) -> impl IntoResponse {
+    let format = params.format.as_deref().unwrap_or("apkg");
+    let Some(format) = flashcards::ExportFormat::parse(format) else {
+        return (
             StatusCode::BAD_REQUEST,
-            Json(serde_json::json!({"error": message})),
+            Json(serde_json::json!({"error": "format must be apkg or csv"})),
         )
-            .into_response(),
-        Err(flashcards::FlashcardsError::Internal(message)) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(serde_json::json!({"error": message})),
+            .into_response();
+    };
+    match flashcards::export_collection(&state, learner.id, params.deck.as_deref(), format).await {
+        Ok(file) => (
+            [
+                (header::CONTENT_TYPE, file.content_type.to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{}\"", file.filename),
+                ),
+            ],
+            file.bytes,
         )
             .into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// Takes the package from the `file` field of a multipart upload.
+async fn import_anki(
+    State(state): State<FlashcardsState>,
+    learner: Learner,
+    mut multipart: Multipart,
+) -> impl IntoResponse {
+    let mut package = None;
+    loop {
+        match multipart.next_field().await {
+            Ok(Some(field)) if field.name() == Some("file") => match field.bytes().await {
+                Ok(bytes) => {
+                    package = Some(bytes.to_vec());
+                    break;
+                }
+                Err(error) => {
+                    return (error.status(), Json(serde_json::json!({"error": error.body_text()})))
+                        .into_response();
+                }
+            },
+            Ok(Some(_)) => {}
+            Ok(None) => break,
+            Err(error) => {
+                return (error.status(), Json(serde_json::json!({"error": error.body_text()})))
+                    .into_response();
+            }
+        }
+    }
+
+    let Some(package) = package else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": "No file provided"})),
+        )
+            .into_response();
+    };
+    match flashcards::import_anki(&state, learner.id, package).await {
+        Ok(report) => Json(report).into_response(),
+        Err(err) => error_response(&err),
     }
 }
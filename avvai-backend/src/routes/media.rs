@@ -0,0 +1,269 @@
+use axum::{
+    body::Body,
+    extract::{Path as AxumPath, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::io::SeekFrom;
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+use tokio_util::io::ReaderStream;
+
+use crate::core::{db::Db, media};
+
+const CACHE_CONTROL: &str = "public, max-age=86400";
+const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+/// SVG can carry scripts. Opened directly, it is rendered sandboxed with
+/// nothing but its own inline styles.
+const SVG_CONTENT_SECURITY_POLICY: &str = "default-src 'none'; style-src 'unsafe-inline'; sandbox";
+
+#[derive(Deserialize)]
+struct MediaParams {
+    w: Option<u32>,
+    format: Option<String>,
+}
+
+/// A satisfiable byte range, inclusive on both ends.
+struct ByteRange {
+    start: u64,
+    end: u64,
+}
+
+enum RangeRequest {
+    Full,
+    Partial(ByteRange),
+    Unsatisfiable,
+}
+
+async fn serve_media(
+    State(db): State<Db>,
+    AxumPath(filename): AxumPath<String>,
+    Query(params): Query<MediaParams>,
+    headers: HeaderMap,
+) -> Response {
+    let record = match media::get_media(&db, &filename).await {
+        Ok(Some(record)) => record,
+        Ok(None) => return not_found(),
+        Err(error) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": error.message()})),
+            )
+                .into_response();
+        }
+    };
+
+    let prefer_webp = params.format.as_deref() == Some("webp");
+    let resolved = media::resolve_variant(&record, params.w, prefer_webp);
+
+    let Ok(mut file) = fs::File::open(&resolved.path).await else {
+        return not_found();
+    };
+    let Ok(metadata) = file.metadata().await else {
+        return not_found();
+    };
+    let length = metadata.len();
+    let last_modified = metadata.modified().ok().map(DateTime::<Utc>::from);
+
+    let mut response_headers = HeaderMap::new();
+    insert_header(&mut response_headers, header::ETAG, &resolved.etag);
+    insert_header(&mut response_headers, header::CACHE_CONTROL, CACHE_CONTROL);
+    insert_header(&mut response_headers, header::ACCEPT_RANGES, "bytes");
+    if let Some(modified) = last_modified {
+        insert_header(
+            &mut response_headers,
+            header::LAST_MODIFIED,
+            &modified.format(HTTP_DATE_FORMAT).to_string(),
+        );
+    }
+
+    if is_not_modified(&headers, &resolved.etag, last_modified) {
+        return (StatusCode::NOT_MODIFIED, response_headers).into_response();
+    }
+
+    insert_header(&mut response_headers, header::CONTENT_TYPE, &resolved.mime_type);
+    insert_header(&mut response_headers, header::X_CONTENT_TYPE_OPTIONS, "nosniff");
+    if resolved.mime_type == "image/svg+xml" {
+        insert_header(
+            &mut response_headers,
+            header::CONTENT_SECURITY_POLICY,
+            SVG_CONTENT_SECURITY_POLICY,
+        );
+    }
+
+    let range = if if_range_matches(&headers, &resolved.etag, last_modified) {
+        parse_range(&headers, length)
+    } else {
+        RangeRequest::Full
+    };
+
+    match range {
+        RangeRequest::Full => {
+            insert_header(&mut response_headers, header::CONTENT_LENGTH, &length.to_string());
+            let body = Body::from_stream(ReaderStream::new(file));
+            (StatusCode::OK, response_headers, body).into_response()
+        }
+        RangeRequest::Partial(ByteRange { start, end }) => {
+            if file.seek(SeekFrom::Start(start)).await.is_err() {
+                return not_found();
+            }
+            let chunk_length = end - start + 1;
+            insert_header(
+                &mut response_headers,
+                header::CONTENT_RANGE,
+                &format!("bytes {start}-{end}/{length}"),
+            );
+            insert_header(
+                &mut response_headers,
+                header::CONTENT_LENGTH,
+                &chunk_length.to_string(),
+            );
+            let body = Body::from_stream(ReaderStream::new(file.take(chunk_length)));
+            (StatusCode::PARTIAL_CONTENT, response_headers, body).into_response()
+        }
+        RangeRequest::Unsatisfiable => {
+            insert_header(
+                &mut response_headers,
+                header::CONTENT_RANGE,
+                &format!("bytes */{length}"),
+            );
+            (StatusCode::RANGE_NOT_SATISFIABLE, response_headers).into_response()
+        }
+    }
+}
+
+fn not_found() -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(serde_json::json!({"error": "File not found"})),
+    )
+        .into_response()
+}
+
+fn insert_header(headers: &mut HeaderMap, name: header::HeaderName, value: &str) {
+    if let Ok(value) = HeaderValue::from_str(value) {
+        headers.insert(name, value);
+    }
+}
+
+fn header_str(headers: &HeaderMap, name: header::HeaderName) -> Option<&str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+fn parse_http_date(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(value)
+        .ok()
+        .map(|date| date.with_timezone(&Utc))
+}
+
+/// Compares ETags using the weak comparison function, which is what
+/// `If-None-Match` requires.
+fn etag_matches(candidates: &str, etag: &str) -> bool {
+    candidates.split(',').map(str::trim).any(|candidate| {
+        candidate == "*" || candidate.trim_start_matches("W/") == etag.trim_start_matches("W/")
+    })
+}
+
+fn is_not_modified(
+    headers: &HeaderMap,
+    etag: &str,
+    last_modified: Option<DateTime<Utc>>,
+) -> bool {
+    if let Some(candidates) = header_str(headers, header::IF_NONE_MATCH) {
+        return etag_matches(candidates, etag);
+    }
+
+    match (
+        header_str(headers, header::IF_MODIFIED_SINCE).and_then(parse_http_date),
+        last_modified,
+    ) {
+        (Some(since), Some(modified)) => modified.timestamp() <= since.timestamp(),
+        _ => false,
+    }
+}
+
+/// `If-Range` only lets the range through when the client's copy is still
+/// current; otherwise the full representation is sent.
+fn if_range_matches(headers: &HeaderMap, etag: &str, last_modified: Option<DateTime<Utc>>) -> bool {
+    let Some(value) = header_str(headers, header::IF_RANGE) else {
+        return true;
+    };
+
+    if value.starts_with('"') {
+        return value == etag;
+    }
+
+    match (parse_http_date(value), last_modified) {
+        (Some(date), Some(modified)) => modified.timestamp() == date.timestamp(),
+        _ => false,
+    }
+}
+
+/// Parses a single `bytes=` range. Multi-range requests and malformed ranges,
+/// such as one ending before it starts, fall back to the full body, which the
+/// spec permits.
+fn parse_range(headers: &HeaderMap, length: u64) -> RangeRequest {
+    let Some(value) = header_str(headers, header::RANGE) else {
+        return RangeRequest::Full;
+    };
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Full;
+    };
+    if spec.contains(',') {
+        return RangeRequest::Full;
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return RangeRequest::Full;
+    };
+
+    let range = match (start.trim(), end.trim()) {
+        ("", "") => return RangeRequest::Full,
+        ("", suffix) => {
+            let Ok(suffix) = suffix.parse::<u64>() else {
+                return RangeRequest::Full;
+            };
+            if suffix == 0 || length == 0 {
+                return RangeRequest::Unsatisfiable;
+            }
+            ByteRange {
+                start: length.saturating_sub(suffix),
+                end: length - 1,
+            }
+        }
+        (start, end) => {
+            let Ok(start) = start.parse::<u64>() else {
+                return RangeRequest::Full;
+            };
+            let end = if end.is_empty() {
+                length.saturating_sub(1)
+            } else {
+                let Ok(end) = end.parse::<u64>() else {
+                    return RangeRequest::Full;
+                };
+                end.min(length.saturating_sub(1))
+            };
+            if start > end && start < length {
+                return RangeRequest::Full;
+            }
+            ByteRange { start, end }
+        }
+    };
+
+    if range.start >= length {
+        RangeRequest::Unsatisfiable
+    } else {
+        RangeRequest::Partial(range)
+    }
+}
+
+pub fn router(db: Db) -> Router {
+    Router::new()
+        .route("/{filename}", get(serve_media))
+        .with_state(db)
+}
@@ -1,6 +1,8 @@
 pub mod admin;
 pub mod dictionary;
+pub mod exercises;
 pub mod flashcards;
 pub mod lemmatise;
 pub mod lesson;
+pub mod media;
 pub mod progress;
@@ -5,13 +5,13 @@ use axum::{
     Json, Router,
 };
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
 
-use crate::core::{db, progress};
+use crate::core::{db::Db, progress};
+use crate::http::learner_auth::Learner;
 
 #[derive(Clone)]
 struct ProgressState {
-    db: Arc<std::sync::Mutex<rusqlite::Connection>>,
+    db: Db,
 }
 
 #[derive(Deserialize)]
@@ -26,16 +26,16 @@ struct UpdateResponse {
     success: bool,
 }
 
-pub fn router() -> Router {
-    let state = ProgressState { db: db::db() };
+pub fn router(db: Db) -> Router {
+    let state = ProgressState { db };
     let state_for_get = state.clone();
     let state_for_update = state;
 
     Router::new()
         .route("/get", get({
             let state = state_for_get;
-            move || async move {
-                match load_progress(state.db.clone()).await {
+            move |learner: Learner| async move {
+                match load_progress(state.db.clone(), learner.id).await {
                     Ok(progress) => Json(progress).into_response(),
                     Err(error) => (
                         StatusCode::INTERNAL_SERVER_ERROR,
@@ -47,8 +47,11 @@ pub fn router() -> Router {
         }))
         .route("/update", post({
             let state = state_for_update;
-            move |Json(body): Json<UpdateRequest>| async move {
-                match upsert_progress(state.db.clone(), &body.lesson_id, body.completed).await {
+            move |learner: Learner, Json(body): Json<UpdateRequest>| async move {
+                let result =
+                    upsert_progress(state.db.clone(), learner.id, &body.lesson_id, body.completed)
+                        .await;
+                match result {
                     Ok(()) => Json(UpdateResponse { success: true }).into_response(),
                     Err(error) => (
                         StatusCode::INTERNAL_SERVER_ERROR,
@@ -61,15 +64,17 @@ pub fn router() -> Router {
 }
 
 async fn load_progress(
-    db: Arc<std::sync::Mutex<rusqlite::Connection>>,
+    db: Db,
+    user_id: i64,
 ) -> Result<std::collections::HashMap<String, bool>, String> {
-    progress::load_progress(db).await
+    progress::load_progress(db, user_id).await
 }
 
 async fn upsert_progress(
-    db: Arc<std::sync::Mutex<rusqlite::Connection>>,
+    db: Db,
+    user_id: i64,
     lesson_id: &str,
     completed: bool,
 ) -> Result<(), String> {
-    progress::upsert_progress(db, lesson_id, completed).await
+    progress::upsert_progress(db, user_id, lesson_id, completed).await
 }
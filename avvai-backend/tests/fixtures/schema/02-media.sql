@@ -0,0 +1,38 @@
+-- Schema created on an empty database by the build that added media tracking.
+CREATE TABLE fsrs_cards (
+            card_id TEXT PRIMARY KEY,
+            stability REAL NOT NULL,
+            difficulty REAL NOT NULL,
+            last_review TEXT,
+            due_date TEXT,
+            interval_days INTEGER NOT NULL
+        );
+CREATE TABLE fsrs_settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        );
+CREATE TABLE progress (
+            lesson_id TEXT PRIMARY KEY,
+            completed INTEGER NOT NULL,
+            updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );
+CREATE TABLE dictionary_cache (
+            cache_key TEXT PRIMARY KEY,
+            word TEXT NOT NULL,
+            definition TEXT NOT NULL,
+            examples TEXT NOT NULL,
+            updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );
+CREATE TABLE media (
+            filename TEXT PRIMARY KEY,
+            content_hash TEXT NOT NULL,
+            mime_type TEXT NOT NULL,
+            size_bytes INTEGER NOT NULL,
+            width INTEGER,
+            height INTEGER,
+            duration_ms INTEGER,
+            uploaded_by TEXT,
+            alt_text TEXT,
+            created_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );
+CREATE INDEX media_content_hash ON media (content_hash);
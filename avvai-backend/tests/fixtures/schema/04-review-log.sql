@@ -0,0 +1,65 @@
+-- Schema created on an empty database by the build that added the review log.
+CREATE TABLE fsrs_cards (
+            card_id TEXT PRIMARY KEY,
+            stability REAL NOT NULL,
+            difficulty REAL NOT NULL,
+            last_review TEXT,
+            due_date TEXT,
+            interval_days INTEGER NOT NULL
+        );
+CREATE TABLE fsrs_review_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            card_id TEXT NOT NULL,
+            rating INTEGER NOT NULL,
+            reviewed_at TEXT NOT NULL,
+            elapsed_days INTEGER NOT NULL,
+            stability_before REAL,
+            difficulty_before REAL,
+            stability_after REAL NOT NULL,
+            difficulty_after REAL NOT NULL,
+            last_review_before TEXT,
+            due_before TEXT,
+            interval_before INTEGER,
+            interval_days INTEGER NOT NULL,
+            due_date TEXT NOT NULL
+        );
+CREATE INDEX fsrs_review_log_card ON fsrs_review_log (card_id, id);
+CREATE TABLE fsrs_settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        );
+CREATE TABLE progress (
+            lesson_id TEXT PRIMARY KEY,
+            completed INTEGER NOT NULL,
+            updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );
+CREATE TABLE dictionary_cache (
+            cache_key TEXT PRIMARY KEY,
+            word TEXT NOT NULL,
+            definition TEXT NOT NULL,
+            examples TEXT NOT NULL,
+            updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );
+CREATE TABLE media (
+            filename TEXT PRIMARY KEY,
+            content_hash TEXT NOT NULL,
+            mime_type TEXT NOT NULL,
+            size_bytes INTEGER NOT NULL,
+            width INTEGER,
+            height INTEGER,
+            duration_ms INTEGER,
+            uploaded_by TEXT,
+            alt_text TEXT,
+            created_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );
+CREATE INDEX media_content_hash ON media (content_hash);
+CREATE TABLE media_variants (
+            source_filename TEXT NOT NULL,
+            variant TEXT NOT NULL,
+            filename TEXT NOT NULL,
+            mime_type TEXT NOT NULL,
+            width INTEGER NOT NULL,
+            height INTEGER NOT NULL,
+            size_bytes INTEGER NOT NULL,
+            PRIMARY KEY (source_filename, variant)
+        );
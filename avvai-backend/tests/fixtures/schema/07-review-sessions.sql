@@ -0,0 +1,83 @@
+-- Schema created on an empty database by the build that added review sessions.
+CREATE TABLE fsrs_cards (
+            card_id TEXT PRIMARY KEY,
+            stability REAL NOT NULL,
+            difficulty REAL NOT NULL,
+            last_review TEXT,
+            due_date TEXT,
+            interval_days INTEGER NOT NULL,
+            state TEXT NOT NULL DEFAULT 'review',
+            step INTEGER NOT NULL DEFAULT 0
+        );
+CREATE TABLE fsrs_review_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            card_id TEXT NOT NULL,
+            rating INTEGER NOT NULL,
+            reviewed_at TEXT NOT NULL,
+            elapsed_days INTEGER NOT NULL,
+            stability_before REAL,
+            difficulty_before REAL,
+            stability_after REAL NOT NULL,
+            difficulty_after REAL NOT NULL,
+            last_review_before TEXT,
+            due_before TEXT,
+            interval_before INTEGER,
+            interval_days INTEGER NOT NULL,
+            due_date TEXT NOT NULL,
+            state_before TEXT,
+            step_before INTEGER,
+            state_after TEXT,
+            session_id TEXT
+        );
+CREATE INDEX fsrs_review_log_card ON fsrs_review_log (card_id, id);
+CREATE TABLE fsrs_settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        );
+CREATE TABLE flashcard_decks (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            tag TEXT,
+            lemma TEXT,
+            created_at TEXT NOT NULL
+        );
+CREATE TABLE flashcard_deck_options (
+            deck_id TEXT PRIMARY KEY,
+            desired_retention REAL NOT NULL
+        );
+CREATE TABLE progress (
+            lesson_id TEXT PRIMARY KEY,
+            completed INTEGER NOT NULL,
+            updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );
+CREATE TABLE dictionary_cache (
+            cache_key TEXT PRIMARY KEY,
+            word TEXT NOT NULL,
+            definition TEXT NOT NULL,
+            examples TEXT NOT NULL,
+            updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );
+CREATE TABLE media (
+            filename TEXT PRIMARY KEY,
+            content_hash TEXT NOT NULL,
+            mime_type TEXT NOT NULL,
+            size_bytes INTEGER NOT NULL,
+            width INTEGER,
+            height INTEGER,
+            duration_ms INTEGER,
+            uploaded_by TEXT,
+            alt_text TEXT,
+            created_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );
+CREATE INDEX media_content_hash ON media (content_hash);
+CREATE TABLE media_variants (
+            source_filename TEXT NOT NULL,
+            variant TEXT NOT NULL,
+            filename TEXT NOT NULL,
+            mime_type TEXT NOT NULL,
+            width INTEGER NOT NULL,
+            height INTEGER NOT NULL,
+            size_bytes INTEGER NOT NULL,
+            PRIMARY KEY (source_filename, variant)
+        );
+CREATE INDEX fsrs_review_log_session ON fsrs_review_log (session_id, id);
@@ -0,0 +1,107 @@
+-- Schema created on an empty database by the build that added learner accounts.
+CREATE TABLE users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            auth_id TEXT UNIQUE,
+            email TEXT,
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            last_seen_at TEXT
+        );
+CREATE TABLE fsrs_review_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            card_id TEXT NOT NULL,
+            rating INTEGER NOT NULL,
+            reviewed_at TEXT NOT NULL,
+            elapsed_days INTEGER NOT NULL,
+            stability_before REAL,
+            difficulty_before REAL,
+            stability_after REAL NOT NULL,
+            difficulty_after REAL NOT NULL,
+            last_review_before TEXT,
+            due_before TEXT,
+            interval_before INTEGER,
+            interval_days INTEGER NOT NULL,
+            due_date TEXT NOT NULL,
+            state_before TEXT,
+            step_before INTEGER,
+            state_after TEXT,
+            session_id TEXT
+        , user_id INTEGER NOT NULL DEFAULT 1);
+CREATE INDEX fsrs_review_log_card ON fsrs_review_log (card_id, id);
+CREATE TABLE flashcard_decks (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            tag TEXT,
+            lemma TEXT,
+            created_at TEXT NOT NULL
+        , user_id INTEGER NOT NULL DEFAULT 1);
+CREATE TABLE dictionary_cache (
+            cache_key TEXT PRIMARY KEY,
+            word TEXT NOT NULL,
+            definition TEXT NOT NULL,
+            examples TEXT NOT NULL,
+            updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );
+CREATE TABLE media (
+            filename TEXT PRIMARY KEY,
+            content_hash TEXT NOT NULL,
+            mime_type TEXT NOT NULL,
+            size_bytes INTEGER NOT NULL,
+            width INTEGER,
+            height INTEGER,
+            duration_ms INTEGER,
+            uploaded_by TEXT,
+            alt_text TEXT,
+            created_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );
+CREATE INDEX media_content_hash ON media (content_hash);
+CREATE TABLE media_variants (
+            source_filename TEXT NOT NULL,
+            variant TEXT NOT NULL,
+            filename TEXT NOT NULL,
+            mime_type TEXT NOT NULL,
+            width INTEGER NOT NULL,
+            height INTEGER NOT NULL,
+            size_bytes INTEGER NOT NULL,
+            PRIMARY KEY (source_filename, variant)
+        );
+CREATE TABLE fsrs_cards (
+            user_id INTEGER NOT NULL,
+            card_id TEXT NOT NULL,
+            stability REAL NOT NULL,
+            difficulty REAL NOT NULL,
+            last_review TEXT,
+            due_date TEXT,
+            interval_days INTEGER NOT NULL,
+            state TEXT NOT NULL DEFAULT 'review',
+            step INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (user_id, card_id)
+        );
+CREATE TABLE fsrs_settings (
+            user_id INTEGER NOT NULL,
+            key TEXT NOT NULL,
+            value TEXT NOT NULL,
+            PRIMARY KEY (user_id, key)
+        );
+CREATE TABLE flashcard_deck_options (
+            user_id INTEGER NOT NULL,
+            deck_id TEXT NOT NULL,
+            desired_retention REAL NOT NULL,
+            PRIMARY KEY (user_id, deck_id)
+        );
+CREATE TABLE flashcard_flags (
+            user_id INTEGER NOT NULL,
+            card_id TEXT NOT NULL,
+            suspended INTEGER NOT NULL DEFAULT 0,
+            buried_until TEXT,
+            PRIMARY KEY (user_id, card_id)
+        );
+CREATE TABLE progress (
+            user_id INTEGER NOT NULL,
+            lesson_id TEXT NOT NULL,
+            completed INTEGER NOT NULL,
+            updated_at TEXT NOT NULL DEFAULT (datetime('now')),
+            PRIMARY KEY (user_id, lesson_id)
+        );
+CREATE INDEX fsrs_review_log_session ON fsrs_review_log (session_id, id);
+CREATE INDEX fsrs_review_log_user ON fsrs_review_log (user_id, card_id, id);
+INSERT INTO "users" VALUES(1,NULL,NULL,'2025-01-01 00:00:00',NULL);
@@ -70,8 +70,13 @@ export interface DialogueLine {
 }
 
 export interface VocabularyEntry {
+	id?: string;
 	word: string;
 	meaning: string;
+	romanisation?: string;
+	audio?: string;
+	lemma?: string;
+	tags?: string[];
 }
 
 export interface ExercisesSection {
@@ -109,8 +109,18 @@ export interface DialogueLine {
 
 /** A vocabulary word and its meaning */
 export interface VocabularyEntry {
+  /** Stable id that flashcard history is keyed on; assigned on save */
+  id?: string;
   word: string;
   meaning: string;
+  /** Latin-script transliteration */
+  romanisation?: string;
+  /** Media library filename of a pronunciation clip */
+  audio?: string;
+  /** Dictionary form, when the word appears inflected */
+  lemma?: string;
+  /** Labels used to build filtered flashcard decks */
+  tags?: string[];
 }
 
 // =============================================================================